@@ -2,6 +2,16 @@ var tilesets = {};
 var selectedMetatile = null;
 var metatiles = {};
 
+// the color that is drawn as transparent, which has to match --transparent-color of the tileset build
+function transparentColor() {
+    return document.getElementById("transparentColor").value;
+}
+
+function isTransparent(pixel) {
+    let color = parseInt(transparentColor().substring(1), 16);
+    return pixel[0] === (color >> 16 & 0xff) && pixel[1] === (color >> 8 & 0xff) && pixel[2] === (color & 0xff);
+}
+
 function copyTile(originImage, tileX, tileY, destinationImage) {
     let originCanvas = document.createElement('canvas');
     originCanvas.width = originImage.naturalWidth;
@@ -48,7 +58,7 @@ function mergeTiles(bottomTile, topTile, destinationImage) {
         for (let x = 0; x < 16; x++) {
             let topPixel = topCtx.getImageData(x, y, 1, 1).data;
             let bottomPixel = bottomCtx.getImageData(x, y, 1, 1).data;
-            let pixelData = topPixel[0] === 0xff && topPixel[1] === 0 && topPixel[2] === 0xfd ? bottomPixel : topPixel;
+            let pixelData = isTransparent(topPixel) ? bottomPixel : topPixel;
             destinationCtx.fillStyle = "rgba(" + pixelData[0] + "," + pixelData[1] + "," + pixelData[2] + "," + (pixelData[3] / 255) + ")";
             destinationCtx.fillRect(x, y, 1, 1);
         }
@@ -143,7 +153,7 @@ window.onload = function () {
         let tile = canvas.getContext("2d");
         tile.beginPath();
         tile.rect(0, 0, 16, 16);
-        tile.fillStyle = "#ff00fd";
+        tile.fillStyle = transparentColor();
         tile.fill();
         document.getElementById(tileName).src = canvas.toDataURL();
     }
@@ -156,7 +166,7 @@ window.onload = function () {
         let tile = canvas.getContext("2d");
         tile.beginPath();
         tile.rect(0, 0, 16, 16);
-        tile.fillStyle = "#ff00fd";
+        tile.fillStyle = transparentColor();
         tile.fill();
         let image = new Image();
         image.src = canvas.toDataURL();
@@ -175,6 +185,12 @@ window.onload = function () {
         metatilesContainer.appendChild(image);
     }
 
+    document.getElementById("transparentColor").addEventListener("change", function (event) {
+        if (selectedMetatile != null) {
+            updateSelectedMetatile();
+        }
+    });
+
     document.getElementById("export").addEventListener("click", function (event) {
         let output = outputMetatileDefinitions();
         console.log(output);
@@ -0,0 +1,117 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+
+use crate::tileset::{read_rgb_image, Tile, TileStorage};
+
+/// Animated tiles have their graphics swapped at runtime by tileset_anims.c,
+/// so every frame has to fit in the same range of tiles.
+pub struct Animation {
+    pub name: String,
+    /// first tile id of the range, as it is referenced in metatiles.bin
+    pub start_tile: usize,
+    /// last tile id of the range (inclusive)
+    pub end_tile: usize,
+    pub frames: Vec<String>,
+}
+
+impl Animation {
+    pub fn tile_count(&self) -> usize {
+        self.end_tile - self.start_tile + 1
+    }
+
+    /// returns the tiles of every frame
+    pub fn read_frames(&self) -> Result<Vec<Vec<Tile>>, String> {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for frame in self.frames.iter() {
+            let tiles = TileStorage::read_tiles_image(frame.clone())
+                .map_err(|e| format!("failed to read animation frame {}: {}", frame, e))?;
+            if tiles.len() != self.tile_count() {
+                return Err(format!("animation frame {} has {} tiles, but the range of {} has {}",
+                                   frame, tiles.len(), self.name, self.tile_count()));
+            }
+            frames.push(tiles);
+        }
+        Ok(frames)
+    }
+}
+
+/// Reserve the tile range of the animation with its first frame, so metatiles drawn
+/// with the first frame reference the animated tiles, and write every frame
+/// to anim/<name>/<frame>.png and .4bpp in the output folder.
+pub fn add_animation(storage: &mut TileStorage, animation: &Animation) -> Result<(), String> {
+    let frames = animation.read_frames()?;
+    let start = if storage.primary {
+        animation.start_tile
+    } else if animation.start_tile >= storage.tile_offset() {
+        animation.start_tile - storage.tile_offset()
+    } else {
+        return Err(format!("animation {} must use tiles of the secondary tileset (0x{:03X} and up)",
+                           animation.name, storage.tile_offset()));
+    };
+    let first_frame = frames[0].iter().map(|tile| Tile::new(tile.data)).collect();
+    storage.reserve_tiles(start, first_frame)
+        .map_err(|e| format!("failed to reserve tiles for animation {}: {}", animation.name, e))?;
+
+    let folder = format!("{}/anim/{}", storage.output_folder, animation.name);
+    fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+    for (i, (tiles, source)) in frames.iter().zip(animation.frames.iter()).enumerate() {
+        let mut data: Vec<u8> = Vec::with_capacity(tiles.len() * 32);
+        for tile in tiles.iter() {
+            data.append(&mut tile.to_4bpp());
+        }
+        let path = format!("{}/{}.4bpp", folder, i);
+        let mut file = File::create(&path).map_err(|e| format!("failed to create {}: {}", path, e))?;
+        file.write_all(&data).map_err(|e| format!("failed to write {}: {}", path, e))?;
+
+        // keep the size of the source frame so the decomp converts it to the same .4bpp
+        let palette = TileStorage::read_palette(source.clone())
+            .map_err(|e| format!("failed to read palette of {}: {}", source, e))?;
+        let (width, height, _) = read_rgb_image(source)
+            .map_err(|e| format!("failed to read {}: {}", source, e))?;
+        TileStorage::output_tiles_image(tiles, &palette, width as u32, height as u32, format!("{}/{}.png", folder, i));
+    }
+    Ok(())
+}
+
+/*
+# name first_tile-last_tile frames...
+flower 0x1FC-0x1FF anim/flower/0.png anim/flower/1.png anim/flower/2.png
+*/
+pub fn parse_animation_config(lines: Vec<String>) -> Result<Vec<Animation>, String> {
+    let mut animations = Vec::new();
+    for line in lines {
+        if line.len() < 3 || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 3 {
+            return Err(format!("animation needs a name, a tile range and frames: {}", line));
+        }
+        let range: Vec<&str> = parts[1].split('-').collect();
+        if range.len() != 2 {
+            return Err(format!("animation tile range must be first-last: {}", parts[1]));
+        }
+        let start_tile = parse_number(range[0])?;
+        let end_tile = parse_number(range[1])?;
+        if end_tile < start_tile {
+            return Err(format!("animation tile range ends before it starts: {}", parts[1]));
+        }
+        animations.push(Animation {
+            name: parts[0].to_string(),
+            start_tile,
+            end_tile,
+            frames: parts[2..].iter().map(|frame| frame.to_string()).collect(),
+        });
+    }
+    Ok(animations)
+}
+
+fn parse_number(number: &str) -> Result<usize, String> {
+    let parsed = if number.starts_with("0x") {
+        usize::from_str_radix(&number[2..], 16)
+    } else {
+        number.parse::<usize>()
+    };
+    parsed.map_err(|_| format!("invalid tile id: {}", number))
+}
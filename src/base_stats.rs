@@ -0,0 +1,386 @@
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::constants::{mask_comments, Constants};
+use crate::decomp::Project;
+use crate::rom::Rom;
+
+/// the size of struct BaseStats, which is the same in every game
+const BASE_STATS_SIZE: usize = 28;
+/// NUM_SPECIES, the species up to the egg
+pub const SPECIES_COUNT: usize = 412;
+
+/// the fields of struct BaseStats in order, with the abilities split like pokeruby has them
+pub const FIELDS: [&str; 29] = [
+    "baseHP", "baseAttack", "baseDefense", "baseSpeed", "baseSpAttack", "baseSpDefense",
+    "type1", "type2", "catchRate", "expYield",
+    "evYield_HP", "evYield_Attack", "evYield_Defense", "evYield_Speed", "evYield_SpAttack", "evYield_SpDefense",
+    "item1", "item2", "genderRatio", "eggCycles", "friendship", "growthRate",
+    "eggGroup1", "eggGroup2", "ability1", "ability2",
+    "safariZoneFleeRate", "bodyColor", "noFlip",
+];
+
+/// fields that species_info.h and newer base_stats.h write as an array of two of FIELDS
+const ARRAY_FIELDS: [(&str, [&str; 2]); 3] = [
+    ("types", ["type1", "type2"]),
+    ("eggGroups", ["eggGroup1", "eggGroup2"]),
+    ("abilities", ["ability1", "ability2"]),
+];
+
+/// the names species_info.h gives some of FIELDS
+const RENAMED_FIELDS: [(&str, &str); 2] = [("itemCommon", "item1"), ("itemRare", "item2")];
+
+/// fields that entries leave out when they are 0, every other field has to be in an entry
+fn is_optional(field: &str) -> bool {
+    field.starts_with("evYield_") || ["item1", "item2", "safariZoneFleeRate", "noFlip"].contains(&field)
+}
+
+/// the largest value of the fields that aren't bytes
+fn field_max(field: &str) -> u32 {
+    match field {
+        "item1" | "item2" => 0xffff,
+        f if f.starts_with("evYield_") => 3,
+        "bodyColor" => 0x7f,
+        "noFlip" => 1,
+        _ => 0xff,
+    }
+}
+
+/// The base stats of a species, as numbers for ROMs and as they are written for decomps
+#[derive(Clone)]
+pub struct BaseStats {
+    pub species: String,
+    /// a value for every one of FIELDS
+    pub values: Vec<String>,
+}
+
+/// where gBaseStats is in the first release of English Emerald, FireRed and Ruby
+pub fn base_stats_table(rom: &Rom) -> Result<usize, String> {
+    rom.known_offset("gBaseStats", "--table", &[("BPEE", 0, 0x3203cc), ("BPRE", 0, 0x254784), ("AXVE", 0, 0x1fec18)])
+}
+
+/// Adds the constants base_stats.h uses that aren't in include/constants, unless the decomp has them
+pub fn add_default_constants(constants: &mut Constants) {
+    let defaults = [
+        ("FALSE", 0), ("TRUE", 1),
+        ("MON_MALE", 0), ("MON_FEMALE", 0xfe), ("MON_GENDERLESS", 0xff),
+        ("NUMBER_OF_MON_TYPES", 18), ("ABILITIES_COUNT", 78),
+    ];
+    for (name, value) in defaults.iter() {
+        if constants.get(name).is_none() {
+            constants.define(name, *value);
+        }
+    }
+}
+
+/// Evaluates a value, with the PERCENT_FEMALE macro of the gender ratios
+fn evaluate(value: &str, constants: &Constants) -> Option<u32> {
+    let value = value.trim();
+    if value.starts_with("PERCENT_FEMALE(") && value.ends_with(')') {
+        let percent = value["PERCENT_FEMALE(".len()..value.len() - 1].trim().parse::<f64>().ok()?;
+        return Some(((percent * 255.0 / 100.0) as u32).min(254));
+    }
+    constants.get(value)
+}
+
+impl BaseStats {
+    /// Checks every field and returns their values, with the types, abilities and growth rates
+    /// the games have and EV yields that fit in their two bits
+    pub fn check(&self, constants: &Constants) -> Result<Vec<u32>, String> {
+        let mut numbers = Vec::with_capacity(FIELDS.len());
+        for (field, value) in FIELDS.iter().zip(self.values.iter()) {
+            let number = evaluate(value, constants)
+                .ok_or(format!("{} {}: unknown value {}", self.species, field, value))?;
+            let (max, what) = match *field {
+                "type1" | "type2" => (constants.get("NUMBER_OF_MON_TYPES").unwrap_or(18) - 1, "type"),
+                "ability1" | "ability2" => (constants.get("ABILITIES_COUNT").unwrap_or(78) - 1, "ability"),
+                // GROWTH_MEDIUM_FAST to GROWTH_SLOW
+                "growthRate" => (5, "growth rate"),
+                f if f.starts_with("evYield_") => (3, "EV yield"),
+                f => (field_max(f), "value"),
+            };
+            if number > max {
+                return Err(format!("{} {}: {} is not a valid {}, the highest is {}", self.species, field, value, what, max));
+            }
+            numbers.push(number);
+        }
+        Ok(numbers)
+    }
+
+    fn from_bytes(species: usize, bytes: &[u8]) -> BaseStats {
+        let evs = bytes[10] as u32 | (bytes[11] as u32) << 8;
+        let u16_at = |offset: usize| bytes[offset] as u32 | (bytes[offset + 1] as u32) << 8;
+        let mut values: Vec<u32> = bytes[0..10].iter().map(|&b| b as u32).collect();
+        values.extend((0..6).map(|i| evs >> (i * 2) & 3));
+        values.push(u16_at(12));
+        values.push(u16_at(14));
+        values.extend(bytes[16..25].iter().map(|&b| b as u32));
+        values.push(bytes[25] as u32 & 0x7f);
+        values.push(bytes[25] as u32 >> 7);
+        BaseStats { species: species.to_string(), values: values.iter().map(|v| v.to_string()).collect() }
+    }
+
+    fn to_bytes(numbers: &[u32]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = numbers[0..10].iter().map(|&n| n as u8).collect();
+        let evs = numbers[10..16].iter().enumerate().fold(0, |evs, (i, &ev)| evs | ev << (i * 2));
+        bytes.extend_from_slice(&(evs as u16).to_le_bytes());
+        bytes.extend_from_slice(&(numbers[16] as u16).to_le_bytes());
+        bytes.extend_from_slice(&(numbers[17] as u16).to_le_bytes());
+        bytes.extend(numbers[18..27].iter().map(|&n| n as u8));
+        bytes.push((numbers[27] | numbers[28] << 7) as u8);
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+}
+
+pub fn read_rom(rom: &Rom, table: usize, count: usize) -> Result<Vec<BaseStats>, String> {
+    let bytes = rom.buffer.get(table..table + count * BASE_STATS_SIZE)
+        .ok_or(format!("the base stats of {} species at 0x{:X} don't fit in the ROM", count, table))?;
+    Ok(bytes.chunks(BASE_STATS_SIZE).enumerate().map(|(species, bytes)| BaseStats::from_bytes(species, bytes)).collect())
+}
+
+/// Writes base stats to the table, by the species number in their species column
+pub fn write_rom(rom: &mut Rom, table: usize, count: usize, stats: &[BaseStats], constants: &Constants) -> Result<(), String> {
+    for stats in stats.iter() {
+        let species = stats.species.parse::<usize>().ok().filter(|&species| species < count)
+            .ok_or(format!("species of ROMs are numbers below {}, not {}", count, stats.species))?;
+        let numbers = stats.check(constants)?;
+        rom.write_bytes(table + species * BASE_STATS_SIZE, &BaseStats::to_bytes(&numbers))?;
+    }
+    Ok(())
+}
+
+/// A base stats entry of the source and where its values are, to change them in place
+struct Entry {
+    stats: BaseStats,
+    spans: Vec<Option<Range<usize>>>,
+}
+
+/// The base stats of a decomp, from the designated initializers of gBaseStats or gSpeciesInfo
+pub struct BaseStatsSource {
+    pub path: PathBuf,
+    source: String,
+    entries: Vec<Entry>,
+}
+
+impl BaseStatsSource {
+    /// newer pokeemerald has the base stats in species_info.h
+    pub fn load(project: &Project) -> Result<BaseStatsSource, String> {
+        let folder = project.root.join("src/data/pokemon");
+        let path = ["species_info.h", "base_stats.h"].iter().map(|name| folder.join(name)).find(|path| path.is_file())
+            .ok_or(format!("{} has no species_info.h or base_stats.h", folder.display()))?;
+        let source = fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let entries = parse_entries(&source).map_err(|e| format!("{} in {}", e, path.display()))?;
+        if entries.is_empty() {
+            return Err(format!("no base stats found in {}", path.display()));
+        }
+        Ok(BaseStatsSource { path, source, entries })
+    }
+
+    pub fn stats(&self) -> Vec<BaseStats> {
+        self.entries.iter().map(|entry| entry.stats.clone()).collect()
+    }
+
+    /// Changes the values that are different in place, keeping the rest of the source as it is.
+    /// Returns the number of changed values.
+    pub fn update(&mut self, stats: &[BaseStats]) -> Result<usize, String> {
+        let mut changes = Vec::new();
+        for stats in stats.iter() {
+            let entry = self.entries.iter().find(|entry| entry.stats.species == stats.species)
+                .ok_or(format!("{} has no base stats in {}, or they are written with a macro", stats.species, self.path.display()))?;
+            for (i, value) in stats.values.iter().enumerate() {
+                if value.trim() == entry.stats.values[i] {
+                    continue;
+                }
+                let span = entry.spans[i].clone()
+                    .ok_or(format!("{} has no {} to change in {}", stats.species, FIELDS[i], self.path.display()))?;
+                changes.push((span, value.trim().to_string()));
+            }
+        }
+        changes.sort_by_key(|(span, _)| span.start);
+        for (span, value) in changes.iter().rev() {
+            self.source.replace_range(span.clone(), value);
+        }
+        self.entries = parse_entries(&self.source).map_err(|e| format!("{} in {}", e, self.path.display()))?;
+        Ok(changes.len())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        fs::write(&self.path, &self.source).map_err(|e| format!("failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+/// Finds the [SPECIES_X] = { .field = value, ... } entries. Entries written with macros are skipped,
+/// and entries that leave out a field that isn't optional are an error.
+fn parse_entries(source: &str) -> Result<Vec<Entry>, String> {
+    // comments are blanked out so their commas and braces don't count
+    let masked = mask_comments(source);
+    let bytes = masked.as_bytes();
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while let Some(start) = masked[position..].find("[SPECIES_").map(|i| position + i) {
+        position = start + 1;
+        let close = match masked[start..].find(']') {
+            Some(i) => start + i,
+            None => break
+        };
+        let species = masked[start + 1..close].trim().to_string();
+        let rest = masked[close + 1..].trim_start();
+        if !rest.starts_with('=') || !rest[1..].trim_start().starts_with('{') {
+            continue;
+        }
+        let open = close + 1 + masked[close + 1..].find('{').unwrap();
+        let end = match matching_brace(bytes, open) {
+            Some(end) => end,
+            None => break
+        };
+        position = end;
+        let mut values = vec!["0".to_string(); FIELDS.len()];
+        let mut spans = vec![None; FIELDS.len()];
+        let mut found = false;
+        for part in split_top_level(&masked, open + 1..end) {
+            let text = &masked[part.clone()];
+            let equals = match text.find('=') {
+                Some(equals) if text.trim_start().starts_with('.') => equals,
+                _ => continue
+            };
+            let name = text[..equals].trim().trim_start_matches('.');
+            let name = RENAMED_FIELDS.iter().find(|(renamed, _)| *renamed == name).map(|(_, field)| *field).unwrap_or(name);
+            let value = trim_span(&masked, part.start + equals + 1..part.end);
+            let mut set = |field: &str, span: Range<usize>| {
+                if let Some(i) = FIELDS.iter().position(|f| *f == field) {
+                    values[i] = source[span.clone()].to_string();
+                    spans[i] = Some(span);
+                }
+            };
+            match ARRAY_FIELDS.iter().find(|(array, _)| *array == name) {
+                Some((_, fields)) if masked[value.clone()].starts_with('{') => {
+                    let elements = split_top_level(&masked, value.start + 1..value.end - 1);
+                    // a trailing comma leaves an empty element
+                    let elements = elements.into_iter().map(|element| trim_span(&masked, element)).filter(|element| element.start < element.end);
+                    for (field, element) in fields.iter().zip(elements) {
+                        set(field, element);
+                    }
+                }
+                _ => set(name, value)
+            }
+            found = true;
+        }
+        if found {
+            if let Some(i) = (0..FIELDS.len()).find(|&i| spans[i].is_none() && !is_optional(FIELDS[i])) {
+                return Err(format!("{} has no {}", species, FIELDS[i]));
+            }
+            entries.push(Entry { stats: BaseStats { species, values }, spans });
+        }
+    }
+    Ok(entries)
+}
+
+fn matching_brace(bytes: &[u8], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &byte) in bytes.iter().enumerate().skip(open) {
+        match byte {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// splits at the commas that aren't in parentheses or braces
+fn split_top_level(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = range.start;
+    for (i, byte) in text.as_bytes()[range.clone()].iter().enumerate() {
+        match byte {
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                parts.push(start..range.start + i);
+                start = range.start + i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(start..range.end);
+    parts
+}
+
+fn trim_span(text: &str, span: Range<usize>) -> Range<usize> {
+    let part = &text[span.clone()];
+    let start = span.start + (part.len() - part.trim_start().len());
+    let end = span.end - (part.len() - part.trim_end().len());
+    start..end.max(start)
+}
+
+/// Formats base stats as CSV, with a header of the species and the fields
+pub fn to_csv(stats: &[BaseStats]) -> String {
+    let mut csv = format!("species,{}\n", FIELDS.join(","));
+    for stats in stats.iter() {
+        let mut row = vec![csv_field(&stats.species)];
+        row.extend(stats.values.iter().map(|v| csv_field(v)));
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads base stats from CSV with the columns in any order, as long as every field is there
+pub fn from_csv(csv: &str) -> Result<Vec<BaseStats>, String> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let header = parse_csv_line(lines.next().ok_or("the CSV is empty")?.1)?;
+    let column = |name: &str| header.iter().position(|h| h.trim() == name).ok_or(format!("the CSV has no {} column", name));
+    let species_column = column("species")?;
+    let columns = FIELDS.iter().map(|field| column(field)).collect::<Result<Vec<_>, _>>()?;
+    let mut stats = Vec::new();
+    for (number, line) in lines {
+        let row = parse_csv_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        if row.len() != header.len() {
+            return Err(format!("line {}: {} values instead of {}", number + 1, row.len(), header.len()));
+        }
+        stats.push(BaseStats {
+            species: row[species_column].trim().to_string(),
+            values: columns.iter().map(|&c| row[c].trim().to_string()).collect(),
+        });
+    }
+    Ok(stats)
+}
+
+fn parse_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => values.push(std::mem::replace(&mut value, String::new())),
+            c => value.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    values.push(value);
+    Ok(values)
+}
@@ -0,0 +1,327 @@
+use std::fs;
+use std::path::Path;
+
+/// the byte that ends every string
+pub const END: u8 = 0xff;
+/// the byte that starts a control code like {COLOR RED}
+const EXT_CTRL_CODE: u8 = 0xfc;
+
+/// how many argument bytes follow each of the FC control codes
+const EXT_CTRL_CODE_ARGS: [usize; 0x19] = [
+    0, 1, 1, 1, 3, 1, 1, 0, 1, 0, 0, 2, 1, 1, 1, 0, 2, 1, 1, 1, 1, 0, 0, 0, 0,
+];
+
+/// the number of argument bytes of an FC control code
+pub fn ext_ctrl_code_args(code: u8) -> usize {
+    EXT_CTRL_CODE_ARGS.get(code as usize).cloned().unwrap_or(0)
+}
+
+const EXT_CTRL_CODES: [&str; 0x19] = [
+    "NAME_END", "COLOR", "HIGHLIGHT", "SHADOW", "COLOR_HIGHLIGHT_SHADOW", "PALETTE", "FONT",
+    "RESET_FONT", "PAUSE", "PAUSE_UNTIL_PRESS", "WAIT_SE", "PLAY_BGM", "ESCAPE", "SHIFT_RIGHT",
+    "SHIFT_DOWN", "FILL_WINDOW", "PLAY_SE", "CLEAR", "SKIP", "CLEAR_TO", "MIN_LETTER_SPACING",
+    "JPN", "ENG", "PAUSE_MUSIC", "RESUME_MUSIC",
+];
+
+const PLACEHOLDERS: [&str; 13] = [
+    "PLAYER", "STR_VAR_1", "STR_VAR_2", "STR_VAR_3", "KUN", "RIVAL", "VERSION",
+    "AQUA", "MAGMA", "ARCHIE", "MAXIE", "KYOGRE", "GROUDON",
+];
+
+const COLORS: [&str; 16] = [
+    "TRANSPARENT", "WHITE", "DARK_GRAY", "LIGHT_GRAY", "RED", "LIGHT_RED", "GREEN", "LIGHT_GREEN",
+    "BLUE", "LIGHT_BLUE", "DYNAMIC_COLOR1", "DYNAMIC_COLOR2", "DYNAMIC_COLOR3", "DYNAMIC_COLOR4",
+    "DYNAMIC_COLOR5", "DYNAMIC_COLOR6",
+];
+
+/// characters of the international games that aren't in runs, as (character, byte)
+const INTERNATIONAL: [(&str, u8); 72] = [
+    (" ", 0x00), ("À", 0x01), ("Á", 0x02), ("Â", 0x03), ("Ç", 0x04), ("È", 0x05), ("É", 0x06),
+    ("Ê", 0x07), ("Ë", 0x08), ("Ì", 0x09), ("Î", 0x0b), ("Ï", 0x0c), ("Ò", 0x0d), ("Ó", 0x0e),
+    ("Ô", 0x0f), ("Œ", 0x10), ("Ù", 0x11), ("Ú", 0x12), ("Û", 0x13), ("Ñ", 0x14), ("ß", 0x15),
+    ("à", 0x16), ("á", 0x17), ("ç", 0x19), ("è", 0x1a), ("é", 0x1b), ("ê", 0x1c), ("ë", 0x1d),
+    ("ì", 0x1e), ("î", 0x20), ("ï", 0x21), ("ò", 0x22), ("ó", 0x23), ("ô", 0x24), ("œ", 0x25),
+    ("ù", 0x26), ("ú", 0x27), ("û", 0x28), ("ñ", 0x29), ("º", 0x2a), ("ª", 0x2b), ("&", 0x2d),
+    ("+", 0x2e), ("=", 0x35), (";", 0x36), ("¿", 0x51), ("¡", 0x52), ("Í", 0x5a), ("%", 0x5b),
+    ("(", 0x5c), (")", 0x5d), ("â", 0x68), ("í", 0x6f), ("<", 0x85), (">", 0x86), ("!", 0xab),
+    ("?", 0xac), (".", 0xad), ("-", 0xae), ("·", 0xaf), ("…", 0xb0), ("“", 0xb1), ("”", 0xb2),
+    ("‘", 0xb3), ("’", 0xb4), ("'", 0xb4), ("♂", 0xb5), ("♀", 0xb6), ("¥", 0xb7), (",", 0xb8),
+    ("×", 0xb9), ("/", 0xba),
+];
+
+/// symbols that have no character, as {NAME}
+const SYMBOLS: [(&str, u8); 15] = [
+    ("SUPER_ER", 0x2c), ("LV", 0x34), ("PK", 0x53), ("MN", 0x54), ("PO", 0x55), ("KE", 0x56),
+    ("BL", 0x57), ("OC", 0x58), ("K", 0x59), ("UP_ARROW", 0x79), ("DOWN_ARROW", 0x7a),
+    ("LEFT_ARROW", 0x7b), ("RIGHT_ARROW", 0x7c), ("SUPER_E", 0x84), ("SUPER_RE", 0xa0),
+];
+
+/// characters that both character sets have in the same place
+const SHARED: [(&str, u8); 11] = [
+    ("▶", 0xef), ("Ä", 0xf1), ("Ö", 0xf2), ("Ü", 0xf3), ("ä", 0xf4), ("ö", 0xf5), ("ü", 0xf6),
+    ("\\l", 0xfa), ("\\p", 0xfb), ("\\n", 0xfe), ("$", 0xff),
+];
+
+const HIRAGANA: &str = "あいうえおかきくけこさしすせそたちつてとなにぬねのはひふへほまみむめもやゆよらりるれろわをんぁぃぅぇぉゃゅょがぎぐげござじずぜぞだぢづでどばびぶべぼぱぴぷぺぽっ";
+const KATAKANA: &str = "アイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワヲンァィゥェォャュョガギグゲゴザジズゼゾダヂヅデドバビブベボパピプペポッ";
+const JAPANESE_PUNCTUATION: &str = "！？。ー・‥『』「」♂♀円．×／";
+
+/// The Gen III character set. Characters are written as they are in the decomps' strings,
+/// with \n, \l and \p for line breaks and {NAME} or {NAME ARG} for everything else.
+pub struct Charmap {
+    /// quoted entries of charmap.txt, like 'A' = BB
+    pub characters: Vec<(String, Vec<u8>)>,
+    /// unquoted entries of charmap.txt, like PLAYER = FD 01
+    pub names: Vec<(String, Vec<u8>)>,
+}
+
+impl Charmap {
+    /// the character set of the English, French, German, Italian and Spanish games
+    pub fn international() -> Charmap {
+        let mut characters: Vec<(String, Vec<u8>)> = INTERNATIONAL.iter().chain(SHARED.iter())
+            .map(|(c, b)| (c.to_string(), vec![*b]))
+            .collect();
+        for i in 0..10u8 {
+            characters.push((((b'0' + i) as char).to_string(), vec![0xa1 + i]));
+        }
+        for i in 0..26u8 {
+            characters.push((((b'A' + i) as char).to_string(), vec![0xbb + i]));
+            characters.push((((b'a' + i) as char).to_string(), vec![0xd5 + i]));
+        }
+        characters.push((":".to_string(), vec![0xf0]));
+        Charmap { characters, names: Charmap::builtin_names() }
+    }
+
+    /// the character set of the Japanese games
+    pub fn japanese() -> Charmap {
+        let mut characters: Vec<(String, Vec<u8>)> = vec![("　".to_string(), vec![0x00])];
+        for (i, c) in HIRAGANA.chars().chain(KATAKANA.chars()).enumerate() {
+            characters.push((c.to_string(), vec![0x01 + i as u8]));
+        }
+        for (i, c) in "０１２３４５６７８９".chars().enumerate() {
+            characters.push((c.to_string(), vec![0xa1 + i as u8]));
+        }
+        for (i, c) in JAPANESE_PUNCTUATION.chars().enumerate() {
+            characters.push((c.to_string(), vec![0xab + i as u8]));
+        }
+        for i in 0..26u8 {
+            characters.push((std::char::from_u32(0xff21 + i as u32).unwrap_or('?').to_string(), vec![0xbb + i]));
+            characters.push((std::char::from_u32(0xff41 + i as u32).unwrap_or('?').to_string(), vec![0xd5 + i]));
+        }
+        characters.push(("：".to_string(), vec![0xf0]));
+        characters.extend(SHARED.iter().map(|(c, b)| (c.to_string(), vec![*b])));
+        Charmap { characters, names: Charmap::builtin_names() }
+    }
+
+    fn builtin_names() -> Vec<(String, Vec<u8>)> {
+        let mut names: Vec<(String, Vec<u8>)> = Vec::new();
+        for (i, name) in PLACEHOLDERS.iter().enumerate() {
+            names.push((name.to_string(), vec![0xfd, 0x01 + i as u8]));
+        }
+        for (i, name) in EXT_CTRL_CODES.iter().enumerate() {
+            names.push((name.to_string(), vec![EXT_CTRL_CODE, i as u8]));
+        }
+        for (i, name) in COLORS.iter().enumerate() {
+            names.push((name.to_string(), vec![i as u8]));
+        }
+        names.extend(SYMBOLS.iter().map(|(name, b)| (name.to_string(), vec![*b])));
+        names
+    }
+
+    /// Loads the charmap.txt of a decomp, so custom characters work too
+    pub fn load(path: &Path) -> Result<Charmap, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Charmap::parse(&contents).map_err(|e| format!("invalid charmap {}: {}", path.display(), e))
+    }
+
+    /*
+    @ comments start with an at sign
+    'A' = BB
+    PLAYER = FD 01
+    */
+    pub fn parse(contents: &str) -> Result<Charmap, String> {
+        let mut characters = Vec::new();
+        let mut names = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('@') {
+                continue;
+            }
+            let error = || format!("line {}: {}", line_number + 1, line);
+            let (key, value) = if line.starts_with('\'') {
+                // the character can be a quote itself, so the entry ends at the quote before the =
+                let end = line.char_indices().skip(1)
+                    .find(|&(i, c)| c == '\'' && line[i + 1..].trim_start().starts_with('='))
+                    .map(|(i, _)| i)
+                    .ok_or_else(error)?;
+                let character = line[1..end].replace("\\'", "'");
+                (Some(character), &line[end + 1..])
+            } else {
+                (None, line)
+            };
+            let parts: Vec<&str> = value.splitn(2, '=').collect();
+            if parts.len() != 2 {
+                return Err(error());
+            }
+            let bytes = parts[1].split('@').next().unwrap_or("").split_whitespace()
+                .map(|b| u8::from_str_radix(b, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| error())?;
+            match key {
+                Some(character) => characters.push((character, bytes)),
+                None => names.push((parts[0].trim().to_string(), bytes)),
+            }
+        }
+        Ok(Charmap { characters, names })
+    }
+
+    fn name(&self, name: &str) -> Option<&Vec<u8>> {
+        self.names.iter().find(|(n, _)| n == name).map(|(_, bytes)| bytes)
+    }
+
+    /// Encodes a string and ends it with the terminator if it doesn't end with $ already
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::with_capacity(text.len() + 1);
+        let mut rest = text;
+        while !rest.is_empty() {
+            if rest.starts_with('{') {
+                let end = rest.find('}').ok_or(format!("unclosed {{ in {}", text))?;
+                for (i, part) in rest[1..end].split_whitespace().enumerate() {
+                    bytes.extend(self.encode_token(part, i > 0)?);
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+            // the longest character wins, so \n isn't read as \ and n
+            let (character, encoded) = self.characters.iter()
+                .filter(|(c, _)| !c.is_empty() && rest.starts_with(c.as_str()))
+                .max_by_key(|(c, _)| c.len())
+                .ok_or(format!("no character for '{}' in {}", rest.chars().next().unwrap_or(' '), text))?;
+            bytes.extend(encoded);
+            rest = &rest[character.len()..];
+        }
+        if bytes.last() != Some(&END) {
+            bytes.push(END);
+        }
+        Ok(bytes)
+    }
+
+    /// a name from the charmap, or a byte as hex or decimal
+    fn encode_token(&self, token: &str, argument: bool) -> Result<Vec<u8>, String> {
+        if let Some(bytes) = self.name(token) {
+            return Ok(bytes.clone());
+        }
+        let number = if token.starts_with("0x") {
+            u32::from_str_radix(&token[2..], 16)
+        } else {
+            token.parse::<u32>()
+        };
+        match number {
+            Ok(n) if n <= 0xff => Ok(vec![n as u8]),
+            // arguments like the song of {PLAY_BGM} are two bytes
+            Ok(n) if argument && n <= 0xffff => Ok(vec![(n & 0xff) as u8, (n >> 8) as u8]),
+            _ => Err(format!("unknown {{{}}}", token))
+        }
+    }
+
+    /// Decodes a string up to its terminator, and returns it with the number of bytes read
+    /// including the terminator. The terminator isn't part of the returned text.
+    pub fn decode(&self, bytes: &[u8]) -> (String, usize) {
+        let mut text = String::new();
+        let mut position = 0;
+        while position < bytes.len() {
+            let rest = &bytes[position..];
+            if rest[0] == END {
+                return (text, position + 1);
+            }
+            if rest[0] == EXT_CTRL_CODE && rest.len() > 1 {
+                let code = rest[1] as usize;
+                let args = ext_ctrl_code_args(rest[1]);
+                let name = self.reverse_name(&rest[..2]).unwrap_or(format!("0xFC 0x{:02X}", code));
+                text.push('{');
+                text.push_str(&name);
+                for arg in rest.iter().skip(2).take(args) {
+                    // the color codes take the name of a color
+                    let color = if code >= 1 && code <= 4 { self.reverse_name(&[*arg]) } else { None };
+                    text.push_str(&format!(" {}", color.unwrap_or(format!("0x{:02X}", arg))));
+                }
+                text.push('}');
+                position += (2 + args).min(rest.len());
+                continue;
+            }
+            if let Some((character, length)) = self.reverse_character(rest) {
+                text.push_str(&character);
+                position += length;
+                continue;
+            }
+            // names are only used for bytes that aren't characters, like {PLAYER}
+            match self.names.iter()
+                .filter(|(_, b)| !b.is_empty() && rest.starts_with(b))
+                .max_by_key(|(_, b)| b.len()) {
+                Some((name, b)) => {
+                    text.push_str(&format!("{{{}}}", name));
+                    position += b.len();
+                }
+                None => {
+                    text.push_str(&format!("{{0x{:02X}}}", rest[0]));
+                    position += 1;
+                }
+            }
+        }
+        (text, position)
+    }
+
+    /// the first character with the longest match, the way charmap.txt lists its preferred spelling first
+    fn reverse_character(&self, bytes: &[u8]) -> Option<(String, usize)> {
+        let mut best: Option<(&String, usize)> = None;
+        for (character, encoded) in self.characters.iter() {
+            if !encoded.is_empty() && bytes.starts_with(encoded) && best.map(|b| encoded.len() > b.1).unwrap_or(true) {
+                best = Some((character, encoded.len()));
+            }
+        }
+        best.map(|(c, length)| (c.clone(), length))
+    }
+
+    fn reverse_name(&self, bytes: &[u8]) -> Option<String> {
+        self.names.iter().find(|(_, b)| b.as_slice() == bytes).map(|(name, _)| name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let charmap = Charmap::international();
+        let bytes = charmap.encode("Hi {PLAYER}!").unwrap();
+        assert_eq!(bytes, vec![0xc2, 0xdd, 0x00, 0xfd, 0x01, 0xab, END]);
+        assert_eq!(charmap.decode(&bytes), ("Hi {PLAYER}!".to_string(), bytes.len()));
+    }
+
+    #[test]
+    fn control_codes() {
+        let charmap = Charmap::international();
+        let bytes = charmap.encode("{COLOR RED}A{PLAY_BGM 0x1A2}\\n{PAUSE 30}").unwrap();
+        assert_eq!(bytes, vec![0xfc, 0x01, 0x04, 0xbb, 0xfc, 0x0b, 0xa2, 0x01, 0xfe, 0xfc, 0x08, 30, END]);
+        // arguments are decoded as hex, except for colors which have names
+        let (text, length) = charmap.decode(&bytes);
+        assert_eq!(text, "{COLOR RED}A{PLAY_BGM 0xA2 0x01}\\n{PAUSE 0x1E}");
+        assert_eq!(length, bytes.len());
+        assert_eq!(charmap.encode(&text).unwrap(), bytes);
+    }
+
+    #[test]
+    fn decoding_stops_at_the_terminator() {
+        let charmap = Charmap::international();
+        assert_eq!(charmap.decode(&[0xbb, END, 0xbc]), ("A".to_string(), 2));
+        assert!(charmap.encode("{UNKNOWN_CODE}").is_err());
+    }
+
+    #[test]
+    fn decomp_charmap() {
+        let charmap = Charmap::parse("@ comment\n'A' = BB\n''' = B4 @ quote\nPLAYER = FD 01\nBIG = FC 06 02\n").unwrap();
+        assert_eq!(charmap.encode("A'{PLAYER}{BIG}").unwrap(), vec![0xbb, 0xb4, 0xfd, 0x01, 0xfc, 0x06, 0x02, END]);
+        assert_eq!(charmap.decode(&[0xfd, 0x01, 0xbb, END]).0, "{PLAYER}A");
+    }
+}
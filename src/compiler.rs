@@ -0,0 +1,1001 @@
+use std::collections::HashMap;
+
+use crate::charmap::Charmap;
+use crate::constants::{parse_number, Constants};
+use crate::decomp::Game;
+use crate::lexer::{tokenize, CompileError, Lexeme, Span, Token};
+use crate::rom::ROM_BASE;
+use crate::script::{self, Arg, CONDITIONS, MSGBOX_TYPES, STD_FIND_ITEM, STD_OBTAIN_ITEM, STEP_END, VAR_AMOUNT, VAR_ITEM};
+
+/// vars start here, smaller values compared to a var are values
+const VARS_START: u32 = 0x4000;
+const MSGBOX_DEFAULT: &str = "MSGBOX_DEFAULT";
+
+/*
+script MyScript {
+    lock
+    faceplayer
+    if (flag(FLAG_RECEIVED_POTION)) {
+        msgbox("Good luck!")
+    } else {
+        giveitem(ITEM_POTION)
+        setflag(FLAG_RECEIVED_POTION)
+    }
+    release
+}
+
+text MyText {
+    "Hello {PLAYER}!\n"
+    "How are you?"
+}
+
+movement MyMovement {
+    walk_up * 2
+    face_down
+}
+
+mart MyMart {
+    ITEM_POTION
+    ITEM_ANTIDOTE
+}
+*/
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Number(u32),
+    Name(String),
+    /// a string, which becomes a text of its own
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Argument {
+    pub value: Value,
+    pub span: Span,
+}
+
+pub enum Condition {
+    Flag(Argument),
+    Defeated(Argument),
+    /// a var, the index of the comparison in CONDITIONS, and a value or another var
+    Var(Argument, usize, Argument),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+pub enum Statement {
+    Command { name: String, args: Vec<Argument>, span: Span },
+    If { branches: Vec<(Condition, Vec<Statement>)>, otherwise: Vec<Statement> },
+    While { condition: Condition, body: Vec<Statement> },
+    DoWhile { body: Vec<Statement>, condition: Condition },
+    /// the var, and the values and statements of every case
+    Switch { var: Argument, cases: Vec<(Vec<Argument>, Vec<Statement>)>, default: Vec<Statement> },
+    Break(Span),
+    Continue(Span),
+}
+
+pub enum Definition {
+    /// a script, and the span of its closing brace where it ends by itself
+    Script { name: String, body: Vec<Statement>, end: Span },
+    /// a text, and the span of its strings
+    Text { name: String, text: String, span: Span },
+    Movement { name: String, steps: Vec<Argument> },
+    Mart { name: String, items: Vec<Argument> },
+}
+
+struct Parser<'a> {
+    lexemes: Vec<Lexeme<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Token {
+        self.lexemes[self.position].token
+    }
+
+    fn next(&mut self) -> &Lexeme<'a> {
+        let lexeme = &self.lexemes[self.position];
+        // the last lexeme is always End
+        if self.position + 1 < self.lexemes.len() {
+            self.position += 1;
+        }
+        lexeme
+    }
+
+    fn error(&self, message: &str) -> CompileError {
+        let lexeme = &self.lexemes[self.position];
+        let found = if lexeme.token == Token::End { "the end of the file".to_string() } else { format!("`{}`", lexeme.text) };
+        CompileError::new(lexeme.span.clone(), format!("expected {}, found {}", message, found))
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<&Lexeme<'a>, CompileError> {
+        if self.peek() != token {
+            return Err(self.error(message));
+        }
+        Ok(self.next())
+    }
+
+    /// the name of a definition, which has to be unique
+    fn name(&mut self, names: &mut HashMap<String, Span>) -> Result<String, CompileError> {
+        let (name, span) = {
+            let lexeme = self.expect(Token::Identifier, "a name")?;
+            (lexeme.text.to_string(), lexeme.span.clone())
+        };
+        if names.insert(name.clone(), span.clone()).is_some() {
+            return Err(CompileError::new(span, format!("{} is defined more than once", name)));
+        }
+        Ok(name)
+    }
+
+    fn definitions(&mut self) -> Result<Vec<Definition>, CompileError> {
+        let mut definitions = Vec::new();
+        let mut names = HashMap::new();
+        loop {
+            let definition = match self.peek() {
+                Token::End => return Ok(definitions),
+                Token::Script => {
+                    self.next();
+                    let name = self.name(&mut names)?;
+                    let body = self.block()?;
+                    let end = self.lexemes[self.position - 1].span.clone();
+                    Definition::Script { name, body, end }
+                }
+                Token::Text => {
+                    self.next();
+                    let name = self.name(&mut names)?;
+                    self.expect(Token::LeftBrace, "`{`")?;
+                    let mut text = String::new();
+                    let mut span = self.lexemes[self.position].span.clone();
+                    while self.peek() != Token::RightBrace {
+                        span.end = self.lexemes[self.position].span.end;
+                        text.push_str(&self.string()?);
+                    }
+                    self.next();
+                    Definition::Text { name, text, span }
+                }
+                Token::Movement => {
+                    self.next();
+                    let name = self.name(&mut names)?;
+                    let mut steps = Vec::new();
+                    for (step, count) in self.list()? {
+                        for _ in 0..count {
+                            steps.push(step.clone());
+                        }
+                    }
+                    Definition::Movement { name, steps }
+                }
+                Token::Mart => {
+                    self.next();
+                    let name = self.name(&mut names)?;
+                    let items = self.list()?.into_iter().map(|(item, _)| item).collect();
+                    Definition::Mart { name, items }
+                }
+                _ => return Err(self.error("`script`, `text`, `movement` or `mart`"))
+            };
+            definitions.push(definition);
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CompileError> {
+        let text = self.expect(Token::String, "a string")?.text;
+        Ok(text[1..text.len() - 1].to_string())
+    }
+
+    /// the values of a movement or a mart, which can be repeated like walk_up * 3
+    fn list(&mut self) -> Result<Vec<(Argument, u32)>, CompileError> {
+        self.expect(Token::LeftBrace, "`{`")?;
+        let mut values = Vec::new();
+        while self.peek() != Token::RightBrace {
+            let value = self.argument()?;
+            let count = if self.peek() == Token::Star {
+                self.next();
+                match self.argument()?.value {
+                    Value::Number(count) => count,
+                    _ => return Err(CompileError::new(value.span, "steps can only be repeated a number of times".to_string()))
+                }
+            } else {
+                1
+            };
+            values.push((value, count));
+            if self.peek() == Token::Comma {
+                self.next();
+            }
+        }
+        self.next();
+        Ok(values)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, CompileError> {
+        self.expect(Token::LeftBrace, "`{`")?;
+        let mut statements = Vec::new();
+        while self.peek() != Token::RightBrace {
+            statements.push(self.statement()?);
+        }
+        self.next();
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, CompileError> {
+        match self.peek() {
+            Token::If => {
+                self.next();
+                let mut branches = vec![(self.parenthesized_condition()?, self.block()?)];
+                while self.peek() == Token::Elif {
+                    self.next();
+                    branches.push((self.parenthesized_condition()?, self.block()?));
+                }
+                let otherwise = if self.peek() == Token::Else {
+                    self.next();
+                    // else if is the same as elif
+                    if self.peek() == Token::If {
+                        vec![self.statement()?]
+                    } else {
+                        self.block()?
+                    }
+                } else {
+                    Vec::new()
+                };
+                Ok(Statement::If { branches, otherwise })
+            }
+            Token::While => {
+                self.next();
+                Ok(Statement::While { condition: self.parenthesized_condition()?, body: self.block()? })
+            }
+            Token::Do => {
+                self.next();
+                let body = self.block()?;
+                self.expect(Token::While, "`while`")?;
+                Ok(Statement::DoWhile { body, condition: self.parenthesized_condition()? })
+            }
+            Token::Switch => {
+                self.next();
+                self.expect(Token::LeftParen, "`(`")?;
+                let var = self.var()?;
+                self.expect(Token::RightParen, "`)`")?;
+                self.expect(Token::LeftBrace, "`{`")?;
+                let mut cases: Vec<(Vec<Argument>, Vec<Statement>)> = Vec::new();
+                let mut values = Vec::new();
+                let mut default = None;
+                while self.peek() != Token::RightBrace {
+                    let is_default = match self.peek() {
+                        Token::Case => {
+                            self.next();
+                            values.push(self.argument()?);
+                            false
+                        }
+                        Token::Default if default.is_none() => {
+                            self.next();
+                            true
+                        }
+                        _ => return Err(self.error("`case` or `default`"))
+                    };
+                    self.expect(Token::Colon, "`:`")?;
+                    let mut body = Vec::new();
+                    while self.peek() != Token::Case && self.peek() != Token::Default && self.peek() != Token::RightBrace {
+                        body.push(self.statement()?);
+                    }
+                    if is_default {
+                        // cases right before default share its statements, and the values
+                        // without a compare of their own already go to default
+                        values.clear();
+                        default = Some(body);
+                    } else if !body.is_empty() || self.peek() == Token::RightBrace {
+                        // empty cases share the statements of the case after them
+                        cases.push((values.split_off(0), body));
+                    }
+                }
+                self.next();
+                if !values.is_empty() {
+                    cases.push((values, Vec::new()));
+                }
+                Ok(Statement::Switch { var, cases, default: default.unwrap_or_default() })
+            }
+            Token::Break => Ok(Statement::Break(self.next().span.clone())),
+            Token::Continue => Ok(Statement::Continue(self.next().span.clone())),
+            Token::Identifier => {
+                let (name, span) = {
+                    let lexeme = self.next();
+                    (lexeme.text.to_string(), lexeme.span.clone())
+                };
+                let mut args = Vec::new();
+                if self.peek() == Token::LeftParen {
+                    self.next();
+                    while self.peek() != Token::RightParen {
+                        args.push(self.argument()?);
+                        if self.peek() != Token::RightParen {
+                            self.expect(Token::Comma, "`,` or `)`")?;
+                        }
+                    }
+                    self.next();
+                }
+                Ok(Statement::Command { name, args, span })
+            }
+            _ => Err(self.error("a command"))
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument, CompileError> {
+        let span = self.lexemes[self.position].span.clone();
+        let value = match self.peek() {
+            Token::Number | Token::HexNumber => {
+                let text = self.next().text;
+                Value::Number(parse_number(text).ok_or(CompileError::new(span.clone(), format!("{} doesn't fit in 32 bits", text)))?)
+            }
+            Token::Identifier => Value::Name(self.next().text.to_string()),
+            Token::String => Value::Text(self.string()?),
+            _ => return Err(self.error("a number, a name or a string"))
+        };
+        Ok(Argument { value, span })
+    }
+
+    /// var(VAR_NAME)
+    fn var(&mut self) -> Result<Argument, CompileError> {
+        if self.peek() != Token::Identifier || self.lexemes[self.position].text != "var" {
+            return Err(self.error("`var(...)`"));
+        }
+        self.next();
+        self.expect(Token::LeftParen, "`(`")?;
+        let var = self.argument()?;
+        self.expect(Token::RightParen, "`)`")?;
+        Ok(var)
+    }
+
+    fn parenthesized_condition(&mut self) -> Result<Condition, CompileError> {
+        self.expect(Token::LeftParen, "`(`")?;
+        let condition = self.condition()?;
+        self.expect(Token::RightParen, "`)`")?;
+        Ok(condition)
+    }
+
+    fn condition(&mut self) -> Result<Condition, CompileError> {
+        let mut condition = self.and_condition()?;
+        while self.peek() == Token::Or {
+            self.next();
+            condition = Condition::Or(Box::new(condition), Box::new(self.and_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn and_condition(&mut self) -> Result<Condition, CompileError> {
+        let mut condition = self.unary_condition()?;
+        while self.peek() == Token::And {
+            self.next();
+            condition = Condition::And(Box::new(condition), Box::new(self.unary_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary_condition(&mut self) -> Result<Condition, CompileError> {
+        match self.peek() {
+            Token::Not => {
+                self.next();
+                Ok(Condition::Not(Box::new(self.unary_condition()?)))
+            }
+            Token::LeftParen => self.parenthesized_condition(),
+            Token::Identifier if self.lexemes[self.position].text == "var" => {
+                let var = self.var()?;
+                let comparison = match self.peek() {
+                    Token::Less => 0,
+                    Token::Equal => 1,
+                    Token::Greater => 2,
+                    Token::LessEqual => 3,
+                    Token::GreaterEqual => 4,
+                    Token::NotEqual => 5,
+                    // a var on its own is true if it isn't 0
+                    _ => return Ok(Condition::Var(var.clone(), 5, Argument { value: Value::Number(0), span: var.span }))
+                };
+                self.next();
+                Ok(Condition::Var(var, comparison, self.argument()?))
+            }
+            Token::Identifier => {
+                let (name, span) = {
+                    let lexeme = self.next();
+                    (lexeme.text.to_string(), lexeme.span.clone())
+                };
+                self.expect(Token::LeftParen, "`(`")?;
+                let argument = self.argument()?;
+                self.expect(Token::RightParen, "`)`")?;
+                match name.as_str() {
+                    "flag" => Ok(Condition::Flag(argument)),
+                    "defeated" => Ok(Condition::Defeated(argument)),
+                    _ => Err(CompileError::new(span, format!("conditions are flag(...), var(...) or defeated(...), not {}(...)", name)))
+                }
+            }
+            _ => Err(self.error("a condition"))
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Vec<Definition>, CompileError> {
+    let mut parser = Parser { lexemes: tokenize(source)?, position: 0 };
+    parser.definitions()
+}
+
+#[derive(Clone, Debug)]
+pub enum Operand {
+    Number(u32),
+    Name(String),
+}
+
+/// A command of a compiled script, which can be a macro of the decomps like msgbox
+pub enum Item {
+    /// a label, and whether it is a global label
+    Label(String, bool),
+    Command { name: String, operands: Vec<(Operand, Span)>, span: Span },
+}
+
+/// Scripts compiled to the commands of the decomps, and the texts, movements and marts they use
+pub struct Program {
+    pub scripts: Vec<(String, Vec<Item>)>,
+    /// the label, the text and where it is in the source
+    pub texts: Vec<(String, String, Span)>,
+    pub movements: Vec<(String, Vec<(Operand, Span)>)>,
+    pub marts: Vec<(String, Vec<(Operand, Span)>)>,
+}
+
+/// where break and continue jump to in a loop or a switch
+struct Jumps {
+    break_label: String,
+    continue_label: Option<String>,
+}
+
+struct Generator<'a> {
+    name: String,
+    items: Vec<Item>,
+    labels: usize,
+    text_count: usize,
+    texts: &'a mut Vec<(String, String, Span)>,
+    jumps: Vec<Jumps>,
+}
+
+impl<'a> Generator<'a> {
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("{}_{}", self.name, self.labels)
+    }
+
+    fn place(&mut self, label: &str) {
+        self.items.push(Item::Label(label.to_string(), false));
+    }
+
+    fn command(&mut self, name: &str, operands: Vec<(Operand, Span)>, span: &Span) {
+        self.items.push(Item::Command { name: name.to_string(), operands, span: span.clone() });
+    }
+
+    fn goto(&mut self, label: &str, span: &Span) {
+        self.command("goto", vec![(Operand::Name(label.to_string()), span.clone())], span);
+    }
+
+    /// true if the last command ends the script, so nothing after it is reached without a jump
+    fn ends(&self) -> bool {
+        match self.items.last() {
+            Some(Item::Command { name, .. }) => script::ends_script(name),
+            _ => false
+        }
+    }
+
+    fn operand(&mut self, argument: &Argument) -> (Operand, Span) {
+        let operand = match &argument.value {
+            Value::Number(value) => Operand::Number(*value),
+            Value::Name(name) => Operand::Name(name.clone()),
+            Value::Text(text) => {
+                let label = format!("{}_Text_{}", self.name, self.text_count);
+                self.text_count += 1;
+                self.texts.push((label.clone(), text.clone(), argument.span.clone()));
+                Operand::Name(label)
+            }
+        };
+        (operand, argument.span.clone())
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), CompileError> {
+        for statement in statements.iter() {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::Command { name, args, span } => {
+                let mut operands: Vec<(Operand, Span)> = args.iter().map(|a| self.operand(a)).collect();
+                // the macros of the decomps have defaults for these
+                match (name.as_str(), operands.len()) {
+                    ("msgbox", 1) => operands.push((Operand::Name(MSGBOX_DEFAULT.to_string()), span.clone())),
+                    ("giveitem", 1) | ("finditem", 1) => operands.push((Operand::Number(1), span.clone())),
+                    _ => {}
+                }
+                self.command(name, operands, span);
+            }
+            Statement::If { branches, otherwise } => {
+                let end = self.label();
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let next = self.label();
+                    self.jump_if(condition, &next, false)?;
+                    self.statements(body)?;
+                    // the last branch without an else goes on at the end anyway
+                    let last = i + 1 == branches.len() && otherwise.is_empty();
+                    if !self.ends() && !last {
+                        self.goto(&end, &condition_span(condition));
+                    }
+                    self.place(&next);
+                }
+                self.statements(otherwise)?;
+                self.place(&end);
+            }
+            Statement::While { condition, body } => {
+                let start = self.label();
+                let end = self.label();
+                self.place(&start);
+                self.jump_if(condition, &end, false)?;
+                self.jumps.push(Jumps { break_label: end.clone(), continue_label: Some(start.clone()) });
+                self.statements(body)?;
+                self.jumps.pop();
+                self.goto(&start, &condition_span(condition));
+                self.place(&end);
+            }
+            Statement::DoWhile { body, condition } => {
+                let start = self.label();
+                let check = self.label();
+                let end = self.label();
+                self.place(&start);
+                self.jumps.push(Jumps { break_label: end.clone(), continue_label: Some(check.clone()) });
+                self.statements(body)?;
+                self.jumps.pop();
+                self.place(&check);
+                self.jump_if(condition, &start, true)?;
+                self.place(&end);
+            }
+            Statement::Switch { var, cases, default } => {
+                let end = self.label();
+                let labels: Vec<String> = cases.iter().map(|_| self.label()).collect();
+                let default_label = self.label();
+                let var_operand = self.operand(var);
+                for ((values, _), label) in cases.iter().zip(labels.iter()) {
+                    for value in values.iter() {
+                        let value = self.operand(value);
+                        let span = value.1.clone();
+                        self.command("compare", vec![var_operand.clone(), value], &span);
+                        self.command("goto_if_eq", vec![(Operand::Name(label.clone()), span.clone())], &span);
+                    }
+                }
+                self.goto(&default_label, &var.span);
+                self.jumps.push(Jumps { break_label: end.clone(), continue_label: None });
+                // every case ends the switch, there's no falling through to the next case
+                for ((_, body), label) in cases.iter().zip(labels.iter()) {
+                    self.place(label);
+                    self.statements(body)?;
+                    if !self.ends() {
+                        self.goto(&end, &var.span);
+                    }
+                }
+                self.place(&default_label);
+                self.statements(default)?;
+                self.jumps.pop();
+                self.place(&end);
+            }
+            Statement::Break(span) => {
+                let label = match self.jumps.last() {
+                    Some(jumps) => jumps.break_label.clone(),
+                    None => return Err(CompileError::new(span.clone(), "break outside of a loop or a switch".to_string()))
+                };
+                self.goto(&label, span);
+            }
+            Statement::Continue(span) => {
+                let label = match self.jumps.iter().rev().filter_map(|j| j.continue_label.clone()).next() {
+                    Some(label) => label,
+                    None => return Err(CompileError::new(span.clone(), "continue outside of a loop".to_string()))
+                };
+                self.goto(&label, span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Jumps to the label if the condition is `when`, and goes on after it otherwise
+    fn jump_if(&mut self, condition: &Condition, label: &str, when: bool) -> Result<(), CompileError> {
+        let target = |span: &Span| (Operand::Name(label.to_string()), span.clone());
+        match condition {
+            Condition::Flag(argument) | Condition::Defeated(argument) => {
+                let check = if let Condition::Flag(_) = condition { "checkflag" } else { "checktrainerflag" };
+                let operand = self.operand(argument);
+                self.command(check, vec![operand], &argument.span);
+                let jump = if when { "goto_if_eq" } else { "goto_if_ne" };
+                self.command(jump, vec![target(&argument.span)], &argument.span);
+            }
+            Condition::Var(var, comparison, value) => {
+                let operands = vec![self.operand(var), self.operand(value)];
+                self.command("compare", operands, &var.span);
+                let comparison = if when { *comparison } else { negate(*comparison) };
+                self.command(&format!("goto_if_{}", CONDITIONS[comparison]), vec![target(&var.span)], &var.span);
+            }
+            Condition::Not(condition) => self.jump_if(condition, label, !when)?,
+            Condition::And(left, right) => {
+                if when {
+                    let skip = self.label();
+                    self.jump_if(left, &skip, false)?;
+                    self.jump_if(right, label, true)?;
+                    self.place(&skip);
+                } else {
+                    self.jump_if(left, label, false)?;
+                    self.jump_if(right, label, false)?;
+                }
+            }
+            Condition::Or(left, right) => {
+                if when {
+                    self.jump_if(left, label, true)?;
+                    self.jump_if(right, label, true)?;
+                } else {
+                    let skip = self.label();
+                    self.jump_if(left, &skip, true)?;
+                    self.jump_if(right, label, false)?;
+                    self.place(&skip);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the comparison that is true when the other one is false
+fn negate(comparison: usize) -> usize {
+    match comparison {
+        0 => 4,
+        1 => 5,
+        2 => 3,
+        3 => 2,
+        4 => 0,
+        _ => 1,
+    }
+}
+
+fn condition_span(condition: &Condition) -> Span {
+    match condition {
+        Condition::Flag(argument) | Condition::Defeated(argument) | Condition::Var(argument, _, _) => argument.span.clone(),
+        Condition::Not(condition) | Condition::And(condition, _) | Condition::Or(condition, _) => condition_span(condition),
+    }
+}
+
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    let mut program = Program { scripts: Vec::new(), texts: Vec::new(), movements: Vec::new(), marts: Vec::new() };
+    for definition in parse(source)? {
+        match definition {
+            Definition::Script { name, body, end } => {
+                let mut generator = Generator { name: name.clone(), items: Vec::new(), labels: 0, text_count: 0, texts: &mut program.texts, jumps: Vec::new() };
+                generator.items.push(Item::Label(name.clone(), true));
+                generator.statements(&body)?;
+                // scripts end by themselves like in poryscript
+                if !generator.ends() {
+                    generator.command("end", Vec::new(), &end);
+                }
+                let mut items = generator.items;
+                // the labels of the control flow that nothing jumps to only make the output harder to read
+                let used: Vec<String> = items.iter()
+                    .flat_map(|item| match item {
+                        Item::Command { operands, .. } => operands.iter().filter_map(|(o, _)| match o {
+                            Operand::Name(name) => Some(name.clone()),
+                            _ => None
+                        }).collect(),
+                        _ => Vec::new()
+                    })
+                    .collect();
+                items.retain(|item| match item {
+                    Item::Label(label, false) => used.contains(label),
+                    _ => true
+                });
+                program.scripts.push((name, items));
+            }
+            Definition::Text { name, text, span } => program.texts.push((name, text, span)),
+            Definition::Movement { name, steps } => {
+                let steps = steps.iter().map(|step| (to_operand(&step.value), step.span.clone())).collect();
+                program.movements.push((name, steps));
+            }
+            Definition::Mart { name, items } => {
+                let items = items.iter().map(|item| (to_operand(&item.value), item.span.clone())).collect();
+                program.marts.push((name, items));
+            }
+        }
+    }
+    Ok(program)
+}
+
+fn to_operand(value: &Value) -> Operand {
+    match value {
+        Value::Number(value) => Operand::Number(*value),
+        Value::Name(name) | Value::Text(name) => Operand::Name(name.clone()),
+    }
+}
+
+fn format_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Number(value) if *value < 10 => value.to_string(),
+        Operand::Number(value) => format!("0x{:X}", value),
+        Operand::Name(name) => name.clone(),
+    }
+}
+
+impl Program {
+    /// Writes the program as assembly with the macros of the decomps, like the .inc files of data/scripts
+    pub fn to_inc(&self) -> String {
+        let mut output = String::new();
+        for (_, items) in self.scripts.iter() {
+            for item in items.iter() {
+                match item {
+                    Item::Label(label, true) => output.push_str(&format!("{}::\n", label)),
+                    Item::Label(label, false) => output.push_str(&format!("{}:\n", label)),
+                    Item::Command { name, operands, .. } if operands.is_empty() => output.push_str(&format!("\t{}\n", name)),
+                    Item::Command { name, operands, .. } => {
+                        let operands: Vec<String> = operands.iter().map(|(o, _)| format_operand(o)).collect();
+                        output.push_str(&format!("\t{} {}\n", name, operands.join(", ")));
+                    }
+                }
+            }
+            output.push('\n');
+        }
+        for (label, text, _) in self.texts.iter() {
+            output.push_str(&format!("{}::\n", label));
+            for line in script::split_string(text) {
+                output.push_str(&format!("\t.string \"{}\"\n", line));
+            }
+            output.push('\n');
+        }
+        for (label, steps) in self.movements.iter() {
+            output.push_str(&format!("{}::\n", label));
+            for (step, _) in steps.iter() {
+                match step {
+                    Operand::Number(value) => output.push_str(&format!("\t.byte 0x{:02X}\n", value)),
+                    Operand::Name(name) => output.push_str(&format!("\t{}\n", name)),
+                }
+            }
+            output.push_str("\tstep_end\n\n");
+        }
+        for (label, items) in self.marts.iter() {
+            output.push_str(&format!("{}::\n", label));
+            for (item, _) in items.iter() {
+                output.push_str(&format!("\t.2byte {}\n", format_operand(item)));
+            }
+            output.push_str("\t.2byte 0\n\n");
+        }
+        output.trim_end().to_string() + "\n"
+    }
+
+    /// Assembles the program to script bytecode that starts at `base` in the ROM. Returns
+    /// the bytes and where every script, text, movement and mart is.
+    pub fn assemble(&self, game: Game, charmap: &Charmap, constants: &Constants, base: usize) -> Result<(Vec<u8>, Vec<(String, usize)>), CompileError> {
+        let mut commands = Vec::new();
+        for (_, items) in self.scripts.iter() {
+            for item in items.iter() {
+                match item {
+                    Item::Label(label, global) => commands.push(Assembled::Label(label.clone(), *global)),
+                    Item::Command { name, operands, span } => {
+                        for command in expand(game, constants, name, operands, span)? {
+                            commands.push(command);
+                        }
+                    }
+                }
+            }
+        }
+
+        // marts come first, their items have to be aligned
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        let mut globals = Vec::new();
+        let mut offset = base;
+        for (label, items) in self.marts.iter() {
+            labels.insert(label.clone(), offset);
+            globals.push((label.clone(), offset));
+            offset += (items.len() + 1) * 2;
+        }
+        for command in commands.iter() {
+            match command {
+                Assembled::Label(label, global) => {
+                    labels.insert(label.clone(), offset);
+                    if *global {
+                        globals.push((label.clone(), offset));
+                    }
+                }
+                Assembled::Command { args, .. } => offset += 1 + args.iter().map(|(arg, _, _)| arg_size(*arg)).sum::<usize>(),
+            }
+        }
+        let mut texts = Vec::with_capacity(self.texts.len());
+        for (label, text, span) in self.texts.iter() {
+            let encoded = charmap.encode(text)
+                .map_err(|e| CompileError::new(span.clone(), format!("failed to encode {}: {}", label, e)))?;
+            labels.insert(label.clone(), offset);
+            globals.push((label.clone(), offset));
+            offset += encoded.len();
+            texts.push(encoded);
+        }
+        for (label, steps) in self.movements.iter() {
+            labels.insert(label.clone(), offset);
+            globals.push((label.clone(), offset));
+            offset += steps.len() + 1;
+        }
+
+        let resolve = |operand: &Operand, span: &Span| -> Result<u32, CompileError> {
+            match operand {
+                Operand::Number(value) => Ok(*value),
+                Operand::Name(name) => labels.get(name).map(|offset| (offset + ROM_BASE) as u32)
+                    .or_else(|| constants.get(name))
+                    .ok_or(CompileError::new(span.clone(), format!("{} isn't a label or a constant", name)))
+            }
+        };
+        let mut bytes = Vec::with_capacity(offset - base);
+        for (_, items) in self.marts.iter() {
+            for (item, span) in items.iter() {
+                bytes.extend_from_slice(&fit(resolve(item, span)?, 2, span)?.to_le_bytes()[..2]);
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        for command in commands.iter() {
+            if let Assembled::Command { opcode, args } = command {
+                bytes.push(*opcode);
+                for (arg, operand, span) in args.iter() {
+                    let value = resolve(operand, span)?;
+                    match arg {
+                        Arg::Byte => bytes.push(fit(value, 1, span)? as u8),
+                        Arg::Half => bytes.extend_from_slice(&fit(value, 2, span)?.to_le_bytes()[..2]),
+                        // map constants have the group in their high byte
+                        Arg::Map => {
+                            let map = fit(value, 2, span)?;
+                            bytes.push((map >> 8) as u8);
+                            bytes.push((map & 0xff) as u8);
+                        }
+                        _ => bytes.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+        }
+        for text in texts.iter() {
+            bytes.extend_from_slice(text);
+        }
+        for (_, steps) in self.movements.iter() {
+            for (step, span) in steps.iter() {
+                let value = match step {
+                    Operand::Name(name) => constants.movement(name).or_else(|| constants.get(name))
+                        .ok_or(CompileError::new(span.clone(), format!("{} isn't a movement", name)))?,
+                    Operand::Number(value) => *value,
+                };
+                bytes.push(fit(value, 1, span)? as u8);
+            }
+            bytes.push(STEP_END);
+        }
+        Ok((bytes, globals))
+    }
+}
+
+enum Assembled {
+    Label(String, bool),
+    Command { opcode: u8, args: Vec<(Arg, Operand, Span)> },
+}
+
+fn arg_size(arg: Arg) -> usize {
+    match arg {
+        Arg::Byte => 1,
+        Arg::Half | Arg::Map => 2,
+        _ => 4,
+    }
+}
+
+fn fit(value: u32, bytes: usize, span: &Span) -> Result<u32, CompileError> {
+    if bytes < 4 && value >> (bytes * 8) != 0 {
+        return Err(CompileError::new(span.clone(), format!("0x{:X} doesn't fit in {} bytes", value, bytes)));
+    }
+    Ok(value)
+}
+
+/// Expands the macros of the decomps to the commands they stand for
+fn expand(game: Game, constants: &Constants, name: &str, operands: &[(Operand, Span)], span: &Span) -> Result<Vec<Assembled>, CompileError> {
+    let number = |value: u32| (Operand::Number(value), span.clone());
+    let primitive = |name: &str, operands: Vec<(Operand, Span)>| expand(game, constants, name, &operands, span);
+    let count = |expected: usize| -> Result<(), CompileError> {
+        if operands.len() != expected {
+            return Err(CompileError::new(span.clone(), format!("{} takes {} arguments, not {}", name, expected, operands.len())));
+        }
+        Ok(())
+    };
+    let mut expanded = Vec::new();
+    match name {
+        "msgbox" => {
+            count(2)?;
+            expanded.append(&mut primitive("loadword", vec![number(0), operands[0].clone()])?);
+            expanded.append(&mut primitive("callstd", vec![operands[1].clone()])?);
+        }
+        "giveitem" | "finditem" => {
+            count(2)?;
+            let std = if name == "giveitem" { STD_OBTAIN_ITEM } else { STD_FIND_ITEM };
+            expanded.append(&mut primitive("setorcopyvar", vec![number(VAR_ITEM), operands[0].clone()])?);
+            expanded.append(&mut primitive("setorcopyvar", vec![number(VAR_AMOUNT), operands[1].clone()])?);
+            expanded.append(&mut primitive("callstd", vec![number(std)])?);
+        }
+        "compare" => {
+            count(2)?;
+            let value = match &operands[1].0 {
+                Operand::Number(value) => Some(*value),
+                Operand::Name(name) => constants.get(name),
+            };
+            let command = if value.map(|v| v >= VARS_START).unwrap_or(false) { "compare_var_to_var" } else { "compare_var_to_value" };
+            expanded.append(&mut primitive(command, operands.to_vec())?);
+        }
+        _ if (name.starts_with("goto_if_") || name.starts_with("call_if_")) && CONDITIONS.contains(&&name[8..]) => {
+            count(1)?;
+            let condition = CONDITIONS.iter().position(|c| *c == &name[8..]).unwrap() as u32;
+            expanded.append(&mut primitive(&name[..7], vec![number(condition), operands[0].clone()])?);
+        }
+        _ => {
+            let (opcode, args) = script::opcode(game, name)
+                .ok_or(CompileError::new(span.clone(), format!("unknown command {} in {}", name, game.name())))?;
+            // the pointers after the type, trainer and local id of trainerbattle depend on the type
+            let extra = if name == "trainerbattle" { operands.len().saturating_sub(args.len()) } else { 0 };
+            count(args.len() + extra)?;
+            let args = args.iter().cloned().chain(std::iter::repeat(Arg::Word).take(extra))
+                .zip(operands.iter())
+                .map(|(arg, (operand, span))| (arg, operand.clone(), span.clone()))
+                .collect();
+            expanded.push(Assembled::Command { opcode, args });
+        }
+    }
+    Ok(expanded)
+}
+
+/// the MSGBOX_ types msgbox takes, for compiling without the constants of a decomp
+pub fn default_constants() -> Constants {
+    let mut constants = Constants::new();
+    for (value, name) in MSGBOX_TYPES.iter() {
+        constants.define(name, *value);
+    }
+    constants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inc(source: &str) -> String {
+        compile(source).map_err(|e| e.format("test.pory", source)).unwrap().to_inc()
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "script Cond {\n    if (!(flag(1) || flag(2)) && (var(0x4000) == 1 || !flag(3))) {\n        release\n    }\n}\n";
+        assert_eq!(inc(source), "Cond::\n\
+            \tcheckflag 1\n\tgoto_if_eq Cond_2\n\
+            \tcheckflag 2\n\tgoto_if_eq Cond_2\n\
+            \tcompare 0x4000, 1\n\tgoto_if_eq Cond_3\n\
+            \tcheckflag 3\n\tgoto_if_eq Cond_2\n\
+            Cond_3:\n\trelease\n\
+            Cond_2:\n\tend\n");
+    }
+
+    #[test]
+    fn do_while() {
+        let source = "script Loop {\n    do {\n        addvar(0x4001, 1)\n        if (flag(4)) { continue }\n    } while (var(0x4001) < 3 && !flag(5))\n}\n";
+        assert_eq!(inc(source), "Loop::\n\
+            Loop_1:\n\taddvar 0x4001, 1\n\
+            \tcheckflag 4\n\tgoto_if_ne Loop_5\n\tgoto Loop_2\n\
+            Loop_5:\n\
+            Loop_2:\n\tcompare 0x4001, 3\n\tgoto_if_ge Loop_6\n\
+            \tcheckflag 5\n\tgoto_if_ne Loop_1\n\
+            Loop_6:\n\tend\n");
+    }
+
+    #[test]
+    fn switch_with_shared_and_default_cases() {
+        let source = "script Pick {\n    switch (var(0x8000)) {\n        case 1:\n        case 2:\n            setvar(0x4000, 1)\n        case 3:\n            setvar(0x4000, 3)\n            break\n        case 4:\n        default:\n            setvar(0x4000, 0)\n    }\n}\n";
+        assert_eq!(inc(source), "Pick::\n\
+            \tcompare 0x8000, 1\n\tgoto_if_eq Pick_2\n\
+            \tcompare 0x8000, 2\n\tgoto_if_eq Pick_2\n\
+            \tcompare 0x8000, 3\n\tgoto_if_eq Pick_3\n\
+            \tgoto Pick_4\n\
+            Pick_2:\n\tsetvar 0x4000, 1\n\tgoto Pick_1\n\
+            Pick_3:\n\tsetvar 0x4000, 3\n\tgoto Pick_1\n\
+            Pick_4:\n\tsetvar 0x4000, 0\n\
+            Pick_1:\n\tend\n");
+    }
+
+    #[test]
+    fn invalid_scripts() {
+        assert!(compile("script A {\n    break\n}\n").is_err());
+        assert!(compile("script A {\n    if (flag(1) {\n}\n").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The #defines of a decomp's include/constants, like FLAG_BADGE01_GET or ITEM_POTION,
+/// and the movement macros of asm/macros/movement.inc
+pub struct Constants {
+    defines: HashMap<String, String>,
+    /// movement macros like walk_up, and the MOVEMENT_ACTION_ they stand for
+    movements: HashMap<String, String>,
+}
+
+impl Constants {
+    pub fn new() -> Constants {
+        Constants { defines: HashMap::new(), movements: HashMap::new() }
+    }
+
+    pub fn load(root: &Path) -> Result<Constants, String> {
+        let mut constants = Constants::new();
+        let folder = root.join("include/constants");
+        let mut folders = vec![folder];
+        while let Some(folder) = folders.pop() {
+            for entry in fs::read_dir(&folder).map_err(|e| format!("failed to read {}: {}", folder.display(), e))? {
+                let path = entry.map_err(|e| e.to_string())?.path();
+                if path.is_dir() {
+                    folders.push(path);
+                } else if path.extension().map(|e| e == "h").unwrap_or(false) {
+                    let contents = fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                    constants.add_header(&contents);
+                }
+            }
+        }
+        // older decomps don't have the movement macros in one file
+        let movement = root.join("asm/macros/movement.inc");
+        if let Ok(contents) = fs::read_to_string(&movement) {
+            constants.add_movements(&contents);
+        }
+        Ok(constants)
+    }
+
+    /// #define NAME value, without the macros that take arguments
+    pub fn add_header(&mut self, contents: &str) {
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            let mut line = line.trim().to_string();
+            // definitions go on over lines that end with a backslash
+            while line.ends_with('\\') {
+                line.pop();
+                line.push_str(lines.next().unwrap_or(""));
+            }
+            if !line.starts_with("#define") || !line[7..].starts_with(char::is_whitespace) {
+                continue;
+            }
+            let rest = line[7..].trim();
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let (name, value) = rest.split_at(end);
+            // function-like macros have their arguments right after the name
+            if name.is_empty() || value.starts_with('(') {
+                continue;
+            }
+            let value = value.split("//").next().unwrap_or("").split("/*").next().unwrap_or("").trim();
+            self.defines.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// create_movement_action walk_up, MOVEMENT_ACTION_WALK_NORMAL_UP
+    pub fn add_movements(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let line = line.trim();
+            if !line.starts_with("create_movement_action ") {
+                continue;
+            }
+            let rest = &line["create_movement_action ".len()..];
+            let parts: Vec<&str> = rest.split(',').map(|p| p.trim()).collect();
+            if parts.len() == 2 && !parts[0].is_empty() {
+                self.movements.insert(parts[0].to_string(), parts[1].to_string());
+            }
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: u32) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.evaluate(name, 0)
+    }
+
+    pub fn movement(&self, name: &str) -> Option<u32> {
+        self.movements.get(name).and_then(|action| self.evaluate(action, 0))
+    }
+
+    /// Evaluates the value of a constant, with the operators the headers use
+    pub fn evaluate(&self, expression: &str, depth: usize) -> Option<u32> {
+        // constants defined in terms of themselves would never end
+        if depth > 32 {
+            return None;
+        }
+        let tokens = tokenize(expression)?;
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position, 0, depth)?;
+        if position == tokens.len() { Some(value) } else { None }
+    }
+
+    fn expression(&self, tokens: &[String], position: &mut usize, min_precedence: usize, depth: usize) -> Option<u32> {
+        let mut left = self.operand(tokens, position, depth)?;
+        while let Some(operator) = tokens.get(*position) {
+            let precedence = match operator.as_str() {
+                "|" => 1,
+                "&" => 2,
+                "<<" | ">>" => 3,
+                "+" | "-" => 4,
+                "*" | "/" => 5,
+                _ => break
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            *position += 1;
+            let right = self.expression(tokens, position, precedence + 1, depth)?;
+            left = match operator.as_str() {
+                "|" => left | right,
+                "&" => left & right,
+                "<<" => left.checked_shl(right)?,
+                ">>" => left.checked_shr(right)?,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                _ => left.checked_div(right)?,
+            };
+        }
+        Some(left)
+    }
+
+    fn operand(&self, tokens: &[String], position: &mut usize, depth: usize) -> Option<u32> {
+        let token = tokens.get(*position)?;
+        *position += 1;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens, position, 0, depth)?;
+                if tokens.get(*position).map(|t| t.as_str()) != Some(")") {
+                    return None;
+                }
+                *position += 1;
+                Some(value)
+            }
+            "-" => self.operand(tokens, position, depth).map(|v| 0u32.wrapping_sub(v)),
+            "~" => self.operand(tokens, position, depth).map(|v| !v),
+            _ => parse_number(token).or_else(|| self.evaluate(self.defines.get(token)?, depth + 1))
+        }
+    }
+}
+
+/// replaces comments with spaces, so offsets into the source stay the same
+pub fn mask_comments(source: &str) -> String {
+    let mut masked = source.as_bytes().to_vec();
+    let mut i = 0;
+    while i + 1 < masked.len() {
+        let end = match &masked[i..i + 2] {
+            b"//" => masked[i..].iter().position(|&b| b == b'\n').map(|e| i + e).unwrap_or(masked.len()),
+            b"/*" => masked[i + 2..].windows(2).position(|w| w == b"*/").map(|e| i + e + 4).unwrap_or(masked.len()),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        for byte in masked[i..end].iter_mut() {
+            *byte = b' ';
+        }
+        i = end;
+    }
+    // only whole comments were replaced, so this is still UTF-8
+    String::from_utf8(masked).unwrap_or_default()
+}
+
+/// parses 123, 0x7B and 0b1111011, with the suffixes of C like 123u
+pub fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim_end_matches(|c| c == 'u' || c == 'U' || c == 'l' || c == 'L');
+    if text.starts_with("0x") || text.starts_with("0X") {
+        u32::from_str_radix(&text[2..], 16).ok()
+    } else if text.starts_with("0b") {
+        u32::from_str_radix(&text[2..], 2).ok()
+    } else {
+        text.parse::<u32>().ok()
+    }
+}
+
+fn tokenize(expression: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expression.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else if (c == '<' || c == '>') && chars.get(i + 1) == Some(&c) {
+            tokens.push(chars[i..i + 2].iter().collect());
+            i += 2;
+        } else if "()|&+-*/~".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            return None;
+        }
+    }
+    Some(tokens)
+}
@@ -0,0 +1,367 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::porymap::{Layout, Layouts};
+use crate::tileset::TileStorage;
+
+/// The decompilation a project is based on
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Game {
+    Emerald,
+    FireRed,
+    Ruby,
+}
+
+impl Game {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Game::Emerald => "pokeemerald",
+            Game::FireRed => "pokefirered",
+            Game::Ruby => "pokeruby",
+        }
+    }
+
+    /// FireRed stores metatile attributes as u32 instead of u16
+    pub fn metatile_attribute_size(&self) -> usize {
+        match self {
+            Game::FireRed => 4,
+            _ => 2,
+        }
+    }
+
+    /// the number of tiles and metatiles that belong to the primary tileset
+    pub fn primary_tile_count(&self) -> usize {
+        match self {
+            Game::FireRed => 0x280,
+            _ => 0x200,
+        }
+    }
+
+    /// the number of palettes that belong to the primary tileset
+    pub fn primary_palette_count(&self) -> usize {
+        match self {
+            Game::FireRed => 7,
+            _ => 6,
+        }
+    }
+
+    /// the number of palettes that belong to the secondary tileset, the map background has 13
+    pub fn secondary_palette_count(&self) -> usize {
+        13 - self.primary_palette_count()
+    }
+}
+
+/// A checkout of one of the decompilations
+pub struct Project {
+    pub root: PathBuf,
+    pub game: Game,
+}
+
+/// The sizes of a tileset in a project
+pub struct TilesetInfo {
+    /// the name of the tileset folder
+    pub name: String,
+    pub path: PathBuf,
+    pub primary: bool,
+    pub tiles: usize,
+    pub palettes: usize,
+    pub metatiles: usize,
+}
+
+impl Project {
+    pub fn open(root: &str) -> Result<Project, String> {
+        let root = PathBuf::from(root);
+        if !root.join("data/tilesets").is_dir() {
+            return Err(format!("{} is not a decomp project, it has no data/tilesets folder", root.display()));
+        }
+        let game = Project::detect_game(&root)
+            .ok_or(format!("couldn't detect if {} is pokeemerald, pokefirered or pokeruby", root.display()))?;
+        Ok(Project { root, game })
+    }
+
+    /// the game code in the Makefile or config.mk tells the games apart
+    pub fn detect_game(root: &Path) -> Option<Game> {
+        for file_name in ["Makefile", "config.mk"].iter() {
+            let contents = match fs::read_to_string(root.join(file_name)) {
+                Ok(contents) => contents,
+                Err(_) => continue
+            };
+            if contents.contains("BPEE") {
+                return Some(Game::Emerald);
+            }
+            if contents.contains("BPRE") || contents.contains("BPGE") {
+                return Some(Game::FireRed);
+            }
+            if contents.contains("AXVE") || contents.contains("AXPE") {
+                return Some(Game::Ruby);
+            }
+        }
+        None
+    }
+
+    pub fn map_groups_path(&self) -> PathBuf {
+        self.root.join("data/maps/map_groups.json")
+    }
+
+    pub fn layouts_path(&self) -> PathBuf {
+        self.root.join("data/layouts/layouts.json")
+    }
+
+    pub fn map_path(&self, map_name: &str) -> PathBuf {
+        self.root.join("data/maps").join(map_name).join("map.json")
+    }
+
+    pub fn wild_encounters_path(&self) -> PathBuf {
+        self.root.join("src/data/wild_encounters.json")
+    }
+
+    /// returns a layout by its id or name from layouts.json
+    pub fn find_layout(&self, layout: &str) -> Result<Layout, String> {
+        Layouts::load(&self.layouts_path())?.find(layout).cloned()
+            .ok_or(format!("couldn't find layout {} in {}", layout, self.layouts_path().display()))
+    }
+
+    /// returns the folder of a tileset of the given kind, which doesn't have to exist yet
+    pub fn tileset_folder(&self, name: &str, primary: bool) -> PathBuf {
+        if let Some(path) = self.find_tileset_of_kind(name, primary) {
+            return path;
+        }
+        let kind = if primary { "primary" } else { "secondary" };
+        self.root.join("data/tilesets").join(kind).join(folder_name(name))
+    }
+
+    /// returns the folder of an existing tileset by its folder name or symbol name,
+    /// so `general`, `General` and `gTileset_General` are all found
+    pub fn find_tileset(&self, name: &str) -> Option<(PathBuf, bool)> {
+        self.find_tileset_of_kind(name, true).map(|path| (path, true))
+            .or_else(|| self.find_tileset_of_kind(name, false).map(|path| (path, false)))
+    }
+
+    /// like find_tileset, but only searches the primary or the secondary tilesets,
+    /// since a primary and a secondary tileset can share a name
+    pub fn find_tileset_of_kind(&self, name: &str, primary: bool) -> Option<PathBuf> {
+        let wanted = normalize_name(name);
+        let kind = if primary { "primary" } else { "secondary" };
+        let entries = fs::read_dir(self.root.join("data/tilesets").join(kind)).ok()?;
+        for entry in entries.filter_map(|e| e.ok()) {
+            if entry.path().is_dir() && normalize_name(&entry.file_name().to_string_lossy()) == wanted {
+                return Some(entry.path());
+            }
+        }
+        None
+    }
+
+    /// returns every tileset in the project, primary tilesets first
+    pub fn tilesets(&self) -> Vec<TilesetInfo> {
+        let mut tilesets = Vec::new();
+        for &(kind, primary) in [("primary", true), ("secondary", false)].iter() {
+            let mut paths: Vec<PathBuf> = match fs::read_dir(self.root.join("data/tilesets").join(kind)) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect(),
+                Err(_) => continue
+            };
+            paths.sort();
+            for path in paths {
+                tilesets.push(self.tileset_info(path, primary));
+            }
+        }
+        tilesets
+    }
+
+    fn tileset_info(&self, path: PathBuf, primary: bool) -> TilesetInfo {
+        let tiles = match TileStorage::read_tiles_image(path.join("tiles.png").to_string_lossy().to_string()) {
+            Ok(mut tiles) => {
+                TileStorage::trim_blank_tiles(&mut tiles);
+                tiles.len()
+            }
+            Err(_) => 0
+        };
+        // every tileset has all 16 palette files, but only uses the slots of its kind
+        let primary_palettes = self.game.primary_palette_count();
+        let slots = if primary { 0..primary_palettes } else { primary_palettes..primary_palettes + self.game.secondary_palette_count() };
+        let palettes = slots.filter(|i| {
+            let path = path.join(format!("palettes/{:0>2}.pal", i)).to_string_lossy().to_string();
+            match TileStorage::read_pal_file(path) {
+                Ok(palette) => palette.iter().any(|color| *color != [0, 0, 0]),
+                Err(_) => false
+            }
+        }).count();
+        let metatiles = fs::metadata(path.join("metatiles.bin")).map(|m| m.len() as usize / 16).unwrap_or(0);
+        TilesetInfo {
+            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            path,
+            primary,
+            tiles,
+            palettes,
+            metatiles,
+        }
+    }
+}
+
+/// converts a symbol name like `MyTown` to the folder name `my_town`
+pub fn folder_name(name: &str) -> String {
+    let name = name.trim_start_matches("gTileset_");
+    if name.contains('_') || name.chars().all(|c| !c.is_uppercase()) {
+        return name.to_lowercase();
+    }
+    let mut folder = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            folder.push('_');
+        }
+        folder.extend(c.to_lowercase());
+    }
+    folder
+}
+
+/// tileset names compare equal if they only differ in case, underscores and the gTileset_ prefix
+pub fn normalize_name(name: &str) -> String {
+    name.trim_start_matches("gTileset_").replace('_', "").to_lowercase()
+}
+
+/// A tileset as it is referenced by the C sources of the decomps
+pub struct TilesetSource {
+    /// the name used in symbols, like `General` in `gTileset_General`
+    pub name: String,
+    /// the folder of the tileset relative to the root of the decomp
+    pub path: String,
+    pub primary: bool,
+    pub game: Game,
+}
+
+impl TilesetSource {
+    pub fn headers(&self) -> String {
+        format!("const struct Tileset gTileset_{name} =\n\
+                 {{\n    \
+                     .isCompressed = TRUE,\n    \
+                     .isSecondary = {secondary},\n    \
+                     .tiles = gTilesetTiles_{name},\n    \
+                     .palettes = gTilesetPalettes_{name},\n    \
+                     .metatiles = gMetatiles_{name},\n    \
+                     .metatileAttributes = gMetatileAttributes_{name},\n    \
+                     .callback = NULL,\n\
+                 }};\n",
+                name = self.name,
+                secondary = if self.primary { "FALSE" } else { "TRUE" })
+    }
+
+    pub fn graphics(&self) -> String {
+        let mut graphics = format!("const u32 gTilesetTiles_{}[] = INCBIN_U32(\"{}/tiles.4bpp.lz\");\n\n", self.name, self.path);
+        graphics.push_str(&format!("const u16 gTilesetPalettes_{}[][16] =\n{{\n", self.name));
+        for i in 0..16 {
+            graphics.push_str(&format!("    INCBIN_U16(\"{}/palettes/{:0>2}.gbapal\"),\n", self.path, i));
+        }
+        graphics.push_str("};\n");
+        graphics
+    }
+
+    pub fn metatiles(&self) -> String {
+        let bits = self.game.metatile_attribute_size() * 8;
+        format!("const u16 gMetatiles_{name}[] = INCBIN_U16(\"{path}/metatiles.bin\");\n\
+                 const u{bits} gMetatileAttributes_{name}[] = INCBIN_U{bits}(\"{path}/metatile_attributes.bin\");\n",
+                name = self.name,
+                path = self.path,
+                bits = bits)
+    }
+}
+
+/// returns the root of the decomp and the path of the tileset folder relative to it,
+/// if the folder is inside of data/tilesets/<primary/secondary>/
+pub fn find_tileset_root(folder: &str) -> Option<(PathBuf, String)> {
+    let components: Vec<Component> = Path::new(folder).components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    if components.len() < 4 {
+        return None;
+    }
+    let tail: Vec<String> = components[components.len() - 4..].iter()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    if tail[0] != "data" || tail[1] != "tilesets" || (tail[2] != "primary" && tail[2] != "secondary") {
+        return None;
+    }
+    let mut root = PathBuf::new();
+    for component in components[..components.len() - 4].iter() {
+        root.push(component.as_os_str());
+    }
+    if root.as_os_str().is_empty() {
+        root.push(".");
+    }
+    Some((root, tail.join("/")))
+}
+
+/// Write the C snippets for the tileset to the output folder, along with the palettes and
+/// metatile attributes the snippets need, and append them to the decomp sources when the
+/// output folder is inside of a decomp. Returns a description of what was written.
+pub fn emit_c(output_folder: &str, name: &str, primary: bool, metatile_count: usize) -> Result<String, String> {
+    let root = find_tileset_root(output_folder);
+    // tilesets outside of a decomp get the pokeemerald layout
+    let game = root.as_ref().and_then(|(root, _)| Project::detect_game(root)).unwrap_or(Game::Emerald);
+    let source = TilesetSource {
+        name: name.to_string(),
+        path: match &root {
+            Some((_, path)) => path.clone(),
+            None => output_folder.trim_end_matches('/').replace('\\', "/"),
+        },
+        primary,
+        game,
+    };
+
+    // the decomp includes all 16 palettes of every tileset
+    for i in 0..16 {
+        let path = format!("{}/palettes/{:0>2}.pal", output_folder, i);
+        if !Path::new(&path).exists() {
+            TileStorage::output_palette(&Default::default(), path);
+        }
+    }
+    // new tilesets start with blank metatile attributes, they are edited in Porymap
+    let attributes_path = format!("{}/metatile_attributes.bin", output_folder);
+    if !Path::new(&attributes_path).exists() {
+        write_file(&attributes_path, &vec![0u8; metatile_count * game.metatile_attribute_size()])?;
+    }
+
+    let snippets = [
+        ("headers.h", source.headers()),
+        ("graphics.h", source.graphics()),
+        ("metatiles.h", source.metatiles()),
+    ];
+    for (file_name, snippet) in snippets.iter() {
+        write_file(&format!("{}/{}", output_folder, file_name), snippet.as_bytes())?;
+    }
+
+    let (root, _) = match root {
+        Some(root) => root,
+        None => return Ok(format!("C snippets written to {}", output_folder)),
+    };
+    let mut patched = Vec::new();
+    for (file_name, snippet) in snippets.iter() {
+        let path = root.join("src/data/tilesets").join(file_name);
+        let mut contents = fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        // the first line of every snippet declares a symbol that is unique to the tileset
+        let declaration = snippet.lines().next().unwrap_or("");
+        if contents.contains(declaration) {
+            continue;
+        }
+        if !contents.is_empty() {
+            if !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+            contents.push('\n');
+        }
+        contents.push_str(snippet);
+        write_file(&path.to_string_lossy(), contents.as_bytes())?;
+        patched.push(path.display().to_string());
+    }
+    if patched.is_empty() {
+        Ok(format!("C snippets written to {}, gTileset_{} already exists in the decomp", output_folder, name))
+    } else {
+        Ok(format!("C snippets written to {} and added to {}", output_folder, patched.join(", ")))
+    }
+}
+
+fn write_file(path: &str, contents: &[u8]) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("failed to create {}: {}", path, e))?;
+    file.write_all(contents).map_err(|e| format!("failed to write {}: {}", path, e))
+}
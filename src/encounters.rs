@@ -0,0 +1,299 @@
+use std::ops::Range;
+
+use crate::constants::Constants;
+use crate::porymap::{ENCOUNTER_TYPES, EncounterField, EncounterGroup, EncounterTable, MapEncounters, WildMon};
+use crate::rom::Rom;
+
+/// struct WildPokemonHeader: the map, and a pointer to the table of every encounter type
+const HEADER_SIZE: usize = 20;
+const HEADER_TABLES: usize = 4;
+/// struct WildPokemonInfo: the encounter rate, and a pointer to the slots
+const INFO_SIZE: usize = 8;
+const INFO_MONS: usize = 4;
+/// struct WildPokemon: the min and max level, and the species
+const WILD_MON_SIZE: usize = 4;
+/// the headers end with MAP_UNDEFINED, which is 0x7F7F in Emerald and 0xFFFF in the other games
+const MAP_GROUP_UNDEFINED: [u8; 2] = [0x7f, 0xff];
+pub const MAX_LEVEL: i64 = 100;
+
+/// the slots of the fishing table every rod uses
+pub const FISHING_RODS: [(&str, Range<usize>); 3] = [("old_rod", 0..2), ("good_rod", 2..5), ("super_rod", 5..10)];
+
+/// the number of slots the games read from a table of an encounter type
+pub fn slot_count(kind: &str) -> Option<usize> {
+    match kind {
+        "land_mons" => Some(12),
+        "water_mons" | "rock_smash_mons" => Some(5),
+        "fishing_mons" => Some(10),
+        _ => None
+    }
+}
+
+/// the chance of every slot in percent, which is the same in every game
+fn default_rates(kind: &str) -> Vec<i64> {
+    match kind {
+        "land_mons" => vec![20, 20, 10, 10, 10, 10, 5, 5, 4, 4, 1, 1],
+        "fishing_mons" => vec![70, 30, 60, 20, 20, 40, 40, 15, 4, 1],
+        _ => vec![60, 30, 5, 4, 1],
+    }
+}
+
+/// the fields of wild_encounters.json for the tables of ROMs
+pub fn default_fields() -> Vec<EncounterField> {
+    ENCOUNTER_TYPES.iter().map(|kind| EncounterField {
+        kind: kind.to_string(),
+        encounter_rates: default_rates(kind),
+        groups: if *kind == "fishing_mons" {
+            FISHING_RODS.iter().map(|(rod, slots)| (rod.to_string(), slots.clone().map(|s| s as i64).collect())).collect()
+        } else {
+            Vec::new()
+        },
+        fields: Vec::new(),
+    }).collect()
+}
+
+/// accepts land as well as land_mons
+pub fn parse_type(name: &str) -> Result<&'static str, String> {
+    ENCOUNTER_TYPES.iter().cloned().find(|kind| *kind == name || kind.trim_end_matches("_mons") == name)
+        .ok_or(format!("encounter types are land, water, rock_smash or fishing, not {}", name))
+}
+
+/// where gWildMonHeaders is in the first release of English Emerald, FireRed and Ruby
+pub fn wild_mon_headers(rom: &Rom) -> Result<usize, String> {
+    rom.known_offset("gWildMonHeaders", "--table", &[("BPEE", 0, 0x552d48), ("BPRE", 0, 0x3c9cb8), ("AXVE", 0, 0x39d454)])
+}
+
+/// maps of ROMs are named <group>.<number>
+pub fn rom_map_name(map: u32) -> String {
+    format!("{}.{}", map >> 8, map & 0xff)
+}
+
+/// reads <group>.<number>, or a MAP_ constant of the decomp
+fn parse_map(name: &str, constants: &Constants) -> Option<u32> {
+    let numbers: Vec<u32> = name.split('.').filter_map(|n| n.parse::<u32>().ok()).collect();
+    match numbers.as_slice() {
+        [group, number] if *group <= 0xff && *number <= 0xff => Some(group << 8 | number),
+        _ => constants.get(name).filter(|map| *map <= 0xffff)
+    }
+}
+
+/// Lists the tables with the wrong number of slots, impossible levels and rates that don't fit in a byte
+pub fn check(group: &EncounterGroup) -> Vec<String> {
+    let mut problems = Vec::new();
+    for encounters in group.encounters.iter() {
+        let name = encounters.map.as_ref().unwrap_or(&encounters.base_label);
+        for (kind, table) in encounters.tables.iter() {
+            match slot_count(kind) {
+                Some(slots) if slots != table.mons.len() => {
+                    problems.push(format!("{} {}: {} slots instead of {}", name, kind, table.mons.len(), slots));
+                }
+                _ => {}
+            }
+            if table.encounter_rate < 0 || table.encounter_rate > 0xff {
+                problems.push(format!("{} {}: the encounter rate {} isn't between 0 and 255", name, kind, table.encounter_rate));
+            }
+            for (i, mon) in table.mons.iter().enumerate() {
+                if mon.min_level < 1 || mon.max_level > MAX_LEVEL {
+                    problems.push(format!("{} {} slot {}: levels {}-{} aren't between 1 and {}", name, kind, i, mon.min_level, mon.max_level, MAX_LEVEL));
+                } else if mon.min_level > mon.max_level {
+                    problems.push(format!("{} {} slot {}: the min level {} is above the max level {}", name, kind, i, mon.min_level, mon.max_level));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// The chance of every species of the tables of a map, with their levels
+pub fn summary(group: &EncounterGroup, encounters: &MapEncounters) -> String {
+    let mut summary = encounters.map.as_ref().unwrap_or(&encounters.base_label).clone();
+    for (kind, table) in encounters.tables.iter() {
+        let field = group.field(kind);
+        let rates = field.map(|f| f.encounter_rates.clone()).unwrap_or_else(|| default_rates(kind));
+        summary.push_str(&format!("\n  {} (rate {})", kind, table.encounter_rate));
+        // every rod picks from its own slots, the other types from all of them
+        let slot_groups: Vec<(String, Vec<usize>)> = match field {
+            Some(field) if !field.groups.is_empty() => field.groups.iter()
+                .map(|(name, slots)| (name.clone(), slots.iter().map(|&s| s as usize).collect()))
+                .collect(),
+            _ if *kind == "fishing_mons" => FISHING_RODS.iter().map(|(rod, slots)| (rod.to_string(), slots.clone().collect())).collect(),
+            _ => vec![(String::new(), (0..table.mons.len()).collect())],
+        };
+        for (name, slots) in slot_groups.iter() {
+            let total: i64 = slots.iter().filter(|&&s| s < table.mons.len()).filter_map(|&s| rates.get(s)).sum();
+            // species in the order of their first slot, with their chance and level range
+            let mut species: Vec<(&str, i64, i64, i64)> = Vec::new();
+            for &slot in slots.iter() {
+                let (mon, rate) = match (table.mons.get(slot), rates.get(slot)) {
+                    (Some(mon), Some(rate)) => (mon, *rate),
+                    _ => continue
+                };
+                match species.iter_mut().find(|(s, _, _, _)| *s == mon.species) {
+                    Some(entry) => {
+                        entry.1 += rate;
+                        entry.2 = entry.2.min(mon.min_level);
+                        entry.3 = entry.3.max(mon.max_level);
+                    }
+                    None => species.push((&mon.species, rate, mon.min_level, mon.max_level)),
+                }
+            }
+            let chances: Vec<String> = species.iter().map(|(species, rate, min, max)| {
+                let chance = if total > 0 { rate * 100 / total } else { 0 };
+                if min == max {
+                    format!("{} {}% lv. {}", species, chance, min)
+                } else {
+                    format!("{} {}% lv. {}-{}", species, chance, min, max)
+                }
+            }).collect();
+            if name.is_empty() {
+                summary.push_str(&format!(": {}", chances.join(", ")));
+            } else {
+                summary.push_str(&format!("\n    {}: {}", name, chances.join(", ")));
+            }
+        }
+    }
+    summary
+}
+
+/// the map and the offset of every header, up to MAP_UNDEFINED
+fn read_headers(rom: &Rom, table: usize) -> Result<Vec<(u32, usize)>, String> {
+    let mut headers = Vec::new();
+    let mut offset = table;
+    loop {
+        let (group, number) = match (rom.read_byte(offset), rom.read_byte(offset + 1)) {
+            (Some(group), Some(number)) => (group, number),
+            _ => return Err(format!("the wild mon headers at 0x{:X} don't end before the end of the ROM", table))
+        };
+        if MAP_GROUP_UNDEFINED.contains(&group) {
+            return Ok(headers);
+        }
+        headers.push(((group as u32) << 8 | number as u32, offset));
+        offset += HEADER_SIZE;
+    }
+}
+
+/// Reads the headers at `table` like the gWildMonHeaders of wild_encounters.json
+pub fn read_rom(rom: &Rom, table: usize) -> Result<EncounterGroup, String> {
+    let mut encounters = Vec::new();
+    for (map, header) in read_headers(rom, table)? {
+        let mut tables = Vec::new();
+        for (i, kind) in ENCOUNTER_TYPES.iter().enumerate() {
+            let info = match rom.read_pointer(header + HEADER_TABLES + i * 4) {
+                Some(info) => info,
+                None => continue
+            };
+            let mons = rom.read_pointer(info + INFO_MONS)
+                .ok_or(format!("the {} of map {} don't point to their slots", kind, rom_map_name(map)))?;
+            let slots = slot_count(kind).unwrap_or(0);
+            let bytes = rom.buffer.get(mons..mons + slots * WILD_MON_SIZE)
+                .ok_or(format!("the {} of map {} are outside of the ROM", kind, rom_map_name(map)))?;
+            tables.push((kind.to_string(), EncounterTable {
+                encounter_rate: rom.read_byte(info).unwrap_or(0) as i64,
+                mons: bytes.chunks(WILD_MON_SIZE).map(|mon| WildMon {
+                    min_level: mon[0] as i64,
+                    max_level: mon[1] as i64,
+                    species: (mon[2] as u32 | (mon[3] as u32) << 8).to_string(),
+                    fields: Vec::new(),
+                }).collect(),
+                fields: Vec::new(),
+            }));
+        }
+        encounters.push(MapEncounters {
+            map: Some(rom_map_name(map)),
+            base_label: format!("gWildMons_{}_{}", map >> 8, map & 0xff),
+            tables,
+            fields: Vec::new(),
+        });
+    }
+    Ok(EncounterGroup {
+        label: "gWildMonHeaders".to_string(),
+        for_maps: true,
+        encounter_fields: default_fields(),
+        encounters,
+        fields: Vec::new(),
+    })
+}
+
+/// Writes the tables of a group of headers to the ROM. Tables the ROM already has are
+/// overwritten, new tables go to free space found from `free_space`, and maps without a
+/// header get one in a copy of the headers that every pointer to the old ones is moved to.
+/// Returns a line for everything that was moved.
+pub fn write_rom(rom: &mut Rom, table: usize, group: &EncounterGroup, constants: &Constants, free_space: usize) -> Result<Vec<String>, String> {
+    let problems = check(group);
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+    let mut maps = Vec::new();
+    for encounters in group.encounters.iter() {
+        let name = encounters.map.as_ref().ok_or(format!("{} isn't the table of a map", encounters.base_label))?;
+        maps.push(parse_map(name, constants).ok_or(format!("unknown map {}, maps are <group>.<number> or MAP_ constants with --project", name))?);
+    }
+    let mut report = Vec::new();
+    let mut free_space = free_space;
+    let mut headers = read_headers(rom, table)?;
+    let new_maps: Vec<u32> = maps.iter().cloned().filter(|map| !headers.iter().any(|(m, _)| m == map)).collect();
+    if !new_maps.is_empty() {
+        // the old headers, the new ones and MAP_UNDEFINED
+        let end = table + headers.len() * HEADER_SIZE;
+        let mut bytes = rom.buffer[table..end].to_vec();
+        for map in new_maps.iter() {
+            let mut header = vec![0; HEADER_SIZE];
+            header[0] = (map >> 8) as u8;
+            header[1] = (map & 0xff) as u8;
+            bytes.append(&mut header);
+        }
+        bytes.extend_from_slice(rom.buffer.get(end..end + HEADER_SIZE).ok_or("the wild mon headers end at the end of the ROM")?);
+        let new_table = rom.find_free_space(free_space, bytes.len())
+            .ok_or(format!("no free space left for the 0x{:X} bytes of the wild mon headers", bytes.len()))?;
+        let pointers = rom.find_pointers(table);
+        rom.write_bytes(new_table, &bytes)?;
+        for pointer in pointers.iter() {
+            rom.write_pointer(*pointer, new_table)?;
+        }
+        free_space = new_table + bytes.len();
+        report.push(format!("the wild mon headers moved to 0x{:X} with {} new maps, {} pointers to them were updated",
+                            new_table, new_maps.len(), pointers.len()));
+        headers = read_headers(rom, new_table)?;
+    }
+
+    for (encounters, map) in group.encounters.iter().zip(maps.iter()) {
+        let header = headers.iter().find(|(m, _)| m == map).map(|(_, header)| *header).unwrap();
+        for (i, kind) in ENCOUNTER_TYPES.iter().enumerate() {
+            let pointer = header + HEADER_TABLES + i * 4;
+            let table = match encounters.table(kind) {
+                Some(table) => table,
+                None => {
+                    if rom.read_pointer(pointer).is_some() {
+                        rom.write_bytes(pointer, &[0; 4])?;
+                    }
+                    continue;
+                }
+            };
+            let mut mons = Vec::with_capacity(table.mons.len() * WILD_MON_SIZE);
+            for mon in table.mons.iter() {
+                let species = constants.get(&mon.species).filter(|species| *species <= 0xffff)
+                    .ok_or(format!("unknown species {}, species are numbers or SPECIES_ constants with --project", mon.species))?;
+                mons.extend_from_slice(&[mon.min_level as u8, mon.max_level as u8]);
+                mons.extend_from_slice(&(species as u16).to_le_bytes());
+            }
+            // the slot count of a type never changes, so existing tables are overwritten
+            let existing = rom.read_pointer(pointer).and_then(|info| rom.read_pointer(info + INFO_MONS).map(|mons| (info, mons)));
+            let (info, slots) = match existing {
+                Some(existing) => existing,
+                None => {
+                    let info = rom.find_free_space(free_space, INFO_SIZE + mons.len())
+                        .ok_or(format!("no free space left for the {} of map {}", kind, rom_map_name(*map)))?;
+                    rom.write_bytes(info, &[0; INFO_SIZE])?;
+                    rom.write_pointer(info + INFO_MONS, info + INFO_SIZE)?;
+                    rom.write_pointer(pointer, info)?;
+                    free_space = info + INFO_SIZE + mons.len();
+                    report.push(format!("the {} of map {} were written at 0x{:X}", kind, rom_map_name(*map), info));
+                    (info, info + INFO_SIZE)
+                }
+            };
+            rom.write_bytes(info, &[table.encounter_rate as u8])?;
+            rom.write_bytes(slots, &mons)?;
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,580 @@
+use crate::constants::parse_number;
+use crate::decomp::Game;
+use crate::json::{Json, merge_object};
+use crate::porymap::Map;
+use crate::rom::Rom;
+
+/// where the events pointer is in a map header
+const HEADER_EVENTS: usize = 4;
+/// the sizes of the templates the game reads the events from
+const OBJECT_EVENT_SIZE: usize = 24;
+const WARP_EVENT_SIZE: usize = 8;
+const COORD_EVENT_SIZE: usize = 16;
+const BG_EVENT_SIZE: usize = 12;
+/// bg event kinds after the player facing directions of signs
+const BG_EVENT_HIDDEN_ITEM: u32 = 7;
+const BG_EVENT_SECRET_BASE: u32 = 8;
+/// the object kind of FireRed objects that copy an object of a connected map
+const OBJ_KIND_CLONE: u8 = 0xff;
+/// fields that are integers or booleans in a map.json, everything else is a string
+const INTEGER_FIELDS: [&str; 3] = ["movement_range_x", "movement_range_y", "quantity"];
+const BOOL_FIELDS: [&str; 1] = ["underfoot"];
+
+/// The event lists of a map, in the order of the map header
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EventGroup {
+    Object,
+    Warp,
+    Coord,
+    Bg,
+}
+
+impl EventGroup {
+    pub const ALL: [EventGroup; 4] = [EventGroup::Object, EventGroup::Warp, EventGroup::Coord, EventGroup::Bg];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventGroup::Object => "object",
+            EventGroup::Warp => "warp",
+            EventGroup::Coord => "coord",
+            EventGroup::Bg => "bg",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<EventGroup, String> {
+        EventGroup::ALL.iter().cloned().find(|group| group.name() == name)
+            .ok_or(format!("events are object, warp, coord or bg events, not {}", name))
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            EventGroup::Object => OBJECT_EVENT_SIZE,
+            EventGroup::Warp => WARP_EVENT_SIZE,
+            EventGroup::Coord => COORD_EVENT_SIZE,
+            EventGroup::Bg => BG_EVENT_SIZE,
+        }
+    }
+}
+
+/// What an event does, with the constants and labels of a map.json or the numbers of a ROM
+#[derive(Clone, Debug)]
+pub enum EventData {
+    Object {
+        graphics_id: String,
+        movement_type: String,
+        movement_range_x: String,
+        movement_range_y: String,
+        trainer_type: String,
+        trainer_sight_or_berry_tree_id: String,
+        script: String,
+        flag: String,
+    },
+    /// an object of a connected map, only in map.json files
+    Clone { graphics_id: String, target_local_id: String, target_map: String },
+    Warp { dest_map: String, dest_warp_id: String },
+    Trigger { var: String, var_value: String, script: String },
+    Weather { weather: String },
+    Sign { player_facing_dir: String, script: String },
+    /// FireRed hidden items also have a quantity and can be underfoot
+    HiddenItem { item: String, flag: String, quantity: Option<String>, underfoot: Option<String> },
+    SecretBase { secret_base_id: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub x: i64,
+    pub y: i64,
+    pub elevation: i64,
+    pub data: EventData,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl Event {
+    /// Creates an event of a type, like the "type" of coord and bg events, with every field 0
+    pub fn new(event_type: &str, x: i64, y: i64) -> Result<Event, String> {
+        let zero = || "0".to_string();
+        let data = match event_type {
+            "object" => EventData::Object {
+                graphics_id: zero(),
+                movement_type: zero(),
+                movement_range_x: zero(),
+                movement_range_y: zero(),
+                trainer_type: zero(),
+                trainer_sight_or_berry_tree_id: zero(),
+                script: zero(),
+                flag: zero(),
+            },
+            "clone" => EventData::Clone { graphics_id: zero(), target_local_id: zero(), target_map: zero() },
+            "warp" => EventData::Warp { dest_map: zero(), dest_warp_id: zero() },
+            "trigger" => EventData::Trigger { var: zero(), var_value: zero(), script: zero() },
+            "weather" => EventData::Weather { weather: zero() },
+            "sign" => EventData::Sign { player_facing_dir: zero(), script: zero() },
+            "hidden_item" => EventData::HiddenItem { item: zero(), flag: zero(), quantity: None, underfoot: None },
+            "secret_base" => EventData::SecretBase { secret_base_id: zero() },
+            _ => return Err(format!("unknown event type {}, the types are object, clone, warp, trigger, weather, sign, hidden_item and secret_base", event_type))
+        };
+        Ok(Event { x, y, elevation: 0, data, fields: Vec::new() })
+    }
+
+    pub fn event_type(&self) -> &'static str {
+        match self.data {
+            EventData::Object { .. } => "object",
+            EventData::Clone { .. } => "clone",
+            EventData::Warp { .. } => "warp",
+            EventData::Trigger { .. } => "trigger",
+            EventData::Weather { .. } => "weather",
+            EventData::Sign { .. } => "sign",
+            EventData::HiddenItem { .. } => "hidden_item",
+            EventData::SecretBase { .. } => "secret_base",
+        }
+    }
+
+    pub fn group(&self) -> EventGroup {
+        match self.data {
+            EventData::Object { .. } | EventData::Clone { .. } => EventGroup::Object,
+            EventData::Warp { .. } => EventGroup::Warp,
+            EventData::Trigger { .. } | EventData::Weather { .. } => EventGroup::Coord,
+            _ => EventGroup::Bg,
+        }
+    }
+
+    /// the fields of the event by their name in map.json files
+    fn properties_mut(&mut self) -> Vec<(&'static str, &mut String)> {
+        match &mut self.data {
+            EventData::Object { graphics_id, movement_type, movement_range_x, movement_range_y, trainer_type, trainer_sight_or_berry_tree_id, script, flag } => vec![
+                ("graphics_id", graphics_id),
+                ("movement_type", movement_type),
+                ("movement_range_x", movement_range_x),
+                ("movement_range_y", movement_range_y),
+                ("trainer_type", trainer_type),
+                ("trainer_sight_or_berry_tree_id", trainer_sight_or_berry_tree_id),
+                ("script", script),
+                ("flag", flag),
+            ],
+            EventData::Clone { graphics_id, target_local_id, target_map } => vec![
+                ("graphics_id", graphics_id),
+                ("target_local_id", target_local_id),
+                ("target_map", target_map),
+            ],
+            EventData::Warp { dest_map, dest_warp_id } => vec![("dest_map", dest_map), ("dest_warp_id", dest_warp_id)],
+            EventData::Trigger { var, var_value, script } => vec![("var", var), ("var_value", var_value), ("script", script)],
+            EventData::Weather { weather } => vec![("weather", weather)],
+            EventData::Sign { player_facing_dir, script } => vec![("player_facing_dir", player_facing_dir), ("script", script)],
+            EventData::HiddenItem { item, flag, quantity, underfoot } => {
+                let mut properties = vec![("item", item), ("flag", flag)];
+                if let Some(quantity) = quantity {
+                    properties.push(("quantity", quantity));
+                }
+                if let Some(underfoot) = underfoot {
+                    properties.push(("underfoot", underfoot));
+                }
+                properties
+            }
+            EventData::SecretBase { secret_base_id } => vec![("secret_base_id", secret_base_id)],
+        }
+    }
+
+    /// Gives new hidden items of FireRed the quantity and underfoot fields its macro has defaults for
+    pub fn add_game_fields(&mut self, game: Game) {
+        if let (Game::FireRed, EventData::HiddenItem { quantity, underfoot, .. }) = (game, &mut self.data) {
+            quantity.get_or_insert("1".to_string());
+            underfoot.get_or_insert("false".to_string());
+        }
+    }
+
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        self.clone().properties_mut().into_iter().map(|(key, value)| (key, value.clone())).collect()
+    }
+
+    /// Changes a field by its name in map.json files, like script or elevation
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if key == "elevation" {
+            self.elevation = value.parse::<i64>().map_err(|_| format!("invalid elevation: {}", value))?;
+            return Ok(());
+        }
+        let event_type = self.event_type();
+        match self.properties_mut().into_iter().find(|(k, _)| *k == key) {
+            Some((_, field)) => {
+                *field = value.to_string();
+                Ok(())
+            }
+            None => Err(format!("{} events don't have a {} field", event_type, key))
+        }
+    }
+
+    pub fn from_json(group: EventGroup, json: &Json) -> Result<Event, String> {
+        let event_type = match (group, json.get("type").and_then(|t| t.as_str())) {
+            (EventGroup::Object, Some("clone")) => "clone",
+            (EventGroup::Object, _) => "object",
+            (EventGroup::Warp, _) => "warp",
+            (_, Some(event_type)) => event_type,
+            (_, None) => return Err(format!("{} events need a type", group.name())),
+        };
+        let mut event = Event::new(event_type, json.integer_field("x")?, json.integer_field("y")?)?;
+        if event.group() != group {
+            return Err(format!("{} events can't be {} events", event_type, group.name()));
+        }
+        // clones take their elevation from the object they copy
+        event.elevation = json.get("elevation").and_then(|e| e.as_i64()).unwrap_or(0);
+        if let EventData::HiddenItem { quantity, underfoot, .. } = &mut event.data {
+            *quantity = json.get("quantity").map(|_| String::new());
+            *underfoot = json.get("underfoot").map(|_| String::new());
+        }
+        for (key, field) in event.properties_mut() {
+            *field = match json.get(key) {
+                Some(Json::String(value)) => value.clone(),
+                Some(Json::Integer(value)) => value.to_string(),
+                Some(Json::Bool(value)) => value.to_string(),
+                _ => return Err(format!("missing field \"{}\" of a {} event", key, event_type))
+            };
+        }
+        event.fields = json.as_object().cloned().unwrap_or_default();
+        Ok(event)
+    }
+
+    pub fn to_json(&self) -> Json {
+        // values keep the type they had, new ones are strings unless they are always integers or booleans
+        let value = |key: &str, value: &str| {
+            let (was_integer, was_bool) = match self.fields.iter().find(|(k, _)| k == key) {
+                Some((_, Json::Bool(_))) => (false, true),
+                Some((_, json)) => (json.as_i64().is_some(), false),
+                None => (INTEGER_FIELDS.contains(&key), BOOL_FIELDS.contains(&key)),
+            };
+            match (value.parse::<i64>(), value) {
+                (Ok(integer), _) if was_integer => Json::Integer(integer),
+                (_, "true") | (_, "false") if was_bool => Json::Bool(value == "true"),
+                _ => Json::String(value.to_string()),
+            }
+        };
+        let event_type = match self.group() {
+            // object events only have a type in newer decomps
+            EventGroup::Object if self.event_type() == "object" && !self.fields.iter().any(|(k, _)| k == "type") => None,
+            EventGroup::Warp => None,
+            _ => Some(Json::String(self.event_type().to_string())),
+        };
+        let mut fields = vec![
+            ("type", event_type),
+            ("x", Some(Json::Integer(self.x))),
+            ("y", Some(Json::Integer(self.y))),
+        ];
+        if self.event_type() != "clone" {
+            fields.push(("elevation", Some(Json::Integer(self.elevation))));
+        }
+        let properties = self.properties();
+        for (key, property) in properties.iter() {
+            fields.push((key, Some(value(key, property))));
+        }
+        merge_object(&self.fields, fields)
+    }
+
+    pub fn describe(&self) -> String {
+        let properties: Vec<String> = self.properties().iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        format!("{} at {},{} elevation {}: {}", self.event_type(), self.x, self.y, self.elevation, properties.join(" "))
+    }
+}
+
+/// The events of a map, from a map.json or from the event tables of a ROM
+pub struct MapEvents {
+    pub objects: Vec<Event>,
+    pub warps: Vec<Event>,
+    pub coords: Vec<Event>,
+    pub bgs: Vec<Event>,
+}
+
+impl MapEvents {
+    pub fn get(&self, group: EventGroup) -> &Vec<Event> {
+        match group {
+            EventGroup::Object => &self.objects,
+            EventGroup::Warp => &self.warps,
+            EventGroup::Coord => &self.coords,
+            EventGroup::Bg => &self.bgs,
+        }
+    }
+
+    pub fn get_mut(&mut self, group: EventGroup) -> &mut Vec<Event> {
+        match group {
+            EventGroup::Object => &mut self.objects,
+            EventGroup::Warp => &mut self.warps,
+            EventGroup::Coord => &mut self.coords,
+            EventGroup::Bg => &mut self.bgs,
+        }
+    }
+
+    pub fn from_map(map: &Map) -> Result<MapEvents, String> {
+        let events = |group: EventGroup, json: &Vec<Json>| -> Result<Vec<Event>, String> {
+            json.iter().enumerate()
+                .map(|(i, event)| Event::from_json(group, event).map_err(|e| format!("{} event {} of {}: {}", group.name(), i, map.name, e)))
+                .collect()
+        };
+        Ok(MapEvents {
+            objects: events(EventGroup::Object, &map.object_events)?,
+            warps: events(EventGroup::Warp, &map.warp_events)?,
+            coords: events(EventGroup::Coord, &map.coord_events)?,
+            bgs: events(EventGroup::Bg, &map.bg_events)?,
+        })
+    }
+
+    pub fn apply(&self, map: &mut Map) {
+        let json = |events: &Vec<Event>| events.iter().map(|e| e.to_json()).collect();
+        map.object_events = json(&self.objects);
+        map.warp_events = json(&self.warps);
+        map.coord_events = json(&self.coords);
+        map.bg_events = json(&self.bgs);
+    }
+
+    /// Reads the events of the map header at `header`
+    pub fn from_rom(rom: &Rom, header: usize, game: Game) -> Result<MapEvents, String> {
+        let events = rom.read_pointer(header + HEADER_EVENTS)
+            .ok_or(format!("the map header at 0x{:X} doesn't point to its events", header))?;
+        let mut map_events = MapEvents { objects: Vec::new(), warps: Vec::new(), coords: Vec::new(), bgs: Vec::new() };
+        for (i, group) in EventGroup::ALL.iter().enumerate() {
+            let count = rom.read_byte(events + i).unwrap_or(0) as usize;
+            if count == 0 {
+                continue;
+            }
+            let table = rom.read_pointer(events + 4 + i * 4)
+                .ok_or(format!("the {} events of the map at 0x{:X} aren't in the ROM", group.name(), header))?;
+            for j in 0..count {
+                let offset = table + j * group.size();
+                let bytes = rom.buffer.get(offset..offset + group.size())
+                    .ok_or(format!("{} event {} at 0x{:X} is outside of the ROM", group.name(), j, offset))?;
+                map_events.get_mut(*group).push(decode_event(*group, bytes, game));
+            }
+        }
+        Ok(map_events)
+    }
+
+    /// Writes the event tables back to the ROM. Tables that grew are moved to free space
+    /// found from `free_space`. Returns a line for every table that was moved.
+    pub fn write_to_rom(&self, rom: &mut Rom, header: usize, free_space: usize, game: Game) -> Result<Vec<String>, String> {
+        let events = rom.read_pointer(header + HEADER_EVENTS)
+            .ok_or(format!("the map header at 0x{:X} doesn't point to its events", header))?;
+        let mut moved = Vec::new();
+        let mut free_space = free_space;
+        for (i, group) in EventGroup::ALL.iter().enumerate() {
+            let list = self.get(*group);
+            if list.len() > 0xff {
+                return Err(format!("a map can only have 255 {} events", group.name()));
+            }
+            let mut bytes = Vec::with_capacity(list.len() * group.size());
+            for (j, event) in list.iter().enumerate() {
+                bytes.append(&mut encode_event(event, j, game).map_err(|e| format!("{} event {}: {}", group.name(), j, e))?);
+            }
+            let old_count = rom.read_byte(events + i).unwrap_or(0) as usize;
+            let table = match rom.read_pointer(events + 4 + i * 4) {
+                Some(table) if list.len() <= old_count => table,
+                _ if list.is_empty() => continue,
+                _ => {
+                    let table = rom.find_free_space(free_space, bytes.len())
+                        .ok_or(format!("no free space left for the {} events", group.name()))?;
+                    rom.write_pointer(events + 4 + i * 4, table)?;
+                    free_space = table + bytes.len();
+                    moved.push(format!("the {} events moved to 0x{:X}", group.name(), table));
+                    table
+                }
+            };
+            rom.write_bytes(table, &bytes)?;
+            rom.write_bytes(events + i, &[list.len() as u8])?;
+        }
+        Ok(moved)
+    }
+}
+
+/// where gMapGroups is in the first release of English Emerald, FireRed and Ruby
+pub fn map_groups_table(rom: &Rom) -> Result<usize, String> {
+    rom.known_offset("gMapGroups", "--map-table", &[("BPEE", 0, 0x486578), ("BPRE", 0, 0x3526a8), ("AXVE", 0, 0x308588)])
+}
+
+/// FLAG_HIDDEN_ITEMS_START, hidden items store their flag as an offset from it
+fn hidden_items_flag_start(game: Game) -> u32 {
+    match game {
+        Game::FireRed => 0x3e8,
+        Game::Emerald | Game::Ruby => 0x1f4,
+    }
+}
+
+/// Finds the header of a map from the table of map groups
+pub fn find_map_header(rom: &Rom, table: usize, group: usize, number: usize) -> Result<usize, String> {
+    let maps = rom.read_pointer(table + group * 4)
+        .ok_or(format!("map group {} isn't in the map group table at 0x{:X}", group, table))?;
+    rom.read_pointer(maps + number * 4)
+        .ok_or(format!("map {}.{} isn't in the ROM", group, number))
+}
+
+fn number(value: u32) -> String {
+    if value < 10 { value.to_string() } else { format!("0x{:X}", value) }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes[offset] as u32 | (bytes[offset + 1] as u32) << 8
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u16_at(bytes, offset) | u16_at(bytes, offset + 2) << 16
+}
+
+/// coordinates are signed so objects can start outside of the map
+fn i16_at(bytes: &[u8], offset: usize) -> i64 {
+    u16_at(bytes, offset) as u16 as i16 as i64
+}
+
+fn decode_event(group: EventGroup, bytes: &[u8], game: Game) -> Event {
+    let data = match group {
+        EventGroup::Object if bytes[2] == OBJ_KIND_CLONE => EventData::Clone {
+            graphics_id: number(bytes[1] as u32),
+            target_local_id: number(bytes[8] as u32),
+            target_map: number(u16_at(bytes, 14) << 8 | u16_at(bytes, 12)),
+        },
+        EventGroup::Object => EventData::Object {
+            graphics_id: number(bytes[1] as u32),
+            movement_type: number(bytes[9] as u32),
+            movement_range_x: number(bytes[10] as u32 & 0xf),
+            movement_range_y: number(bytes[10] as u32 >> 4),
+            trainer_type: number(u16_at(bytes, 12)),
+            trainer_sight_or_berry_tree_id: number(u16_at(bytes, 14)),
+            script: number(u32_at(bytes, 16)),
+            flag: number(u16_at(bytes, 20)),
+        },
+        // maps are written like the map constants of the decomps, with the group in the high byte
+        EventGroup::Warp => EventData::Warp { dest_map: number((bytes[7] as u32) << 8 | bytes[6] as u32), dest_warp_id: number(bytes[5] as u32) },
+        // weather triggers don't have a script
+        EventGroup::Coord if u32_at(bytes, 12) == 0 => EventData::Weather { weather: number(u16_at(bytes, 6)) },
+        EventGroup::Coord => EventData::Trigger { var: number(u16_at(bytes, 6)), var_value: number(u16_at(bytes, 8)), script: number(u32_at(bytes, 12)) },
+        EventGroup::Bg => match bytes[5] as u32 {
+            // FireRed packs the quantity and underfoot into the upper byte of the flag
+            BG_EVENT_HIDDEN_ITEM if game == Game::FireRed => EventData::HiddenItem {
+                item: number(u16_at(bytes, 8)),
+                flag: number(bytes[10] as u32 + hidden_items_flag_start(game)),
+                quantity: Some(number(bytes[11] as u32 & 0x7f)),
+                underfoot: Some(number(bytes[11] as u32 >> 7)),
+            },
+            BG_EVENT_HIDDEN_ITEM => EventData::HiddenItem {
+                item: number(u16_at(bytes, 8)),
+                flag: number(u16_at(bytes, 10) + hidden_items_flag_start(game)),
+                quantity: None,
+                underfoot: None,
+            },
+            BG_EVENT_SECRET_BASE => EventData::SecretBase { secret_base_id: number(u32_at(bytes, 8)) },
+            kind => EventData::Sign { player_facing_dir: number(kind), script: number(u32_at(bytes, 8)) },
+        },
+    };
+    // objects start with their local id, graphics and kind, and clones don't have an elevation
+    let (position, elevation) = match group {
+        EventGroup::Object if bytes[2] == OBJ_KIND_CLONE => (4, 0),
+        EventGroup::Object => (4, bytes[8]),
+        _ => (0, bytes[4]),
+    };
+    Event { x: i16_at(bytes, position), y: i16_at(bytes, position + 2), elevation: elevation as i64, data, fields: Vec::new() }
+}
+
+/// Encodes an event like the macros of the decomps. Every field has to be a number,
+/// since the names of the constants are only known to the decomps.
+fn encode_event(event: &Event, index: usize, game: Game) -> Result<Vec<u8>, String> {
+    let value = |name: &str, text: &str, max: u32| -> Result<u32, String> {
+        let value = parse_number(text).ok_or(format!("{} must be a number to write it to a ROM, not {}", name, text))?;
+        if value > max {
+            return Err(format!("{} doesn't fit in its field: {}", name, text));
+        }
+        Ok(value)
+    };
+    let coordinate = |name: &str, coordinate: i64| -> Result<[u8; 2], String> {
+        if coordinate < i16::min_value() as i64 || coordinate > i16::max_value() as i64 {
+            return Err(format!("{} is too far out: {}", name, coordinate));
+        }
+        Ok((coordinate as i16).to_le_bytes())
+    };
+    let mut bytes = Vec::new();
+    let elevation = value("elevation", &event.elevation.to_string(), 0xf)? as u8;
+    match &event.data {
+        EventData::Object { graphics_id, movement_type, movement_range_x, movement_range_y, trainer_type, trainer_sight_or_berry_tree_id, script, flag } => {
+            // local ids start at 1
+            bytes.push((index + 1) as u8);
+            bytes.push(value("graphics_id", graphics_id, 0xff)? as u8);
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(&coordinate("x", event.x)?);
+            bytes.extend_from_slice(&coordinate("y", event.y)?);
+            bytes.push(elevation);
+            bytes.push(value("movement_type", movement_type, 0xff)? as u8);
+            let range = value("movement_range_x", movement_range_x, 0xf)? | value("movement_range_y", movement_range_y, 0xf)? << 4;
+            bytes.extend_from_slice(&(range as u16).to_le_bytes());
+            bytes.extend_from_slice(&(value("trainer_type", trainer_type, 0xffff)? as u16).to_le_bytes());
+            bytes.extend_from_slice(&(value("trainer_sight_or_berry_tree_id", trainer_sight_or_berry_tree_id, 0xffff)? as u16).to_le_bytes());
+            bytes.extend_from_slice(&value("script", script, u32::max_value())?.to_le_bytes());
+            bytes.extend_from_slice(&(value("flag", flag, 0xffff)? as u16).to_le_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        EventData::Clone { graphics_id, target_local_id, target_map } => {
+            if game != Game::FireRed {
+                return Err(format!("clone object events only exist in FireRed, not in {}", game.name()));
+            }
+            bytes.push((index + 1) as u8);
+            bytes.push(value("graphics_id", graphics_id, 0xff)? as u8);
+            bytes.extend_from_slice(&[OBJ_KIND_CLONE, 0]);
+            bytes.extend_from_slice(&coordinate("x", event.x)?);
+            bytes.extend_from_slice(&coordinate("y", event.y)?);
+            bytes.push(value("target_local_id", target_local_id, 0xff)? as u8);
+            bytes.extend_from_slice(&[0, 0, 0]);
+            // maps are written with the group in the high byte, the game has the number first
+            let map = value("target_map", target_map, 0xffff)?;
+            bytes.extend_from_slice(&((map & 0xff) as u16).to_le_bytes());
+            bytes.extend_from_slice(&((map >> 8) as u16).to_le_bytes());
+            bytes.extend_from_slice(&[0; 8]);
+        }
+        EventData::Warp { dest_map, dest_warp_id } => {
+            bytes.extend_from_slice(&coordinate("x", event.x)?);
+            bytes.extend_from_slice(&coordinate("y", event.y)?);
+            bytes.push(elevation);
+            bytes.push(value("dest_warp_id", dest_warp_id, 0xff)? as u8);
+            let map = value("dest_map", dest_map, 0xffff)?;
+            bytes.push((map & 0xff) as u8);
+            bytes.push((map >> 8) as u8);
+        }
+        EventData::Trigger { .. } | EventData::Weather { .. } => {
+            let (var, var_value, weather, script) = match &event.data {
+                EventData::Trigger { var, var_value, script } => (value("var", var, 0xffff)?, value("var_value", var_value, 0xffff)?, 0, value("script", script, u32::max_value())?),
+                EventData::Weather { weather } => (0, 0, value("weather", weather, 0xffff)?, 0),
+                _ => unreachable!()
+            };
+            bytes.extend_from_slice(&coordinate("x", event.x)?);
+            bytes.extend_from_slice(&coordinate("y", event.y)?);
+            bytes.extend_from_slice(&[elevation, 0]);
+            bytes.extend_from_slice(&((var | weather) as u16).to_le_bytes());
+            bytes.extend_from_slice(&(var_value as u16).to_le_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(&script.to_le_bytes());
+        }
+        EventData::Sign { .. } | EventData::HiddenItem { .. } | EventData::SecretBase { .. } => {
+            let (kind, argument) = match &event.data {
+                EventData::Sign { player_facing_dir, script } => (value("player_facing_dir", player_facing_dir, BG_EVENT_HIDDEN_ITEM - 1)?, value("script", script, u32::max_value())?),
+                EventData::HiddenItem { item, flag, quantity, underfoot } => {
+                    let flag = value("flag", flag, 0xffff)?;
+                    let start = hidden_items_flag_start(game);
+                    let max_id = if game == Game::FireRed { 0xff } else { 0xffff };
+                    if flag < start || flag - start > max_id {
+                        return Err(format!("flag 0x{:X} isn't a hidden item flag of {}, those start at 0x{:X}", flag, game.name(), start));
+                    }
+                    let mut argument = value("item", item, 0xffff)? | (flag - start) << 16;
+                    if game == Game::FireRed {
+                        // the defaults of the bg_hidden_item_event macro
+                        let underfoot = match underfoot.as_ref().map(|u| u.as_str()) {
+                            None | Some("false") | Some("FALSE") => 0,
+                            Some("true") | Some("TRUE") => 1,
+                            Some(underfoot) => value("underfoot", underfoot, 1)?,
+                        };
+                        argument |= value("quantity", quantity.as_ref().map(|q| q.as_str()).unwrap_or("1"), 0x7f)? << 24 | underfoot << 31;
+                    } else if quantity.is_some() || underfoot.is_some() {
+                        return Err(format!("only FireRed hidden items have a quantity and underfoot, not the ones of {}", game.name()));
+                    }
+                    (BG_EVENT_HIDDEN_ITEM, argument)
+                }
+                EventData::SecretBase { secret_base_id } => (BG_EVENT_SECRET_BASE, value("secret_base_id", secret_base_id, u32::max_value())?),
+                _ => unreachable!()
+            };
+            bytes.extend_from_slice(&coordinate("x", event.x)?);
+            bytes.extend_from_slice(&coordinate("y", event.y)?);
+            bytes.extend_from_slice(&[elevation, kind as u8, 0, 0]);
+            bytes.extend_from_slice(&argument.to_le_bytes());
+        }
+    }
+    Ok(bytes)
+}
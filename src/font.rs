@@ -0,0 +1,225 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::charmap;
+use crate::constants::{mask_comments, parse_number};
+use crate::rom::Rom;
+use crate::sprite::Sprite;
+use crate::tileset::{Tile, TILE_SIZE};
+
+/// one width for every character of the font
+pub const GLYPH_COUNT: usize = 0x100;
+/// glyph sheets have 16 glyphs in every row, like the ones in graphics/fonts
+pub const GLYPHS_PER_ROW: usize = 16;
+/// the background, the text and its shadow, and the rest of the colors of 4bpp fonts in gray
+const FONT_PALETTE: [[u8; 3]; 16] = [
+    [255, 255, 255], [56, 56, 56], [216, 216, 216], [0, 0, 0],
+    [17, 17, 17], [34, 34, 34], [51, 51, 51], [68, 68, 68],
+    [85, 85, 85], [102, 102, 102], [119, 119, 119], [136, 136, 136],
+    [153, 153, 153], [170, 170, 170], [187, 187, 187], [204, 204, 204],
+];
+/// placeholders like {PLAYER} are measured as a name of this many of the widest character
+const PLACEHOLDER_LENGTH: usize = 7;
+
+/// The width in pixels of every character of a font, indexed by the encoded character
+pub struct FontWidths {
+    pub widths: Vec<u8>,
+}
+
+impl FontWidths {
+    pub fn from_bytes(bytes: &[u8]) -> Result<FontWidths, String> {
+        if bytes.len() < GLYPH_COUNT {
+            return Err(format!("a width table needs {} widths, but there are only {}", GLYPH_COUNT, bytes.len()));
+        }
+        Ok(FontWidths { widths: bytes[..GLYPH_COUNT].to_vec() })
+    }
+
+    /// Reads a width table like the ones in the decomp's graphics/fonts
+    pub fn load(path: &Path) -> Result<FontWidths, String> {
+        let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        FontWidths::from_bytes(&bytes).map_err(|e| format!("invalid width table {}: {}", path.display(), e))
+    }
+
+    pub fn from_rom(rom: &Rom, offset: usize) -> Result<FontWidths, String> {
+        let bytes = rom.buffer.get(offset..offset + GLYPH_COUNT)
+            .ok_or(format!("the width table at 0x{:X} is outside of the ROM", offset))?;
+        FontWidths::from_bytes(bytes)
+    }
+
+    /// Reads the width table of a font from a decomp, from the arrays of src/fonts.c like
+    /// gFontNormalLatinGlyphWidths, or from a .bin file in graphics/fonts
+    pub fn from_project(root: &Path, font: &str) -> Result<FontWidths, String> {
+        let path = root.join("src/fonts.c");
+        if !path.exists() {
+            return FontWidths::load(&FontWidths::find(root, font)?);
+        }
+        let source = mask_comments(&fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?);
+        let wanted = font.replace('_', "").to_lowercase();
+        let mut names = Vec::new();
+        for (start, _) in source.match_indices("LatinGlyphWidths[]") {
+            let name_start = source[..start].rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_').map(|i| i + 1).unwrap_or(0);
+            let name = &source[name_start..start + "LatinGlyphWidths".len()];
+            let font_name = name.trim_start_matches(|c| c == 'g' || c == 's').trim_start_matches("Font").trim_end_matches("LatinGlyphWidths");
+            names.push(name.to_string());
+            if font_name.to_lowercase() != wanted {
+                continue;
+            }
+            let body = source[start..].find('{').and_then(|open| source[start + open..].find('}').map(|close| &source[start + open + 1..start + open + close]))
+                .ok_or(format!("{} in {} has no initializer", name, path.display()))?;
+            let widths = body.split(',').map(|w| w.trim()).filter(|w| !w.is_empty())
+                .map(|w| parse_number(w).filter(|&w| w < 0x100).map(|w| w as u8).ok_or(format!("invalid width in {}: {}", name, w)))
+                .collect::<Result<Vec<u8>, String>>()?;
+            return FontWidths::from_bytes(&widths).map_err(|e| format!("invalid width table {} in {}: {}", name, path.display(), e));
+        }
+        Err(format!("couldn't find the width table of the {} font in {}, it has {}", font, path.display(), names.join(", ")))
+    }
+
+    /// Finds the width table of a font in graphics/fonts of a decomp, like latin_normal.width.bin
+    pub fn find(root: &Path, font: &str) -> Result<PathBuf, String> {
+        let folder = root.join("graphics/fonts");
+        let mut candidates = Vec::new();
+        let mut folders = vec![folder.clone()];
+        while let Some(folder) = folders.pop() {
+            for entry in fs::read_dir(&folder).map_err(|e| format!("failed to read {}: {}", folder.display(), e))? {
+                let path = entry.map_err(|e| e.to_string())?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+                if path.is_dir() {
+                    folders.push(path);
+                } else if name.ends_with(".bin") && name.contains(font) && (name.contains("width") || path.parent().map(|p| p.ends_with("widths")).unwrap_or(false)) {
+                    candidates.push(path);
+                }
+            }
+        }
+        candidates.sort();
+        match candidates.len() {
+            0 => Err(format!("couldn't find the width table of the {} font in {}", font, folder.display())),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(format!("the width table of the {} font is ambiguous: {}", font,
+                             candidates.iter().map(|c| c.display().to_string()).collect::<Vec<String>>().join(", ")))
+        }
+    }
+
+    /// Replaces the widths of the glyphs that were measured, and keeps the
+    /// others, so blank glyphs like the space keep the width they had
+    pub fn update(&mut self, measured: &[Option<u8>]) -> usize {
+        let mut changed = 0;
+        for (width, measured) in self.widths.iter_mut().zip(measured.iter()) {
+            if let Some(measured) = measured {
+                if width != measured {
+                    *width = *measured;
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Measures every line of an encoded string, split at \n, \l and \p
+    pub fn measure_lines(&self, bytes: &[u8]) -> Vec<usize> {
+        let widest = self.widths.iter().cloned().max().unwrap_or(0) as usize;
+        let mut lines = vec![0];
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                charmap::END => break,
+                0xfa | 0xfb | 0xfe => lines.push(0),
+                0xfc => {
+                    // control codes don't take up space
+                    i += 1 + bytes.get(i + 1).map(|&code| 1 + charmap::ext_ctrl_code_args(code)).unwrap_or(0);
+                    continue;
+                }
+                0xfd => {
+                    *lines.last_mut().unwrap() += widest * PLACEHOLDER_LENGTH;
+                    i += 2;
+                    continue;
+                }
+                byte => *lines.last_mut().unwrap() += self.widths[byte as usize] as usize,
+            }
+            i += 1;
+        }
+        lines
+    }
+}
+
+/// The glyphs of a font, made of 8x8 tiles like sprite frames, so an 8x16 glyph is
+/// the top and the bottom tile, and a 16x16 glyph is four tiles row by row
+pub struct Glyphs {
+    pub cells: Sprite,
+    /// bits per pixel of the font data, 2 or 4
+    pub bpp: usize,
+}
+
+impl Glyphs {
+    /// parses a glyph size, 8x8, 8x16 or 16x16
+    pub fn parse_cell(size: &str) -> Result<(usize, usize), String> {
+        match size {
+            "8x8" => Ok((8, 8)),
+            "8x16" => Ok((8, 16)),
+            "16x16" => Ok((16, 16)),
+            _ => Err(format!("glyphs must be 8x8, 8x16 or 16x16: {}", size))
+        }
+    }
+
+    fn tile_size(bpp: usize) -> usize {
+        TILE_SIZE * TILE_SIZE * bpp / 8
+    }
+
+    pub fn from_bytes(bytes: &[u8], cell: (usize, usize), bpp: usize) -> Result<Glyphs, String> {
+        if bpp != 2 && bpp != 4 {
+            return Err(format!("fonts are 2bpp or 4bpp, not {}bpp", bpp));
+        }
+        let glyph_size = (cell.0 / TILE_SIZE) * (cell.1 / TILE_SIZE) * Glyphs::tile_size(bpp);
+        if bytes.is_empty() || bytes.len() % glyph_size != 0 {
+            return Err(format!("{} bytes of font data aren't a whole number of {}x{} glyphs", bytes.len(), cell.0, cell.1));
+        }
+        let frames = bytes.chunks(glyph_size)
+            .map(|glyph| glyph.chunks(Glyphs::tile_size(bpp))
+                .map(|tile| if bpp == 2 { Tile::from_2bpp(tile) } else { Tile::from_4bpp(tile) })
+                .collect())
+            .collect();
+        Ok(Glyphs { cells: Sprite { frame_width: cell.0, frame_height: cell.1, frames }, bpp })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for tile in self.cells.frames.iter().flatten() {
+            bytes.append(&mut if self.bpp == 2 { tile.to_2bpp() } else { tile.to_4bpp() });
+        }
+        bytes
+    }
+
+    pub fn read_sheet(path: &str, cell: (usize, usize), bpp: usize) -> Result<Glyphs, String> {
+        let cells = Sprite::read_sheet(path, cell.0, cell.1)?;
+        let max = (1 << bpp) - 1;
+        if cells.frames.iter().flatten().any(|tile| tile.data.iter().flatten().any(|&index| index > max)) {
+            return Err(format!("{} uses colors above index {}, which a {}bpp font can't store", path, max, bpp));
+        }
+        Ok(Glyphs { cells, bpp })
+    }
+
+    pub fn write_sheet(&self, path: &str) {
+        self.cells.write_sheet(path, &FONT_PALETTE, GLYPHS_PER_ROW);
+    }
+
+    /// Measures every glyph from its pixels: the last column that isn't background,
+    /// and a column of space after it. Blank glyphs have no width.
+    pub fn measure(&self) -> Vec<Option<u8>> {
+        let tiles_per_row = self.cells.frame_width / TILE_SIZE;
+        self.cells.frames.iter()
+            .map(|glyph| {
+                let mut last_column = None;
+                for (i, tile) in glyph.iter().enumerate() {
+                    for row in tile.data.iter() {
+                        for (x, &index) in row.iter().enumerate() {
+                            let column = (i % tiles_per_row) * TILE_SIZE + x;
+                            if index != 0 && last_column.map(|c| column > c).unwrap_or(true) {
+                                last_column = Some(column);
+                            }
+                        }
+                    }
+                }
+                last_column.map(|column| (column + 2) as u8)
+            })
+            .collect()
+    }
+}
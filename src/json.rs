@@ -0,0 +1,409 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A JSON value that keeps the order of object keys, so files written by Porymap
+/// can be read and written back without reordering anything
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    /// floats keep the text they were read from, so 1.0 isn't written back as 1
+    Float(f64, String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Json, String> {
+        let mut chars = source.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => Err(format!("unexpected '{}' after the end of the json value", c)),
+            None => Ok(value)
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Integer(i) => Some(*i),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Json)>> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None
+        }
+    }
+
+    /// returns a required string field of an object
+    pub fn string_field(&self, key: &str) -> Result<String, String> {
+        self.get(key).and_then(|v| v.as_str()).map(|s| s.to_string())
+            .ok_or(format!("missing string field \"{}\"", key))
+    }
+
+    /// returns a required integer field of an object
+    pub fn integer_field(&self, key: &str) -> Result<i64, String> {
+        self.get(key).and_then(|v| v.as_i64())
+            .ok_or(format!("missing integer field \"{}\"", key))
+    }
+
+    /// Write the value the way Porymap does: two spaces of indentation,
+    /// every array element and object field on its own line and a trailing newline
+    pub fn to_porymap_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Integer(i) => out.push_str(&i.to_string()),
+            Json::Float(_, text) => out.push_str(text),
+            Json::String(s) => write_string(out, s),
+            Json::Array(values) => {
+                if values.is_empty() {
+                    out.push_str("[]");
+                    return;
+                }
+                out.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    value.write(out, indent + 1);
+                    if i + 1 < values.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                if fields.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    if i + 1 < fields.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Builds an object with the keys of `original` in their original order. Known fields
+/// replace the original values, or are removed when they are None, and new fields are
+/// appended, so fields this tool doesn't know about survive a round trip.
+pub fn merge_object(original: &[(String, Json)], fields: Vec<(&str, Option<Json>)>) -> Json {
+    let mut merged: Vec<(String, Json)> = original.to_vec();
+    for (key, value) in fields {
+        let position = merged.iter().position(|(k, _)| k == key);
+        match (position, value) {
+            (Some(position), Some(value)) => merged[position].1 = value,
+            (Some(position), None) => { merged.remove(position); }
+            (None, Some(value)) => merged.push((key.to_string(), value)),
+            (None, None) => {}
+        }
+    }
+    Json::Object(merged)
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected '{}' but found '{}'", expected, c)),
+        None => Err(format!("expected '{}' but found the end of the file", expected))
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Json, String> {
+    skip_whitespace(chars);
+    match chars.peek().cloned() {
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                expect(chars, ':')?;
+                let value = parse_value(chars)?;
+                fields.push((key, value));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err("expected ',' or '}' in object".to_string())
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Json::Array(values)),
+                    _ => return Err("expected ',' or ']' in array".to_string())
+                }
+            }
+        }
+        Some('"') => Ok(Json::String(parse_string(chars)?)),
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' || c.is_ascii_digit() {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if let Ok(i) = number.parse::<i64>() {
+                Ok(Json::Integer(i))
+            } else {
+                number.parse::<f64>().map(|f| Json::Float(f, number.clone())).map_err(|_| format!("invalid number {}", number))
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_alphabetic() {
+                    word.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match word.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(format!("unexpected value '{}'", word))
+            }
+        }
+        None => Err("unexpected end of the file".to_string())
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let code = parse_hex4(chars)?;
+                    // characters outside of the BMP are escaped as surrogate pairs
+                    let code = if code >= 0xd800 && code < 0xdc00 {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("unpaired surrogate in string".to_string());
+                        }
+                        let low = parse_hex4(chars)?;
+                        if low < 0xdc00 || low >= 0xe000 {
+                            return Err("unpaired surrogate in string".to_string());
+                        }
+                        0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+                    } else {
+                        code
+                    };
+                    s.push(std::char::from_u32(code).ok_or("invalid unicode escape in string")?);
+                }
+                _ => return Err("invalid escape in string".to_string())
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string())
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Result<u32, String> {
+    let hex: String = chars.take(4).collect();
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid unicode escape \\u{}", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"{
+  "id": "MAP_LITTLEROOT_TOWN",
+  "name": "LittlerootTown",
+  "layout": "LAYOUT_LITTLEROOT_TOWN",
+  "music": "MUS_LITTLEROOT",
+  "region_map_section": "MAPSEC_LITTLEROOT_TOWN",
+  "requires_flash": false,
+  "weather": "WEATHER_SUNNY",
+  "map_type": "MAP_TYPE_TOWN",
+  "allow_cycling": true,
+  "allow_escaping": false,
+  "allow_running": true,
+  "show_map_name": true,
+  "battle_scene": "MAP_BATTLE_SCENE_NORMAL",
+  "connections": [
+    {
+      "map": "MAP_ROUTE101",
+      "offset": 0,
+      "direction": "up"
+    }
+  ],
+  "object_events": [
+    {
+      "graphics_id": "OBJ_EVENT_GFX_TWIN",
+      "x": 16,
+      "y": -10,
+      "elevation": 3,
+      "movement_type": "MOVEMENT_TYPE_WANDER_AROUND",
+      "movement_range_x": 1,
+      "movement_range_y": 2,
+      "trainer_type": "TRAINER_TYPE_NONE",
+      "trainer_sight_or_berry_tree_id": "0",
+      "script": "LittlerootTown_EventScript_Twin",
+      "flag": "0"
+    }
+  ],
+  "warp_events": [],
+  "coord_events": [],
+  "bg_events": [],
+  "scale": 1.0,
+  "ratio": -2.50e3,
+  "note": "a \"quoted\" é\tand \\ \u0001",
+  "custom": {},
+  "nothing": null
+}
+"#;
+
+    #[test]
+    fn porymap_round_trip() {
+        let json = Json::parse(MAP).unwrap();
+        assert_eq!(json.to_porymap_string(), MAP);
+        assert_eq!(json.get("object_events").and_then(|e| e.as_array()).map(|e| e.len()), Some(1));
+        assert_eq!(json.get("note").and_then(|n| n.as_str()), Some("a \"quoted\" é\tand \\ \u{1}"));
+    }
+
+    #[test]
+    fn merged_fields_keep_their_order() {
+        let json = Json::parse(r#"{"b": 1, "a": 2, "c": 3}"#).unwrap();
+        let merged = merge_object(json.as_object().unwrap(), vec![
+            ("a", Some(Json::Integer(4))), ("c", None), ("d", Some(Json::Bool(true))),
+        ]);
+        assert_eq!(merged.to_porymap_string(), "{\n  \"b\": 1,\n  \"a\": 4,\n  \"d\": true\n}\n");
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#).unwrap(), Json::String("\u{1f600}".to_string()));
+        assert!(Json::parse(r#""\ud800\u0041""#).is_err());
+        assert!(Json::parse(r#""\ud800\ue000""#).is_err());
+        assert!(Json::parse(r#""\ud800""#).is_err());
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(Json::parse("{\"a\": 1,}").is_err());
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{} {}").is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use std::fs;
+use std::path::Path;
+
+use crate::decomp::Game;
+use crate::tileset::{METATILE_SIZE, read_rgb_image, Tile, TILE_SIZE, TileRef, TileStorage, Transparency};
+
+/// A block of a layout, stored as a u16 in map.bin and border.bin
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Block {
+    pub metatile: usize,
+    pub collision: usize,
+    pub elevation: usize,
+}
+
+impl Block {
+    pub fn encode(&self) -> u16 {
+        (((self.elevation & 0xf) << 12) | ((self.collision & 0x3) << 10) | (self.metatile & 0x3ff)) as u16
+    }
+
+    pub fn decode(value: u16) -> Block {
+        let value = value as usize;
+        Block {
+            metatile: value & 0x3ff,
+            collision: (value >> 10) & 0x3,
+            elevation: (value >> 12) & 0xf,
+        }
+    }
+}
+
+/// The blocks of a map.bin or border.bin
+pub struct MapLayout {
+    pub width: usize,
+    pub height: usize,
+    pub blocks: Vec<Block>,
+}
+
+impl MapLayout {
+    pub fn new(width: usize, height: usize) -> MapLayout {
+        MapLayout {
+            width,
+            height,
+            blocks: vec![Block { metatile: 0, collision: 0, elevation: 0 }; width * height],
+        }
+    }
+
+    pub fn load(path: &Path, width: usize) -> Result<MapLayout, String> {
+        let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let blocks: Vec<Block> = bytes.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| Block::decode(pair[0] as u16 | ((pair[1] as u16) << 8)))
+            .collect();
+        if width == 0 || blocks.len() % width != 0 {
+            return Err(format!("{} has {} blocks, which isn't a multiple of the width {}", path.display(), blocks.len(), width));
+        }
+        Ok(MapLayout {
+            width,
+            height: blocks.len() / width,
+            blocks,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(self.blocks.len() * 2);
+        for block in self.blocks.iter() {
+            let value = block.encode();
+            bytes.push((value & 0xff) as u8);
+            bytes.push(((value >> 8) & 0xff) as u8);
+        }
+        fs::write(path, bytes).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Block> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.blocks.get(y * self.width + x)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, block: Block) -> Result<(), String> {
+        if x >= self.width || y >= self.height {
+            return Err(format!("({}, {}) is outside of the {}x{} layout", x, y, self.width, self.height));
+        }
+        self.blocks[y * self.width + x] = block;
+        Ok(())
+    }
+}
+
+/// The graphics of a primary and secondary tileset as they are loaded in VRAM for a map
+pub struct MetatileRenderer {
+    pub tiles: Vec<Tile>,
+    pub palettes: Vec<[[u8; 3]; 16]>,
+    pub metatiles: Vec<Vec<TileRef>>,
+    pub game: Game,
+}
+
+impl MetatileRenderer {
+    pub fn load(primary_folder: &str, secondary_folder: &str, game: Game) -> Result<MetatileRenderer, String> {
+        let primary_count = game.primary_tile_count();
+        let mut tiles = Vec::with_capacity(primary_count * 2);
+        let mut metatiles = Vec::with_capacity(primary_count * 2);
+        for (folder, count) in [(primary_folder, primary_count), (secondary_folder, primary_count)].iter() {
+            let mut folder_tiles = TileStorage::read_tiles_image(format!("{}/tiles.png", folder))
+                .map_err(|e| format!("failed to read {}/tiles.png: {}", folder, e))?;
+            // the secondary tileset always starts after the space of the primary tileset
+            folder_tiles.resize_with(*count, Tile::blank);
+            tiles.append(&mut folder_tiles);
+
+            let bytes = fs::read(format!("{}/metatiles.bin", folder))
+                .map_err(|e| format!("failed to read {}/metatiles.bin: {}", folder, e))?;
+            let mut folder_metatiles: Vec<Vec<TileRef>> = bytes.chunks(16)
+                .map(|metatile| TileRef::decode_all(metatile))
+                .collect();
+            folder_metatiles.resize(*count, Vec::new());
+            metatiles.append(&mut folder_metatiles);
+        }
+
+        let mut palettes = Vec::with_capacity(16);
+        for i in 0..16 {
+            let folder = if i < game.primary_palette_count() { primary_folder } else { secondary_folder };
+            let path = format!("{}/palettes/{:0>2}.pal", folder, i);
+            palettes.push(TileStorage::read_pal_file(path).unwrap_or_default());
+        }
+        Ok(MetatileRenderer { tiles, palettes, metatiles, game })
+    }
+
+    /// returns the colors of a metatile with the top layer drawn over the bottom layer
+    pub fn render_metatile(&self, metatile: usize) -> [[[u8; 3]; METATILE_SIZE]; METATILE_SIZE] {
+        let mut pixels = [[[0u8; 3]; METATILE_SIZE]; METATILE_SIZE];
+        let tile_refs = match self.metatiles.get(metatile) {
+            Some(tile_refs) => tile_refs,
+            None => return pixels
+        };
+        for (i, tile_ref) in tile_refs.iter().enumerate() {
+            let top_layer = i >= 4;
+            let tile = match self.tiles.get(tile_ref.tile_id) {
+                Some(tile) => tile.flip(tile_ref.flip_x, tile_ref.flip_y),
+                None => continue
+            };
+            let palette = &self.palettes[tile_ref.palette];
+            for y in 0..TILE_SIZE {
+                for x in 0..TILE_SIZE {
+                    let index = tile.data[y][x];
+                    // index 0 is transparent on the top layer
+                    if top_layer && index == 0 {
+                        continue;
+                    }
+                    pixels[(i % 4 / 2) * TILE_SIZE + y][(i % 2) * TILE_SIZE + x] = palette[index as usize];
+                }
+            }
+        }
+        pixels
+    }
+
+    /// returns the rgb pixels of the whole layout, optionally with the collision and
+    /// elevation of every block drawn over it
+    pub fn render_layout(&self, layout: &MapLayout, overlay: bool) -> Vec<u8> {
+        let width = layout.width * METATILE_SIZE;
+        let mut pixels = vec![0u8; width * layout.height * METATILE_SIZE * 3];
+        for (i, block) in layout.blocks.iter().enumerate() {
+            let x_start = (i % layout.width) * METATILE_SIZE;
+            let y_start = (i / layout.width) * METATILE_SIZE;
+            let metatile = self.render_metatile(block.metatile);
+            for y in 0..METATILE_SIZE {
+                for x in 0..METATILE_SIZE {
+                    let mut color = metatile[y][x];
+                    if overlay {
+                        color = overlay_color(block, x, y, color);
+                    }
+                    let offset = ((y_start + y) * width + x_start + x) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// Cut an image of a whole map into metatiles and add them to the storage. Returns the
+/// encoded metatiles.bin and a layout that places them to reproduce the image.
+/// The image is drawn on the bottom layer, and the top layer of every metatile is left blank.
+/// Every 8x8 tile can use 15 colors besides the transparent one, and the tiles are packed
+/// into as few palettes as possible, so the whole image can use more than 16 colors.
+pub fn import_image(storage: &mut TileStorage, path: &str, game: Game) -> Result<(Vec<u8>, MapLayout), String> {
+    let (width, height, pixels) = read_rgb_image(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    if width % METATILE_SIZE != 0 || height % METATILE_SIZE != 0 {
+        return Err(format!("{} is {}x{}, which isn't a multiple of the {}x{} metatile size", path, width, height, METATILE_SIZE, METATILE_SIZE));
+    }
+    // indexed images keep their transparent index, other images only have one with --transparent-color
+    let transparent = match storage.transparency {
+        Transparency::Color(color) => Some(color),
+        Transparency::Index(index) => TileStorage::read_palette(path.to_string()).ok()
+            .and_then(|palette| palette.get(index as usize).cloned()),
+    };
+
+    let tiles_x = width / TILE_SIZE;
+    let tiles_y = height / TILE_SIZE;
+    let mut tile_colors: Vec<Vec<[u8; 3]>> = Vec::with_capacity(tiles_x * tiles_y);
+    for i in 0..tiles_x * tiles_y {
+        let mut colors: Vec<[u8; 3]> = Vec::new();
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                let color = pixel_color(&pixels, width, (i % tiles_x) * TILE_SIZE + x, (i / tiles_x) * TILE_SIZE + y);
+                if Some(color) != transparent && !colors.contains(&color) {
+                    colors.push(color);
+                }
+            }
+        }
+        if colors.len() > 15 {
+            return Err(format!("the tile at ({}, {}) of {} has {} colors, but a tile can only use 15 besides the transparent color",
+                               (i % tiles_x) * TILE_SIZE, (i / tiles_x) * TILE_SIZE, path, colors.len()));
+        }
+        tile_colors.push(colors);
+    }
+
+    // tiles with the most colors are placed first, so the ones with fewer colors fill the gaps
+    let mut sorted: Vec<&Vec<[u8; 3]>> = tile_colors.iter()
+        .filter(|colors| !storage.primary_palettes.iter().any(|palette| covers(&palette[1..], colors)))
+        .collect();
+    sorted.sort_by(|a, b| b.len().cmp(&a.len()));
+    let mut palettes: Vec<Vec<[u8; 3]>> = Vec::new();
+    for colors in sorted {
+        if palettes.iter().any(|palette| covers(palette, colors)) {
+            continue;
+        }
+        // merge into the palette that needs the fewest new colors
+        let best = palettes.iter().enumerate()
+            .map(|(i, palette)| (i, colors.iter().filter(|color| !palette.contains(color)).count(), palette.len()))
+            .filter(|&(_, missing, len)| len + missing <= 15)
+            .min_by_key(|&(_, missing, _)| missing);
+        match best {
+            Some((i, _, _)) => {
+                let palette = &mut palettes[i];
+                for color in colors {
+                    if !palette.contains(color) {
+                        palette.push(*color);
+                    }
+                }
+            }
+            None => palettes.push(colors.clone())
+        }
+    }
+    let max_palettes = storage.max_palette_count();
+    if storage.palettes.len() + palettes.len() > max_palettes {
+        return Err(format!("{} needs {} palettes, but a tileset only has room for {}",
+                           path, storage.palettes.len() + palettes.len(), max_palettes));
+    }
+    let first_palette = storage.palettes.len();
+    for palette in palettes.iter() {
+        let mut formatted_palette: [[u8; 3]; 16] = Default::default();
+        formatted_palette[0] = transparent.unwrap_or_default();
+        formatted_palette[1..=palette.len()].copy_from_slice(palette);
+        storage.add_palette(formatted_palette);
+    }
+
+    // index every tile with its palette and add it to the storage
+    let mut tile_refs: Vec<TileRef> = Vec::with_capacity(tile_colors.len());
+    for (i, colors) in tile_colors.iter().enumerate() {
+        let (palette_id, palette) = match storage.primary_palettes.iter().position(|palette| covers(&palette[1..], colors)) {
+            Some(palette_id) => (palette_id, storage.primary_palettes[palette_id][1..].to_vec()),
+            None => match palettes.iter().position(|palette| covers(palette, colors)) {
+                Some(palette_id) => (first_palette + palette_id + storage.palette_offset(), palettes[palette_id].clone()),
+                None => (storage.palette_offset(), Vec::new())
+            }
+        };
+        let mut data = [[0u8; TILE_SIZE]; TILE_SIZE];
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                let color = pixel_color(&pixels, width, (i % tiles_x) * TILE_SIZE + x, (i / tiles_x) * TILE_SIZE + y);
+                if Some(color) != transparent {
+                    data[y][x] = palette.iter().position(|c| *c == color).map(|index| index + 1).unwrap_or(0) as u8;
+                }
+            }
+        }
+        let tile = Tile::new(data);
+        let (tile_id, flip_x, flip_y) = if tile.is_transparent() {
+            // fully transparent tiles always use the blank tile of the primary tileset
+            (0, false, false)
+        } else if let Some(primary_tile) = storage.find_primary_tile(&tile) {
+            primary_tile
+        } else {
+            let (tile_id, flip_x, flip_y) = storage.push(tile);
+            (tile_id + storage.tile_offset(), flip_x, flip_y)
+        };
+        tile_refs.push(TileRef { tile_id, flip_x, flip_y, palette: palette_id });
+    }
+
+    // secondary metatiles are numbered after the metatiles of the primary tileset
+    let (offset, max_metatiles) = if storage.primary {
+        (0, game.primary_tile_count())
+    } else {
+        (game.primary_tile_count(), 0x400 - game.primary_tile_count())
+    };
+    let mut layout = MapLayout::new(width / METATILE_SIZE, height / METATILE_SIZE);
+    let mut metatiles: Vec<Vec<u8>> = Vec::new();
+    for (i, block) in layout.blocks.iter_mut().enumerate() {
+        let tile_x = (i % layout.width) * 2;
+        let tile_y = (i / layout.width) * 2;
+        // the bottom layer in the order of Tile::extract, followed by a blank top layer
+        let mut metatile = Vec::with_capacity(16);
+        for &(x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            let value = tile_refs[(tile_y + y) * tiles_x + tile_x + x].encode();
+            metatile.push((value & 0xff) as u8);
+            metatile.push(((value >> 8) & 0xff) as u8);
+        }
+        metatile.resize(16, 0);
+        // TileStorage::push already matched every tile against the stored tiles with
+        // Tile::is_equivalent, so metatiles that look the same have the same tile refs
+        let metatile_id = match metatiles.iter().position(|m| *m == metatile) {
+            Some(metatile_id) => metatile_id,
+            None => {
+                metatiles.push(metatile);
+                metatiles.len() - 1
+            }
+        };
+        block.metatile = metatile_id + offset;
+    }
+
+    if metatiles.len() > max_metatiles {
+        return Err(format!("{} has {} unique metatiles, but a tileset only has room for {}", path, metatiles.len(), max_metatiles));
+    }
+    let max_tiles = storage.max_tile_count();
+    if storage.tiles.len() > max_tiles {
+        return Err(format!("{} has {} unique tiles, but a tileset only has room for {}", path, storage.tiles.len(), max_tiles));
+    }
+    Ok((metatiles.concat(), layout))
+}
+
+fn pixel_color(pixels: &[u8], width: usize, x: usize, y: usize) -> [u8; 3] {
+    let offset = (y * width + x) * 3;
+    [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+}
+
+/// true if the palette has every color of the tile
+fn covers(palette: &[[u8; 3]], colors: &[[u8; 3]]) -> bool {
+    colors.iter().all(|color| palette.contains(color))
+}
+
+/// every elevation gets its own tint, and blocks with collision are crossed out in red
+fn overlay_color(block: &Block, x: usize, y: usize, color: [u8; 3]) -> [u8; 3] {
+    if block.collision != 0 && (x == y || x + y == METATILE_SIZE - 1) {
+        return [0xff, 0, 0];
+    }
+    let hue = (block.elevation * 360 / 16) as f32;
+    let tint = hue_to_rgb(hue);
+    [
+        ((color[0] as u16 + tint[0] as u16) / 2) as u8,
+        ((color[1] as u16 + tint[1] as u16) / 2) as u8,
+        ((color[2] as u16 + tint[2] as u16) / 2) as u8,
+    ]
+}
+
+fn hue_to_rgb(hue: f32) -> [u8; 3] {
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
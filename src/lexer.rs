@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use logos::Logos;
+
+#[derive(Logos, Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    #[end]
+    End,
+    #[error]
+    Error,
+    #[regex = "(#|//)[^\n]*"]
+    Comment,
+
+    #[token = "script"]
+    Script,
+    #[token = "text"]
+    Text,
+    #[token = "movement"]
+    Movement,
+    #[token = "mart"]
+    Mart,
+    #[token = "if"]
+    If,
+    #[token = "elif"]
+    Elif,
+    #[token = "else"]
+    Else,
+    #[token = "while"]
+    While,
+    #[token = "do"]
+    Do,
+    #[token = "switch"]
+    Switch,
+    #[token = "case"]
+    Case,
+    #[token = "default"]
+    Default,
+    #[token = "break"]
+    Break,
+    #[token = "continue"]
+    Continue,
+
+    #[token = "{"]
+    LeftBrace,
+    #[token = "}"]
+    RightBrace,
+    #[token = "("]
+    LeftParen,
+    #[token = ")"]
+    RightParen,
+    #[token = ","]
+    Comma,
+    #[token = ":"]
+    Colon,
+    #[token = "*"]
+    Star,
+    #[token = "=="]
+    Equal,
+    #[token = "!="]
+    NotEqual,
+    #[token = "<"]
+    Less,
+    #[token = "<="]
+    LessEqual,
+    #[token = ">"]
+    Greater,
+    #[token = ">="]
+    GreaterEqual,
+    #[token = "!"]
+    Not,
+    #[token = "&&"]
+    And,
+    #[token = "||"]
+    Or,
+
+    #[regex = "[a-zA-Z_][a-zA-Z0-9_]*"]
+    Identifier,
+    #[regex = "[0-9]+"]
+    Number,
+    #[regex = "0x[0-9a-fA-F]+"]
+    HexNumber,
+    #[regex = "\"[^\"]*\""]
+    String,
+}
+
+/// where something is in the source, in bytes
+pub type Span = Range<usize>;
+
+/// An error with the part of the source it is about
+pub struct CompileError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl CompileError {
+    pub fn new(span: Span, message: String) -> CompileError {
+        CompileError { span, message }
+    }
+
+    /// Formats the error like a compiler would, with the line it is in and the span underlined
+    pub fn format(&self, path: &str, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..].find('\n').map(|i| start + i).unwrap_or(source.len());
+        let line_number = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let length = source[start..self.span.end.min(line_end).max(start)].chars().count().max(1);
+        // tabs are kept so the carets line up with the span
+        let padding: String = source[line_start..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        format!("{}:{}:{}: {}\n    {}\n    {}{}", path, line_number, column, self.message,
+                &source[line_start..line_end], padding, "^".repeat(length))
+    }
+}
+
+/// A token and the source it was read from
+pub struct Lexeme<'a> {
+    pub token: Token,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// Splits the source into tokens, without the comments
+pub fn tokenize(source: &str) -> Result<Vec<Lexeme<'_>>, CompileError> {
+    let mut lexemes = Vec::new();
+    let mut lexer = Token::lexer(source);
+    while lexer.token != Token::End {
+        match lexer.token {
+            Token::Comment => {}
+            Token::Error => return Err(CompileError::new(lexer.range(), format!("unexpected {}", lexer.slice()))),
+            token => lexemes.push(Lexeme { token, text: lexer.slice(), span: lexer.range() }),
+        }
+        lexer.advance();
+    }
+    lexemes.push(Lexeme { token: Token::End, text: "", span: source.len()..source.len() });
+    Ok(lexemes)
+}
@@ -0,0 +1,153 @@
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 18;
+const MAX_DISTANCE: usize = 0x1000;
+/// VRAM is written 16 bits at a time, so a match can't copy the byte right before it
+const MIN_DISTANCE: usize = 2;
+
+/// Compress data in the LZ77 format of the GBA BIOS, like the .lz files of the decomps
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x10, (data.len() & 0xff) as u8, ((data.len() >> 8) & 0xff) as u8, ((data.len() >> 16) & 0xff) as u8];
+    let mut position = 0;
+    while position < data.len() {
+        let flag_index = out.len();
+        out.push(0);
+        for bit in 0..8 {
+            if position >= data.len() {
+                break;
+            }
+            let (length, distance) = longest_match(data, position);
+            if length >= MIN_MATCH {
+                out[flag_index] |= 0x80 >> bit;
+                out.push((((length - MIN_MATCH) << 4) | ((distance - 1) >> 8)) as u8);
+                out.push(((distance - 1) & 0xff) as u8);
+                position += length;
+            } else {
+                out.push(data[position]);
+                position += 1;
+            }
+        }
+    }
+    // the BIOS reads whole words, so the files are padded to 4 bytes
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+    out
+}
+
+fn longest_match(data: &[u8], position: usize) -> (usize, usize) {
+    let mut best = (0, 0);
+    let max_length = MAX_MATCH.min(data.len() - position);
+    for distance in MIN_DISTANCE..=MAX_DISTANCE.min(position) {
+        let start = position - distance;
+        let mut length = 0;
+        while length < max_length && data[start + length] == data[position + length] {
+            length += 1;
+        }
+        if length > best.0 {
+            best = (length, distance);
+            if length == max_length {
+                break;
+            }
+        }
+    }
+    best
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 4 || data[0] != 0x10 {
+        return Err("data isn't LZ77 compressed".to_string());
+    }
+    let size = data[1] as usize | (data[2] as usize) << 8 | (data[3] as usize) << 16;
+    let mut out = Vec::with_capacity(size);
+    let mut position = 4;
+    let truncated = || "LZ77 data ends before its decompressed size".to_string();
+    while out.len() < size {
+        let flags = *data.get(position).ok_or_else(truncated)?;
+        position += 1;
+        for bit in 0..8 {
+            if out.len() >= size {
+                break;
+            }
+            if flags & (0x80 >> bit) != 0 {
+                let high = *data.get(position).ok_or_else(truncated)? as usize;
+                let low = *data.get(position + 1).ok_or_else(truncated)? as usize;
+                position += 2;
+                let length = (high >> 4) + MIN_MATCH;
+                let distance = (((high & 0xf) << 8) | low) + 1;
+                if distance > out.len() {
+                    return Err(format!("LZ77 match at 0x{:X} points before the start of the data", position - 2));
+                }
+                for _ in 0..length {
+                    let byte = out[out.len() - distance];
+                    out.push(byte);
+                }
+            } else {
+                out.push(*data.get(position).ok_or_else(truncated)?);
+                position += 1;
+            }
+        }
+    }
+    out.truncate(size);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the distance of every match in compressed data
+    fn match_distances(compressed: &[u8]) -> Vec<usize> {
+        let size = compressed[1] as usize | (compressed[2] as usize) << 8 | (compressed[3] as usize) << 16;
+        let mut distances = Vec::new();
+        let mut written = 0;
+        let mut position = 4;
+        while written < size {
+            let flags = compressed[position];
+            position += 1;
+            for bit in 0..8 {
+                if written >= size {
+                    break;
+                }
+                if flags & (0x80 >> bit) != 0 {
+                    let (high, low) = (compressed[position] as usize, compressed[position + 1] as usize);
+                    distances.push((((high & 0xf) << 8) | low) + 1);
+                    written += (high >> 4) + MIN_MATCH;
+                    position += 2;
+                } else {
+                    written += 1;
+                    position += 1;
+                }
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data: Vec<u8> = (0..0x2000u32).map(|i| (i * 7 % 13) as u8).collect();
+        data.extend(b"some text that repeats, some text that repeats");
+        data.extend(vec![0; 100]);
+        for data in [Vec::new(), vec![0x42], data].iter() {
+            let compressed = compress(data);
+            assert_eq!(compressed.len() % 4, 0);
+            assert_eq!(&decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn matches_skip_the_previous_byte() {
+        let data = vec![0x11; 64];
+        let compressed = compress(&data);
+        let distances = match_distances(&compressed);
+        assert!(!distances.is_empty());
+        assert!(distances.iter().all(|&distance| distance >= MIN_DISTANCE));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let compressed = compress(b"abcdefgh");
+        assert!(decompress(&compressed[..5]).is_err());
+        assert!(decompress(&[0x11, 0, 0, 0]).is_err());
+    }
+}
@@ -8,12 +8,39 @@ use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use crate::tileset::{parse_metatile_config, Tile, TileStorage};
+use crate::charmap::Charmap;
+use crate::decomp::{Game, Project};
+use crate::layout::{Block, MapLayout, MetatileRenderer};
+use crate::rom::Rom;
+use crate::sprite::Sprite;
+use crate::tileset::{parse_metatile_config, Tile, TileStorage, Transparency};
 
+mod animation;
+mod base_stats;
+mod charmap;
+mod compiler;
+mod constants;
+mod decomp;
+mod encounters;
+mod events;
+mod font;
+mod json;
+mod layout;
+mod lexer;
+mod lz77;
+mod porymap;
+mod remap;
 mod rom;
+mod script;
+mod species;
+mod sprite;
+mod text;
 mod tileset;
+mod usage;
+mod verify;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -33,10 +60,182 @@ fn main() {
     }
 }
 
-fn inner_main(args: Vec<String>) -> Result<String, String> {
-    if let Some(arg) = args.get(0) {
+/// removes a flag from the arguments, returning true if it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false
+    }
+}
+
+/// removes an option and its value from the arguments
+fn take_option(args: &mut Vec<String>, option: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == option) {
+        Some(index) => {
+            if index + 1 >= args.len() {
+                return Err(format!("missing value for {}", option));
+            }
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Ok(Some(value))
+        }
+        None => Ok(None)
+    }
+}
+
+/// Parses a number given on the command line, decimal like 412 or hex like 0x3203CC
+fn parse_number_argument(arg: &str, name: &str) -> Result<usize, String> {
+    constants::parse_number(arg).map(|n| n as usize).ok_or(format!("invalid {}: {}", name, arg))
+}
+
+/// removes an option and its number from the arguments
+fn take_number(args: &mut Vec<String>, option: &str) -> Result<Option<usize>, String> {
+    match take_option(args, option)? {
+        Some(value) => Ok(Some(parse_number_argument(&value, option)?)),
+        None => Ok(None)
+    }
+}
+
+/// Where the data of a command is read from and written back to, a ROM with what was read
+/// to find the data in it, or the files of a decomp with --project
+enum Source<R, P> {
+    Rom(Rom, R),
+    Project(P),
+}
+
+/// A layout given on the command line, either as a map.bin with --width or by its name with --project
+struct LayoutArgument {
+    path: PathBuf,
+    layout: MapLayout,
+    primary_tileset: Option<String>,
+    secondary_tileset: Option<String>,
+}
+
+fn open_layout(project: &Option<Project>, layout: &str, width: Option<usize>) -> Result<LayoutArgument, String> {
+    if let Some(width) = width {
+        let path = PathBuf::from(layout);
+        return Ok(LayoutArgument {
+            layout: MapLayout::load(&path, width)?,
+            path,
+            primary_tileset: None,
+            secondary_tileset: None,
+        });
+    }
+    let project = match project {
+        Some(project) => project,
+        None => return Err("layouts need --width unless they are found by name with --project".to_string())
+    };
+    let layout = project.find_layout(layout)?;
+    let path = project.root.join(&layout.blockdata_filepath);
+    Ok(LayoutArgument {
+        layout: MapLayout::load(&path, layout.width as usize)?,
+        path,
+        primary_tileset: Some(layout.primary_tileset),
+        secondary_tileset: Some(layout.secondary_tileset),
+    })
+}
+
+/// tilesets are folders, or names like gTileset_General inside of a project
+fn resolve_tileset(project: &Option<Project>, tileset: &str) -> Result<String, String> {
+    if Path::new(tileset).is_dir() {
+        return Ok(tileset.to_string());
+    }
+    match project.as_ref().and_then(|project| project.find_tileset(tileset)) {
+        Some((path, _)) => Ok(path.to_string_lossy().to_string()),
+        None => Err(format!("couldn't find tileset {}", tileset))
+    }
+}
+
+/// the charmap.txt of a decomp, or the built in character set
+fn open_charmap(args: &mut Vec<String>, project: &Option<String>) -> Result<Charmap, String> {
+    let japanese = take_flag(args, "--japanese");
+    match project {
+        Some(root) => Charmap::load(&Path::new(root).join("charmap.txt")),
+        None if japanese => Ok(Charmap::japanese()),
+        None => Ok(Charmap::international())
+    }
+}
+
+fn parse_transparency(args: &mut Vec<String>) -> Result<Transparency, String> {
+    if let Some(color) = take_option(args, "--transparent-color")? {
+        let hex = color.trim_start_matches('#');
+        if hex.len() != 6 {
+            return Err(format!("transparent color must be in RRGGBB format: {}", color));
+        }
+        let mut rgb = [0u8; 3];
+        for i in 0..3 {
+            rgb[i] = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("transparent color must be in RRGGBB format: {}", color))?;
+        }
+        return Ok(Transparency::Color(rgb));
+    }
+    if let Some(index) = take_number(args, "--transparent-index")? {
+        if index >= 16 {
+            return Err(format!("transparent index must be between 0 and 15: {}", index));
+        }
+        return Ok(Transparency::Index(index as u8));
+    }
+    Ok(Transparency::Index(0))
+}
+
+fn inner_main(mut args: Vec<String>) -> Result<String, String> {
+    if let Some(arg) = args.get(0).cloned() {
         match arg.as_str() {
+            "tileset" if args.get(1).map(|a| a.as_str()) == Some("usage") => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let project = Project::open(&root)?;
+                let tileset = match args.get(2) {
+                    Some(arg) => arg.clone(),
+                    None => return Err("missing tileset".to_string())
+                };
+                let usage = usage::analyze_tileset(&project, &tileset)?;
+                let unused = usage.unused_metatiles();
+                let mut message = format!("{} {} tileset {} is used by {} layouts\n",
+                                          if usage.primary { "Primary" } else { "Secondary" },
+                                          project.game.name(), tileset, usage.layouts.len());
+                message.push_str(&format!("{} of {} metatiles are unused: {}\n",
+                                          unused.len(), usage.metatile_uses.len(), usage::format_ranges(&unused)));
+                message.push_str(&format!("{} tiles are only used by unused metatiles: {}",
+                                          usage.reclaimable_tiles.len(), usage::format_ranges(&usage.reclaimable_tiles)));
+                return Ok(message);
+            }
+            "tileset" if args.get(1).map(|a| a.as_str()) == Some("remap") => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let project = Project::open(&root)?;
+                if args.len() < 5 {
+                    return Err("usage: tileset remap <tileset> <old_definitions> <new_definitions>".to_string());
+                }
+                let mapping = remap::metatile_mapping(&args[3], &args[4])?;
+                let files = remap::remap_layouts(&project, &args[2], &mapping)?;
+                let mut message = format!("Remapped {} of {} metatiles in {} files", mapping.iter().enumerate()
+                    .filter(|&(i, new_id)| *new_id != Some(i)).count(), mapping.len(), files.len());
+                for file in files {
+                    message.push_str(&format!("\n{}", file));
+                }
+                return Ok(message);
+            }
             "tileset" => {
+                let verify = take_flag(&mut args, "--verify");
+                let preview = take_flag(&mut args, "--preview");
+                let incremental = take_flag(&mut args, "--incremental");
+                let transparency = parse_transparency(&mut args)?;
+                let project = match take_option(&mut args, "--project")? {
+                    Some(root) => Some(Project::open(&root)?),
+                    None => None
+                };
+                let primary_folder = take_option(&mut args, "--primary")?;
+                let emit_c = take_option(&mut args, "--emit-c")?;
+                let animations = match take_option(&mut args, "--animations")? {
+                    Some(path) => {
+                        let contents = fs::read_to_string(&path)
+                            .map_err(|e| format!("failed to read animations {}: {}", path, e))?;
+                        animation::parse_animation_config(contents.lines().map(|l| l.to_string()).collect())?
+                    }
+                    None => Vec::new()
+                };
                 let primary = match args.get(1) {
                     Some(arg) => {
                         match arg.as_str() {
@@ -52,6 +251,18 @@ fn inner_main(args: Vec<String>) -> Result<String, String> {
                     Some(arg) => { arg }
                     None => return Err("missing output folder".to_string())
                 }.clone();
+                // tilesets can be referred to by name inside of a project
+                let output_path = match &project {
+                    Some(project) => project.tileset_folder(&output_path, primary).to_string_lossy().to_string(),
+                    None => output_path
+                };
+                let primary_folder = match (&project, primary_folder) {
+                    (Some(project), Some(name)) => match project.find_tileset_of_kind(&name, true) {
+                        Some(path) => Some(path.to_string_lossy().to_string()),
+                        None => return Err(format!("couldn't find primary tileset {} in {}", name, project.root.display()))
+                    },
+                    (_, primary_folder) => primary_folder
+                };
 
                 // parse metatiles from file
                 let metatile_definitions = match args.get(3) {
@@ -71,16 +282,49 @@ fn inner_main(args: Vec<String>) -> Result<String, String> {
                 }
                 let inputs = &args[4..];
 
+                // the game decides where secondary tilesets start, tilesets outside of a decomp get the pokeemerald layout
+                let game = match &project {
+                    Some(project) => project.game,
+                    None => decomp::find_tileset_root(&output_path)
+                        .and_then(|(root, _)| Project::detect_game(&root)).unwrap_or(Game::Emerald)
+                };
+
                 // add the tilesets to our storage
-                let mut storage = TileStorage::new(output_path.clone(), primary);
+                let mut storage = TileStorage::new(output_path.clone(), primary, game);
+                storage.transparency = transparency;
+                if let Some(folder) = &primary_folder {
+                    if primary {
+                        return Err("--primary can only be used when building a secondary tileset".to_string());
+                    }
+                    storage.load_primary(folder)
+                        .map_err(|e| format!("failed to load primary tileset from {}: {}", folder, e))?;
+                }
+                if incremental {
+                    storage.load_previous()
+                        .map_err(|e| format!("failed to load previous build from {}: {}", output_path, e))?;
+                }
+                // animated tiles are reserved first so the metatiles can reference them
+                for animation in animations.iter() {
+                    animation::add_animation(&mut storage, animation)?;
+                }
+                if incremental {
+                    storage.plan_palettes(inputs)?;
+                }
                 for tileset in inputs {
-                    storage.add_image(tileset.clone()).expect("failed to add tileset to storage");
+                    storage.add_image(tileset.clone()).map_err(|e| format!("failed to add {}: {}", tileset, e))?;
+                }
+                if incremental {
+                    storage.release_unused();
                 }
+                storage.check_limits()?;
                 storage.output();
+                if preview {
+                    storage.output_metatile_preview(&metatile_definitions);
+                }
                 // build the metatiles
                 let mut metatiles: Vec<u8> = Vec::new();
-                for (metatile_file_name, metatile_id) in metatile_definitions {
-                    let metatile = storage.encoded_metatiles.get(&(metatile_file_name.clone(), metatile_id))
+                for (metatile_file_name, metatile_id) in metatile_definitions.iter() {
+                    let metatile = storage.encoded_metatiles.get(&(metatile_file_name.clone(), *metatile_id))
                         .expect(&format!("failed to get encoded metatile: {} {}", metatile_file_name, metatile_id)).clone();
                     metatiles.append(&mut metatile.clone());
                 }
@@ -89,7 +333,752 @@ fn inner_main(args: Vec<String>) -> Result<String, String> {
                 fs::remove_file(&path); // ignore
                 let mut file = File::create(path).expect("failed to create metatiles.bin file");
                 file.write_all(&metatiles).expect("failed to write metatiles to file");
-                return Ok(format!("Tileset and palettes written to {}", output_path).to_string());
+
+                let mut message = format!("Tileset and palettes written to {}", output_path);
+                if let Some(name) = &emit_c {
+                    message = decomp::emit_c(&output_path, name, primary, metatile_definitions.len() / 2)?;
+                }
+
+                if verify {
+                    let mismatches = verify::verify_tileset(&output_path, primary, game, primary_folder.as_ref().map(|f| f.as_str()), &metatile_definitions, inputs)?;
+                    if !mismatches.is_empty() {
+                        return Err(format!("Tileset written to {} but failed verification:\n{}", output_path, mismatches.join("\n")));
+                    }
+                    return Ok(format!("{}\nVerified {} metatiles", message, metatile_definitions.len() / 2));
+                }
+                return Ok(message);
+            }
+            "tilesets" => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let project = Project::open(&root)?;
+                let mut message = format!("{} project at {}\n", project.game.name(), project.root.display());
+                message.push_str(&format!("{:<10} {:<32} {:>6} {:>9} {:>10}", "kind", "name", "tiles", "palettes", "metatiles"));
+                for tileset in project.tilesets() {
+                    message.push_str(&format!("\n{:<10} {:<32} {:>6} {:>9} {:>10}",
+                                              if tileset.primary { "primary" } else { "secondary" },
+                                              tileset.name, tileset.tiles, tileset.palettes, tileset.metatiles));
+                }
+                return Ok(message);
+            }
+            "maps" => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let check = take_flag(&mut args, "--check");
+                let project = Project::open(&root)?;
+                let map_groups = porymap::MapGroups::load(&project.map_groups_path())?;
+                if check {
+                    // every file is read and written back in memory, and must come out unchanged
+                    let mut changed = Vec::new();
+                    if !porymap::round_trips(&project.map_groups_path(), &map_groups.to_json())? {
+                        changed.push(project.map_groups_path());
+                    }
+                    let layouts = porymap::Layouts::load(&project.layouts_path())?;
+                    if !porymap::round_trips(&project.layouts_path(), &layouts.to_json())? {
+                        changed.push(project.layouts_path());
+                    }
+                    let mut count = 0;
+                    for map_name in map_groups.groups.iter().flat_map(|(_, maps)| maps.iter()) {
+                        let path = project.map_path(map_name);
+                        if !porymap::round_trips(&path, &porymap::Map::load(&path)?.to_json())? {
+                            changed.push(path);
+                        }
+                        count += 1;
+                    }
+                    if !changed.is_empty() {
+                        let changed: Vec<String> = changed.iter().map(|p| p.display().to_string()).collect();
+                        return Err(format!("{} files wouldn't be written back unchanged:\n{}", changed.len(), changed.join("\n")));
+                    }
+                    return Ok(format!("map_groups.json, layouts.json and {} maps are written back unchanged", count));
+                }
+                let mut message = String::new();
+                for (group, maps) in map_groups.groups.iter() {
+                    message.push_str(&format!("{}\n", group));
+                    for map_name in maps {
+                        let map = porymap::Map::load(&project.map_path(map_name))?;
+                        message.push_str(&format!("  {:<40} {}\n", map.name, map.layout));
+                    }
+                }
+                return Ok(message.trim_end().to_string());
+            }
+            "map" if args.get(1).map(|a| a.as_str()) == Some("events") => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let rom_path = take_option(&mut args, "--rom")?;
+                let map_table = take_number(&mut args, "--map-table")?;
+                let output = take_option(&mut args, "-o")?;
+                let map_name = args.get(3).ok_or("missing map")?.clone();
+                // the events come from the map.json of a decomp, or from the map header of a ROM
+                let (mut events, source) = match &rom_path {
+                    Some(rom_path) => {
+                        let rom = Rom::from_file(rom_path).map_err(|e| format!("failed to read {}: {}", rom_path, e))?;
+                        let table = match map_table {
+                            Some(offset) => offset,
+                            None => events::map_groups_table(&rom)?
+                        };
+                        let numbers: Vec<usize> = map_name.split('.').filter_map(|n| n.parse::<usize>().ok()).collect();
+                        if numbers.len() != 2 {
+                            return Err(format!("maps of ROMs are <group>.<number>, like 0.9, not {}", map_name));
+                        }
+                        let header = events::find_map_header(&rom, table, numbers[0], numbers[1])?;
+                        let game = rom.game().unwrap_or(Game::Emerald);
+                        (events::MapEvents::from_rom(&rom, header, game)?, Source::Rom(rom, (header, game)))
+                    }
+                    None => {
+                        let project = Project::open(&root)?;
+                        let path = project.map_path(&map_name);
+                        let map = porymap::Map::load(&path)?;
+                        (events::MapEvents::from_map(&map)?, Source::Project((map, path, project.game)))
+                    }
+                };
+                let game = match &source {
+                    Source::Rom(_, (_, game)) | Source::Project((_, _, game)) => *game
+                };
+                // lines about what changed, in front of the message about the written file
+                let mut message = String::new();
+                match args.get(2).map(|s| s.as_str()) {
+                    Some("list") => {
+                        for group in events::EventGroup::ALL.iter() {
+                            for (i, event) in events.get(*group).iter().enumerate() {
+                                message.push_str(&format!("{} {}: {}\n", group.name(), i, event.describe()));
+                            }
+                        }
+                        return Ok(message.trim_end().to_string());
+                    }
+                    Some("add") => {
+                        if args.len() < 7 {
+                            return Err("usage: map events add <map> <type> <x> <y> [<field>=<value>...]".to_string());
+                        }
+                        let x = args[5].parse::<i64>().map_err(|_| format!("invalid x: {}", args[5]))?;
+                        let y = args[6].parse::<i64>().map_err(|_| format!("invalid y: {}", args[6]))?;
+                        let mut event = events::Event::new(&args[4], x, y)?;
+                        event.add_game_fields(game);
+                        for field in args[7..].iter() {
+                            let mut parts = field.splitn(2, '=');
+                            match (parts.next(), parts.next()) {
+                                (Some(key), Some(value)) => event.set(key, value)?,
+                                _ => return Err(format!("fields are <field>=<value>, not {}", field))
+                            }
+                        }
+                        let group = event.group();
+                        events.get_mut(group).push(event);
+                        message.push_str(&format!("Added {} event {}\n", group.name(), events.get(group).len() - 1));
+                    }
+                    Some("move") => {
+                        if args.len() < 8 {
+                            return Err("usage: map events move <map> <object|warp|coord|bg> <index> <x> <y>".to_string());
+                        }
+                        let group = events::EventGroup::parse(&args[4])?;
+                        let index = parse_number_argument(&args[5], "index")?;
+                        let x = args[6].parse::<i64>().map_err(|_| format!("invalid x: {}", args[6]))?;
+                        let y = args[7].parse::<i64>().map_err(|_| format!("invalid y: {}", args[7]))?;
+                        let event = events.get_mut(group).get_mut(index)
+                            .ok_or(format!("{} has no {} event {}", map_name, group.name(), index))?;
+                        event.x = x;
+                        event.y = y;
+                    }
+                    _ => return Err("missing list/add/move argument".to_string())
+                }
+                return match source {
+                    Source::Project((mut map, path, _)) => {
+                        events.apply(&mut map);
+                        map.save(&path)?;
+                        Ok(format!("{}Events of {} written to {}", message, map_name, path.display()))
+                    }
+                    Source::Rom(mut rom, (header, game)) => {
+                        let free_space = text::default_free_space(&rom);
+                        for line in events.write_to_rom(&mut rom, header, free_space, game)? {
+                            message.push_str(&format!("{}\n", line));
+                        }
+                        if let Some(output) = output {
+                            rom.path = output;
+                        }
+                        rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                        Ok(format!("{}Events of map {} written to {}", message, map_name, rom.path))
+                    }
+                };
+            }
+            "map" => {
+                let project = match take_option(&mut args, "--project")? {
+                    Some(root) => Some(Project::open(&root)?),
+                    None => None
+                };
+                let width = take_number(&mut args, "--width")?;
+                let game = project.as_ref().map(|p| p.game).unwrap_or(Game::Emerald);
+                // options are taken first so they can come before the layout
+                let overlay = take_flag(&mut args, "--overlay");
+                let output = take_option(&mut args, "-o")?.unwrap_or("map.png".to_string());
+                let tilesets = match args.iter().position(|arg| arg == "--tilesets") {
+                    Some(index) if index + 2 < args.len() => {
+                        let tilesets = (args[index + 1].clone(), args[index + 2].clone());
+                        args.drain(index..index + 3);
+                        Some(tilesets)
+                    }
+                    Some(_) => return Err("--tilesets needs a primary and a secondary tileset".to_string()),
+                    None => None
+                };
+                let collision = take_number(&mut args, "--collision")?;
+                let elevation = take_number(&mut args, "--elevation")?;
+                let primary_folder = take_option(&mut args, "--primary")?;
+                let transparency = parse_transparency(&mut args)?;
+                let layout_name = match args.get(2) {
+                    Some(arg) => arg.clone(),
+                    None => return Err("missing layout".to_string())
+                };
+                match args.get(1).map(|s| s.as_str()) {
+                    Some("render") => {
+                        let layout = open_layout(&project, &layout_name, width)?;
+                        let (primary, secondary) = match (tilesets, layout.primary_tileset, layout.secondary_tileset) {
+                            (Some(tilesets), _, _) => tilesets,
+                            (None, Some(primary), Some(secondary)) => (primary, secondary),
+                            _ => return Err("missing --tilesets".to_string())
+                        };
+                        let renderer = MetatileRenderer::load(&resolve_tileset(&project, &primary)?, &resolve_tileset(&project, &secondary)?, game)?;
+                        let pixels = renderer.render_layout(&layout.layout, overlay);
+                        tileset::write_rgb_image(&output, layout.layout.width * 16, layout.layout.height * 16, &pixels)
+                            .map_err(|e| format!("failed to write {}: {}", output, e))?;
+                        return Ok(format!("Layout {} rendered to {}", layout_name, output));
+                    }
+                    Some("set") => {
+                        let number = |arg: Option<&String>, name: &str| -> Result<usize, String> {
+                            parse_number_argument(arg.ok_or(format!("missing {}", name))?, name)
+                        };
+                        let x = number(args.get(3), "x")?;
+                        let y = number(args.get(4), "y")?;
+                        let mut layout = open_layout(&project, &layout_name, width)?;
+                        let previous = *layout.layout.get(x, y)
+                            .ok_or(format!("({}, {}) is outside of the layout", x, y))?;
+                        let block = Block {
+                            metatile: number(args.get(5), "metatile")?,
+                            collision: collision.unwrap_or(previous.collision),
+                            elevation: elevation.unwrap_or(previous.elevation),
+                        };
+                        layout.layout.set(x, y, block)?;
+                        layout.layout.save(&layout.path)?;
+                        return Ok(format!("Block ({}, {}) of {} set to metatile 0x{:03X}", x, y, layout_name, block.metatile));
+                    }
+                    Some("import") => {
+                        let output_folder = match args.get(3) {
+                            Some(arg) => arg.clone(),
+                            None => return Err("missing output folder".to_string())
+                        };
+                        let mut storage = TileStorage::new(output_folder.clone(), primary_folder.is_none(), game);
+                        storage.transparency = transparency;
+                        if let Some(primary) = primary_folder {
+                            let folder = resolve_tileset(&project, &primary)?;
+                            storage.load_primary(&folder)
+                                .map_err(|e| format!("failed to load primary tileset from {}: {}", folder, e))?;
+                        }
+                        let (metatiles, layout) = layout::import_image(&mut storage, &layout_name, game)?;
+                        storage.output();
+                        let path = format!("{}/metatiles.bin", output_folder);
+                        fs::write(&path, &metatiles).map_err(|e| format!("failed to write {}: {}", path, e))?;
+                        layout.save(&Path::new(&output_folder).join("map.bin"))?;
+                        return Ok(format!("{}x{} layout with {} metatiles and {} tiles written to {}",
+                                          layout.width, layout.height, metatiles.len() / 16, storage.tiles.len(), output_folder));
+                    }
+                    _ => return Err("missing render/set/import argument".to_string())
+                }
+            }
+            "sprite" => {
+                // options are taken first so they can come before the positional arguments
+                let table = take_option(&mut args, "--table")?;
+                let palette = take_option(&mut args, "--palette")?;
+                let offset = take_number(&mut args, "--offset")?;
+                let frames = take_number(&mut args, "--frames")?;
+                let compressed = take_flag(&mut args, "--lz");
+                if args.len() < 5 {
+                    return Err("usage: sprite build <sheet.png> <output.4bpp> <WxH> or sprite extract <graphics> <output.png> <WxH>".to_string());
+                }
+                let size = Sprite::parse_size(&args[4])?;
+                match args.get(1).map(|s| s.as_str()) {
+                    Some("build") => {
+                        let sprite = Sprite::read_sheet(&args[2], size.0, size.1)?;
+                        fs::write(&args[3], sprite.to_4bpp()).map_err(|e| format!("failed to write {}: {}", args[3], e))?;
+                        let message = format!("{} frames written to {}", sprite.frames.len(), args[3]);
+                        return Ok(match table {
+                            Some(name) => format!("{}\n{}", message, sprite.frame_table(&name).trim_end()),
+                            None => message
+                        });
+                    }
+                    Some("extract") => {
+                        let length = frames.map(|frames| frames * size.0 * size.1 / 2);
+                        let offset = offset.unwrap_or(0);
+                        let palette = match palette {
+                            Some(path) => TileStorage::read_pal_file(path.clone())
+                                .map_err(|e| format!("failed to read {}: {}", path, e))?,
+                            // without a palette the sheet is drawn in shades of gray
+                            None => {
+                                let mut gray: [[u8; 3]; 16] = Default::default();
+                                for (i, color) in gray.iter_mut().enumerate() {
+                                    *color = [(i * 17) as u8; 3];
+                                }
+                                gray
+                            }
+                        };
+                        let graphics = if compressed {
+                            // the compressed size isn't known, so the graphics are decompressed from the offset
+                            let graphics = lz77::decompress(&sprite::read_graphics(&args[2], offset, None)?)?;
+                            match length {
+                                Some(length) => graphics.into_iter().take(length).collect(),
+                                None => graphics
+                            }
+                        } else {
+                            sprite::read_graphics(&args[2], offset, length)?
+                        };
+                        let sprite = Sprite::from_4bpp(&graphics, size.0, size.1)?;
+                        sprite.write_sheet(&args[3], &palette, sprite.frames.len());
+                        return Ok(format!("{} frames written to {}", sprite.frames.len(), args[3]));
+                    }
+                    _ => return Err("missing build/extract argument".to_string())
+                }
+            }
+            "species" if args.get(1).map(|a| a.as_str()) == Some("gfx") => {
+                let project = match take_option(&mut args, "--project")? {
+                    Some(root) => Some(Project::open(&root)?),
+                    None => None
+                };
+                let folder = match args.get(2) {
+                    Some(arg) => arg.clone(),
+                    None => return Err("missing species folder".to_string())
+                };
+                let game = project.as_ref().map(|p| p.game).unwrap_or(Game::Emerald);
+                // the icon palettes can only be checked against the ones in a decomp
+                let icon_palettes = match &project {
+                    Some(project) => Some(species::read_icon_palettes(project)?),
+                    None => None
+                };
+                return match species::build_species_graphics(&folder, game, icon_palettes.as_ref().map(|p| p.as_slice())) {
+                    Ok(written) => {
+                        let mut message = written.join("\n");
+                        if icon_palettes.is_none() {
+                            message.push_str("\nicon palette not checked, use --project to check it against the decomp");
+                        }
+                        Ok(message)
+                    }
+                    Err(problems) => Err(format!("{} has {} problems:\n{}", folder, problems.len(), problems.join("\n")))
+                };
+            }
+            "species" if args.get(1).map(|a| a.as_str()) == Some("stats") => {
+                let root = take_option(&mut args, "--project")?;
+                let rom_path = take_option(&mut args, "--rom")?;
+                let table = take_number(&mut args, "--table")?;
+                let count = take_number(&mut args, "--count")?.unwrap_or(base_stats::SPECIES_COUNT);
+                let output = take_option(&mut args, "-o")?;
+                if args.len() < 4 {
+                    return Err("usage: species stats <export|import> <stats.csv>".to_string());
+                }
+                let csv_path = args[3].clone();
+                let mut constants = match &root {
+                    Some(root) => constants::Constants::load(Path::new(root))?,
+                    None => constants::Constants::new()
+                };
+                base_stats::add_default_constants(&mut constants);
+                // the base stats come from the gBaseStats of a ROM, or from the source of a decomp
+                let mut source = match &rom_path {
+                    Some(rom_path) => {
+                        let rom = Rom::from_file(rom_path).map_err(|e| format!("failed to read {}: {}", rom_path, e))?;
+                        let table = match table {
+                            Some(offset) => offset,
+                            None => base_stats::base_stats_table(&rom)?
+                        };
+                        Source::Rom(rom, table)
+                    }
+                    None => Source::Project(base_stats::BaseStatsSource::load(&Project::open(root.as_ref().map(|r| r.as_str()).unwrap_or("."))?)?)
+                };
+                match args[2].as_str() {
+                    "export" => {
+                        let stats = match &source {
+                            Source::Project(source) => source.stats(),
+                            Source::Rom(rom, table) => base_stats::read_rom(rom, *table, count)?
+                        };
+                        fs::write(&csv_path, base_stats::to_csv(&stats)).map_err(|e| format!("failed to write {}: {}", csv_path, e))?;
+                        return Ok(format!("Base stats of {} species written to {}", stats.len(), csv_path));
+                    }
+                    "import" => {
+                        let contents = fs::read_to_string(&csv_path).map_err(|e| format!("failed to read {}: {}", csv_path, e))?;
+                        let stats = base_stats::from_csv(&contents).map_err(|e| format!("invalid {}: {}", csv_path, e))?;
+                        // every row is checked before anything is written
+                        for stats in stats.iter() {
+                            stats.check(&constants)?;
+                        }
+                        return match &mut source {
+                            Source::Project(source) => {
+                                let changed = source.update(&stats)?;
+                                source.save()?;
+                                Ok(format!("{} values changed in {}", changed, source.path.display()))
+                            }
+                            Source::Rom(rom, table) => {
+                                base_stats::write_rom(rom, *table, count, &stats, &constants)?;
+                                if let Some(output) = output {
+                                    rom.path = output;
+                                }
+                                rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                                Ok(format!("Base stats of {} species written to {}", stats.len(), rom.path))
+                            }
+                        };
+                    }
+                    _ => return Err("missing export/import argument".to_string())
+                }
+            }
+            "species" if args.get(1).map(|a| a.as_str()) == Some("icon") => {
+                let root = take_option(&mut args, "--project")?.unwrap_or(".".to_string());
+                let project = Project::open(&root)?;
+                if args.len() < 4 {
+                    return Err("usage: species icon <icon.png> <output.png>".to_string());
+                }
+                let (width, height, pixels) = species::read_icon_pixels(&args[2])?;
+                if (width, height) != (32, 64) {
+                    return Err(format!("{} must be two 32x32 frames (32x64), but it is {}x{}", args[2], width, height));
+                }
+                let palettes = species::read_icon_palettes(&project)?;
+                let (palette_index, indices, error) = species::match_icon_palette(&pixels, &palettes);
+                species::write_indexed(&args[3], width, height, &indices, &palettes[palette_index]);
+                return Ok(format!("{} written with icon palette {} (average error {:.1})\nUse {} in gMonIconPaletteIndices",
+                                  args[3], palette_index, error, palette_index));
+            }
+            "text" => {
+                let root = take_option(&mut args, "--project")?;
+                let charmap = open_charmap(&mut args, &root)?;
+                match args.get(1).map(|s| s.as_str()) {
+                    Some("encode") => {
+                        let text = args.get(2).ok_or("missing text")?;
+                        let bytes = charmap.encode(text)?;
+                        return Ok(bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" "));
+                    }
+                    Some("decode") => {
+                        let bytes = args[2..].iter()
+                            .flat_map(|arg| arg.split_whitespace().map(|b| b.to_string()).collect::<Vec<String>>())
+                            .map(|b| u8::from_str_radix(b.trim_start_matches("0x"), 16).map_err(|_| format!("invalid byte: {}", b)))
+                            .collect::<Result<Vec<u8>, String>>()?;
+                        return Ok(charmap.decode(&bytes).0);
+                    }
+                    Some("dump") => {
+                        let table = take_option(&mut args, "--table")?.ok_or("missing --table <offset,count>")?;
+                        let output = take_option(&mut args, "-o")?;
+                        let parts: Vec<&str> = table.split(',').map(|n| n.trim()).collect();
+                        if parts.len() != 2 {
+                            return Err(format!("--table must be <offset,count>: {}", table));
+                        }
+                        let (offset, count) = (parse_number_argument(parts[0], "offset")?, parse_number_argument(parts[1], "count")?);
+                        let rom_path = args.get(2).ok_or("missing rom")?;
+                        let rom = Rom::from_file(rom_path).map_err(|e| format!("failed to read {}: {}", rom_path, e))?;
+                        let entries = text::dump_table(&rom, &charmap, offset, count)?;
+                        let dump = text::format_dump(rom_path, &entries);
+                        return match output {
+                            Some(path) => {
+                                fs::write(&path, dump).map_err(|e| format!("failed to write {}: {}", path, e))?;
+                                Ok(format!("{} strings written to {}", entries.len(), path))
+                            }
+                            None => Ok(dump.trim_end().to_string())
+                        };
+                    }
+                    Some("insert") => {
+                        let output = take_option(&mut args, "-o")?;
+                        let free_space = take_number(&mut args, "--free-space")?;
+                        if args.len() < 4 {
+                            return Err("usage: text insert <rom> <dump>".to_string());
+                        }
+                        let mut rom = Rom::from_file(&args[2]).map_err(|e| format!("failed to read {}: {}", args[2], e))?;
+                        let contents = fs::read_to_string(&args[3]).map_err(|e| format!("failed to read {}: {}", args[3], e))?;
+                        let entries = text::parse_dump(&contents)?;
+                        let free_space = free_space.unwrap_or(text::default_free_space(&rom));
+                        let report = text::insert_strings(&mut rom, &charmap, &entries, free_space)?;
+                        if let Some(path) = output {
+                            rom.path = path;
+                        }
+                        rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                        let mut message = format!("{} strings unchanged, {} changed in place and {} relocated in {}",
+                                                  report.unchanged, report.in_place, report.relocated.len(), rom.path);
+                        for (pointer, address) in report.relocated.iter() {
+                            message.push_str(&format!("\n0x{:06X} now points to 0x{:06X}", pointer, address));
+                        }
+                        return Ok(message);
+                    }
+                    Some("check") => {
+                        let font = take_option(&mut args, "--font")?.unwrap_or("normal".to_string());
+                        let widths = take_option(&mut args, "--widths")?;
+                        let rom_path = take_option(&mut args, "--rom")?;
+                        let offset = take_number(&mut args, "--offset")?;
+                        let max_width = take_number(&mut args, "--width")?.unwrap_or(208);
+                        let font_widths = match (widths, rom_path, &root) {
+                            (Some(path), _, _) => font::FontWidths::load(Path::new(&path))?,
+                            (None, Some(rom_path), _) => {
+                                let offset = offset.ok_or("--rom needs the --offset of the width table")?;
+                                let rom = Rom::from_file(&rom_path).map_err(|e| format!("failed to read {}: {}", rom_path, e))?;
+                                font::FontWidths::from_rom(&rom, offset)?
+                            }
+                            (None, None, Some(root)) => font::FontWidths::from_project(Path::new(root), &font)?,
+                            _ => return Err("missing --widths, --rom or --project to load the font widths from".to_string())
+                        };
+                        let path = args.get(2).ok_or("missing text file")?;
+                        let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+                        // text dumps have their own format, everything else is read as assembly
+                        let strings = if path.ends_with(".inc") || path.ends_with(".s") {
+                            text::parse_inc_strings(&contents)
+                        } else {
+                            text::dump_strings(&text::parse_dump(&contents)?)
+                        };
+                        let overflows = text::check_widths(&charmap, &font_widths, &strings, max_width)?;
+                        let mut message = format!("{} lines of {} strings are wider than {}px", overflows.len(), strings.len(), max_width);
+                        for overflow in overflows {
+                            message.push_str(&format!("\n{}", overflow));
+                        }
+                        return Ok(message);
+                    }
+                    _ => return Err("missing encode/decode/dump/insert/check argument".to_string())
+                }
+            }
+            "font" => {
+                let bpp = take_number(&mut args, "--bpp")?.unwrap_or(2);
+                let widths = take_option(&mut args, "--widths")?;
+                // options are taken first so they can come before the positional arguments
+                let offset = take_number(&mut args, "--offset")?;
+                let count = take_number(&mut args, "--count")?;
+                let widths_offset = take_number(&mut args, "--widths-offset")?;
+                let cell = match args.get(4) {
+                    Some(arg) => font::Glyphs::parse_cell(arg)?,
+                    None => return Err("missing glyph size".to_string())
+                };
+                match args.get(1).map(|s| s.as_str()) {
+                    Some("export") => {
+                        let glyph_bytes = (cell.0 / 8) * (cell.1 / 8) * 8 * bpp;
+                        let length = count.map(|count| count * glyph_bytes);
+                        let offset = offset.unwrap_or(0);
+                        let glyphs = font::Glyphs::from_bytes(&sprite::read_graphics(&args[2], offset, length)?, cell, bpp)?;
+                        glyphs.write_sheet(&args[3]);
+                        let mut message = format!("{} glyphs written to {}", glyphs.cells.frames.len(), args[3]);
+                        if let Some(path) = widths {
+                            // the width table is copied from the font data, or measured from the glyphs
+                            let font_widths = match widths_offset {
+                                Some(offset) => font::FontWidths::from_bytes(&sprite::read_graphics(&args[2], offset, Some(font::GLYPH_COUNT))?)?,
+                                None => {
+                                    let mut font_widths = font::FontWidths { widths: vec![0; font::GLYPH_COUNT] };
+                                    font_widths.update(&glyphs.measure());
+                                    font_widths
+                                }
+                            };
+                            fs::write(&path, &font_widths.widths).map_err(|e| format!("failed to write {}: {}", path, e))?;
+                            message.push_str(&format!("\nwidths written to {}", path));
+                        }
+                        return Ok(message);
+                    }
+                    Some("import") => {
+                        let glyphs = font::Glyphs::read_sheet(&args[2], cell, bpp)?;
+                        fs::write(&args[3], glyphs.to_bytes()).map_err(|e| format!("failed to write {}: {}", args[3], e))?;
+                        let mut message = format!("{} glyphs written to {}", glyphs.cells.frames.len(), args[3]);
+                        if let Some(path) = widths {
+                            let mut font_widths = if Path::new(&path).exists() {
+                                font::FontWidths::load(Path::new(&path))?
+                            } else {
+                                font::FontWidths { widths: vec![0; font::GLYPH_COUNT] }
+                            };
+                            let changed = font_widths.update(&glyphs.measure());
+                            fs::write(&path, &font_widths.widths).map_err(|e| format!("failed to write {}: {}", path, e))?;
+                            message.push_str(&format!("\n{} widths changed in {}", changed, path));
+                        }
+                        return Ok(message);
+                    }
+                    _ => return Err("missing export/import argument".to_string())
+                }
+            }
+            "script" if args.get(1).map(|a| a.as_str()) == Some("decompile") => {
+                let root = take_option(&mut args, "--project")?;
+                let charmap = open_charmap(&mut args, &root)?;
+                let output = take_option(&mut args, "-o")?;
+                let prefix = take_option(&mut args, "--prefix")?.unwrap_or("Rom".to_string());
+                if args.len() < 4 {
+                    return Err("usage: script decompile <rom> <offset...>".to_string());
+                }
+                let rom = Rom::from_file(&args[2]).map_err(|e| format!("failed to read {}: {}", args[2], e))?;
+                let game = rom.game().unwrap_or(Game::Emerald);
+                let mut decompiler = script::Decompiler::new(&rom, &charmap, game, &prefix);
+                for offset in args[3..].iter() {
+                    let offset = parse_number_argument(offset, "script offset")?;
+                    decompiler.add_script(offset)?;
+                }
+                let scripts = decompiler.format();
+                return match output {
+                    Some(path) => {
+                        fs::write(&path, scripts).map_err(|e| format!("failed to write {}: {}", path, e))?;
+                        Ok(format!("scripts of {} written to {}", game.name(), path))
+                    }
+                    None => Ok(scripts.trim_end().to_string())
+                };
+            }
+            "script" if args.get(1).map(|a| a == "compile" || a == "insert").unwrap_or(false) => {
+                let root = take_option(&mut args, "--project")?;
+                let charmap = open_charmap(&mut args, &root)?;
+                let output = take_option(&mut args, "-o")?;
+                let free_space = take_number(&mut args, "--free-space")?;
+                let path = args.get(2).ok_or("missing script file")?.clone();
+                let source = fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+                let program = compiler::compile(&source).map_err(|e| e.format(&path, &source))?;
+                if args[1] == "compile" {
+                    let inc = program.to_inc();
+                    return match output {
+                        Some(output) => {
+                            fs::write(&output, inc).map_err(|e| format!("failed to write {}: {}", output, e))?;
+                            Ok(format!("{} scripts written to {}", program.scripts.len(), output))
+                        }
+                        None => Ok(inc.trim_end().to_string())
+                    };
+                }
+
+                let rom_path = args.get(3).ok_or("missing rom")?;
+                let mut rom = Rom::from_file(rom_path).map_err(|e| format!("failed to read {}: {}", rom_path, e))?;
+                let game = rom.game().unwrap_or(Game::Emerald);
+                // the values of constants like FLAG_ or ITEM_ come from the decomp
+                let constants = match &root {
+                    Some(root) => {
+                        let mut constants = constants::Constants::load(Path::new(root))?;
+                        for (value, name) in script::MSGBOX_TYPES.iter() {
+                            if constants.get(name).is_none() {
+                                constants.define(name, *value);
+                            }
+                        }
+                        constants
+                    }
+                    None => compiler::default_constants()
+                };
+                // the size doesn't depend on where the scripts go, so they're assembled once to find space for them
+                let (bytes, _) = program.assemble(game, &charmap, &constants, 0).map_err(|e| e.format(&path, &source))?;
+                let start = free_space.unwrap_or(text::default_free_space(&rom));
+                let offset = rom.find_free_space(start, bytes.len())
+                    .ok_or(format!("no free space left for the 0x{:X} bytes of the scripts", bytes.len()))?;
+                let (bytes, labels) = program.assemble(game, &charmap, &constants, offset).map_err(|e| e.format(&path, &source))?;
+                rom.write_bytes(offset, &bytes)?;
+                if let Some(output) = output {
+                    rom.path = output;
+                }
+                rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                let mut message = format!("0x{:X} bytes of scripts written at 0x{:X} in {}", bytes.len(), offset, rom.path);
+                for (label, address) in labels.iter() {
+                    message.push_str(&format!("\n0x{:06X} {}", address, label));
+                }
+                return Ok(message);
+            }
+            "encounters" => {
+                let root = take_option(&mut args, "--project")?;
+                let rom_path = take_option(&mut args, "--rom")?;
+                let table = take_number(&mut args, "--table")?;
+                let output = take_option(&mut args, "-o")?;
+                let constants = match &root {
+                    Some(root) => constants::Constants::load(Path::new(root))?,
+                    None => constants::Constants::new()
+                };
+                let open_rom = |path: &str| -> Result<(Rom, usize), String> {
+                    let rom = Rom::from_file(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+                    let table = match table {
+                        Some(offset) => offset,
+                        None => encounters::wild_mon_headers(&rom)?
+                    };
+                    Ok((rom, table))
+                };
+                let command = args.get(1).map(|s| s.as_str()).unwrap_or("");
+                if command == "export" || command == "import" {
+                    if args.len() < 4 {
+                        return Err("usage: encounters export <rom> <wild_encounters.json> or encounters import <wild_encounters.json> <rom>".to_string());
+                    }
+                    if command == "export" {
+                        let (rom, table) = open_rom(&args[2])?;
+                        let group = encounters::read_rom(&rom, table)?;
+                        let count = group.encounters.len();
+                        porymap::WildEncounters { groups: vec![group], fields: Vec::new() }.save(Path::new(&args[3]))?;
+                        return Ok(format!("Encounters of {} maps written to {}", count, args[3]));
+                    }
+                    let wild_encounters = porymap::WildEncounters::load(Path::new(&args[2]))?;
+                    let group = wild_encounters.groups.iter().find(|g| g.for_maps)
+                        .ok_or(format!("{} has no encounters for maps", args[2]))?;
+                    let (mut rom, table) = open_rom(&args[3])?;
+                    let free_space = text::default_free_space(&rom);
+                    let mut message = String::new();
+                    for line in encounters::write_rom(&mut rom, table, group, &constants, free_space)? {
+                        message.push_str(&format!("{}\n", line));
+                    }
+                    if let Some(output) = output {
+                        rom.path = output;
+                    }
+                    rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                    return Ok(format!("{}Encounters of {} maps written to {}", message, group.encounters.len(), rom.path));
+                }
+
+                // the encounters come from the wild_encounters.json of a decomp, or from the headers of a ROM
+                let (mut wild_encounters, source) = match &rom_path {
+                    Some(rom_path) => {
+                        let (rom, table) = open_rom(rom_path)?;
+                        let group = encounters::read_rom(&rom, table)?;
+                        (porymap::WildEncounters { groups: vec![group], fields: Vec::new() }, Source::Rom(rom, table))
+                    }
+                    None => {
+                        let project = Project::open(root.as_ref().map(|r| r.as_str()).unwrap_or("."))?;
+                        let path = project.wild_encounters_path();
+                        (porymap::WildEncounters::load(&path)?, Source::Project(path))
+                    }
+                };
+                match command {
+                    "list" => {
+                        let mut summaries = Vec::new();
+                        for group in wild_encounters.groups.iter() {
+                            for map in group.encounters.iter() {
+                                if args.len() < 3 || map.map.as_ref() == Some(&args[2]) || map.base_label == args[2] {
+                                    summaries.push(encounters::summary(group, map));
+                                }
+                            }
+                        }
+                        if summaries.is_empty() {
+                            return Err("no encounters found".to_string());
+                        }
+                        return Ok(summaries.join("\n"));
+                    }
+                    "check" => {
+                        let problems: Vec<String> = wild_encounters.groups.iter().flat_map(encounters::check).collect();
+                        if !problems.is_empty() {
+                            return Err(problems.join("\n"));
+                        }
+                        let maps: usize = wild_encounters.groups.iter().map(|g| g.encounters.len()).sum();
+                        return Ok(format!("The encounters of {} maps are valid", maps));
+                    }
+                    "set" | "rate" => {
+                        let number = |index: usize, name: &str| -> Result<i64, String> {
+                            Ok(parse_number_argument(args.get(index).ok_or(format!("missing {}", name))?, name)? as i64)
+                        };
+                        let map_name = args.get(2).ok_or("missing map")?.clone();
+                        let kind = encounters::parse_type(args.get(3).ok_or("missing encounter type")?)?;
+                        let group = wild_encounters.groups.iter_mut().find(|g| g.find(&map_name).is_some())
+                            .ok_or(format!("{} has no encounters", map_name))?;
+                        let table = group.find_mut(&map_name).and_then(|map| map.table_mut(kind))
+                            .ok_or(format!("{} has no {}", map_name, kind))?;
+                        if command == "rate" {
+                            table.encounter_rate = number(4, "rate")?;
+                        } else {
+                            let slot = number(4, "slot")? as usize;
+                            let species = args.get(5).ok_or("missing species")?.clone();
+                            let (min_level, max_level) = (number(6, "min level")?, number(7, "max level")?);
+                            let mon = table.mons.get_mut(slot).ok_or(format!("{} of {} have no slot {}", kind, map_name, slot))?;
+                            mon.species = species;
+                            mon.min_level = min_level;
+                            mon.max_level = max_level;
+                        }
+                        let problems = encounters::check(group);
+                        if !problems.is_empty() {
+                            return Err(problems.join("\n"));
+                        }
+                    }
+                    _ => return Err("missing list/check/set/rate/export/import argument".to_string())
+                }
+                return match source {
+                    Source::Project(path) => {
+                        wild_encounters.save(&path)?;
+                        Ok(format!("Encounters written to {}", path.display()))
+                    }
+                    Source::Rom(mut rom, table) => {
+                        let free_space = text::default_free_space(&rom);
+                        encounters::write_rom(&mut rom, table, &wild_encounters.groups[0], &constants, free_space)?;
+                        if let Some(output) = output {
+                            rom.path = output;
+                        }
+                        rom.write_to_file().map_err(|e| format!("failed to write {}: {}", rom.path, e))?;
+                        Ok(format!("Encounters written to {}", rom.path))
+                    }
+                };
             }
             "palette" => {
                 let image = match args.get(1) {
@@ -125,11 +1114,110 @@ fn inner_main(args: Vec<String>) -> Result<String, String> {
 fn print_help() {
     println!("*.*.*.* Pokerus v{} *.*.*.* ", VERSION);
     println!("Available Commands:");
+    println!("Numbers can be decimal or hex with 0x, like 412 or 0x3203CC.");
     println!("- pokerus");
     println!("    Launches the GUI. (WIP)");
-    println!("- pokerus tileset <primary/secondary> <output_folder> <metatile_definitions> <input_images...>");
+    println!("- pokerus tileset <primary/secondary> <output_folder> <metatile_definitions> <input_images...> [options]");
     println!("    Merges tilesets and their palettes into one image.");
     println!("    Useful for importing into Porymap.");
+    println!("    --verify re-renders every metatile from the output and compares it to the input images.");
+    println!("    --preview writes metatiles_preview.png with every metatile in full color.");
+    println!("    --incremental keeps the tile and palette indices of the previous build in the output folder,");
+    println!("    changed palettes keep their slot and tiles nothing uses anymore are released.");
+    println!("    --primary <folder> lets a secondary tileset reuse the tiles and palettes of a built primary tileset.");
+    println!("    --animations <file> reserves tiles for animations (name first-last frames...) and writes their frames to anim/.");
+    println!("    --emit-c <name> writes the C sources for gTileset_<name> and adds them to the decomp the output folder is in.");
+    println!("    --transparent-color <RRGGBB> or --transparent-index <0-15> sets the transparent color of the input images.");
+    println!("    --project <decomp_root> resolves the output folder and --primary by tileset name.");
+    println!("- pokerus tileset usage <tileset> [--project <decomp_root>]");
+    println!("    Lists the metatiles no layout uses and the tiles only those metatiles use.");
+    println!("- pokerus tileset remap <tileset> <old_definitions> <new_definitions> [--project <decomp_root>]");
+    println!("    Rewrites the map.bin and border.bin of every layout using the tileset after its metatiles were reordered.");
+    println!("- pokerus tilesets [--project <decomp_root>]");
+    println!("    Lists the tilesets of a pokeemerald, pokefirered or pokeruby project with their sizes.");
+    println!("- pokerus maps [--project <decomp_root>] [--check]");
+    println!("    Lists the maps of every map group with their layouts.");
+    println!("    --check reads map_groups.json, layouts.json and every map.json and checks they would be written back unchanged.");
+    println!("- pokerus map render <layout> [--tilesets <primary> <secondary>] [-o <map.png>] [--overlay]");
+    println!("    Renders a layout with its tilesets. --overlay shows the collision and elevation of every block.");
+    println!("- pokerus map set <layout> <x> <y> <metatile> [--collision <0-3>] [--elevation <0-15>]");
+    println!("    Changes a block of a layout.");
+    println!("    Layouts are found by name with --project <decomp_root>, or are map.bin files with --width <blocks>.");
+    println!("- pokerus map events list <map> [--project <decomp_root>] [--rom <rom> [--map-table <offset>]]");
+    println!("    Lists the object, warp, coord and bg events of a map, numbered like they are moved.");
+    println!("    Maps are map.json files found by name with --project, or <group>.<number> in a ROM with --rom.");
+    println!("    gMapGroups is found in English Emerald, FireRed and Ruby v1.0, other ROMs need --map-table.");
+    println!("- pokerus map events add <map> <object|clone|warp|trigger|weather|sign|hidden_item|secret_base> <x> <y> [<field>=<value>...] [-o <output.gba>]");
+    println!("    Adds an event with the fields of map.json files, like script=Route101_EventScript_Boy. Other fields are 0.");
+    println!("    Events written to ROMs can only have numbers. Event tables that grow are moved to free space.");
+    println!("    Hidden item flags are the flags themselves, FireRed hidden items also have a quantity and underfoot.");
+    println!("- pokerus map events move <map> <object|warp|coord|bg> <index> <x> <y> [-o <output.gba>]");
+    println!("    Moves an event.");
+    println!("- pokerus map import <image.png> <output_folder> [--primary <tileset>]");
+    println!("    Cuts an image of a whole map into a tileset and writes a map.bin that reproduces it.");
+    println!("    --primary builds a secondary tileset that reuses the tiles and palettes of the primary tileset.");
+    println!("- pokerus sprite build <sheet.png> <output.4bpp> <WxH> [--table <name>]");
+    println!("    Converts the frames of an indexed sprite sheet to .4bpp in OBJ tile order (1D mapping).");
+    println!("    --table prints the overworld frame table for gObjectEventPic_<name>.");
+    println!("- pokerus sprite extract <graphics> <output.png> <WxH> [--palette <file.pal>] [--offset <n>] [--frames <n>] [--lz]");
+    println!("    Converts .4bpp graphics, or graphics in a ROM at an offset, back to a sprite sheet.");
+    println!("    --lz decompresses LZ77 graphics like .4bpp.lz files first.");
+    println!("- pokerus species gfx <folder> [--project <decomp_root>]");
+    println!("    Validates the front, back and icon pics and the normal and shiny palettes of a species,");
+    println!("    and writes the .4bpp.lz, .gbapal.lz and icon.4bpp files the decomp builds from them.");
+    println!("    Emerald needs anim_front.png with 2 frames (64x128), front.png is optional and defaults to the first frame.");
+    println!("- pokerus species icon <icon.png> <output.png> [--project <decomp_root>]");
+    println!("    Remaps an icon to the shared icon palette that looks closest to it.");
+    println!("    Transparent pixels are index 0, alpha, or the color of the top left pixel of images without alpha.");
+    println!("- pokerus species stats export <stats.csv> [--project <decomp_root>] [--rom <rom> [--table <offset>] [--count <412>]]");
+    println!("    Writes the base stats of every species to a CSV file, from species_info.h or base_stats.h with --project,");
+    println!("    or from the base stats table of a ROM with --rom.");
+    println!("- pokerus species stats import <stats.csv> [--project <decomp_root>] [--rom <rom> [--table <offset>] [--count <412>] [-o <output.gba>]]");
+    println!("    Writes edited base stats back. Types, abilities, growth rates and EV yields are checked before anything is written.");
+    println!("    Decomp sources are changed in place, ROMs need numbers unless constants are read with --project.");
+    println!("    The table is found in English Emerald, FireRed and Ruby v1.0, other ROMs need --table.");
+    println!("- pokerus text encode <text> [--project <decomp_root>] [--japanese]");
+    println!("    Encodes a string written like the strings of the decomps, like \"Hi {{PLAYER}}!\\n\".");
+    println!("- pokerus text decode <hex bytes...> [--project <decomp_root>] [--japanese]");
+    println!("    Decodes a string up to its terminator.");
+    println!("- pokerus text dump <rom> --table <offset,count> [-o <dump.txt>]");
+    println!("    Writes the strings of a table of pointers to an editable file, with their pointers.");
+    println!("    The table is an offset and a count, like --table 0x3E1F00,20.");
+    println!("- pokerus text insert <rom> <dump.txt> [-o <output.gba>] [--free-space <offset>]");
+    println!("    Writes edited strings back. Strings that grew are moved to free space and repointed.");
+    println!("- pokerus text check <file.inc|dump.txt> [--font <normal>] [--width <208>] [--widths <file.bin>] [--rom <rom> --offset <offset>]");
+    println!("    Lists the lines of the strings that are wider than the message box with the widths of a font.");
+    println!("    The widths are read from a file, from a ROM, or from src/fonts.c with --project, like gFontNormalLatinGlyphWidths for --font normal.");
+    println!("    Without --project the built in international or Japanese (--japanese) character set is used.");
+    println!("- pokerus font export <glyphs> <sheet.png> <8x8|8x16|16x16> [--bpp <2|4>] [--offset <n>] [--count <n>] [--widths <widths.bin>] [--widths-offset <n>]");
+    println!("    Converts 2bpp or 4bpp font glyphs, from a file or a ROM at an offset, to a glyph sheet of 16 glyphs per row.");
+    println!("    --widths writes the width table at --widths-offset, or the widths measured from the glyphs.");
+    println!("- pokerus font import <sheet.png> <output> <8x8|8x16|16x16> [--bpp <2|4>] [--widths <widths.bin>]");
+    println!("    Converts a glyph sheet back to font glyphs.");
+    println!("    --widths updates the width table with the width of every glyph that isn't blank.");
+    println!("- pokerus script decompile <rom> <offset...> [-o <scripts.inc>] [--prefix <name>] [--project <decomp_root>] [--japanese]");
+    println!("    Decompiles the event scripts at the offsets, and the scripts, texts, movements and marts they lead to.");
+    println!("    The labels start with --prefix, like <name>_EventScript_1A2B3C.");
+    println!("- pokerus script compile <script.pory> [-o <scripts.inc>]");
+    println!("    Compiles scripts with if/else, switch, while and do/while, and text, movement and mart blocks");
+    println!("    to the assembly of the decomps.");
+    println!("- pokerus script insert <script.pory> <rom> [-o <output.gba>] [--free-space <offset>] [--project <decomp_root>] [--japanese]");
+    println!("    Compiles scripts to bytecode and writes them to free space in a ROM.");
+    println!("    Constants like FLAG_ and ITEM_ are read from include/constants with --project, otherwise only numbers can be used.");
+    println!("- pokerus encounters list [<map>] [--project <decomp_root>] [--rom <rom> [--table <offset>]]");
+    println!("    Lists the encounter rate of every table of the maps, and the chance and levels of every species.");
+    println!("    Maps are read from src/data/wild_encounters.json with --project, or are <group>.<number> in a ROM with --rom.");
+    println!("- pokerus encounters check [--project <decomp_root>] [--rom <rom> [--table <offset>]]");
+    println!("    Checks that land tables have 12 slots, water and rock smash tables 5 and fishing tables 10,");
+    println!("    and that the levels are between 1 and 100.");
+    println!("- pokerus encounters set <map> <land|water|rock_smash|fishing> <slot> <species> <min_level> <max_level> [-o <output.gba>]");
+    println!("- pokerus encounters rate <map> <land|water|rock_smash|fishing> <rate> [-o <output.gba>]");
+    println!("    Changes a slot or the encounter rate of a table. Species of ROMs are numbers, or SPECIES_ constants with --project.");
+    println!("- pokerus encounters export <rom> <wild_encounters.json> [--table <offset>]");
+    println!("    Writes the encounters of a ROM like the wild_encounters.json of the decomps.");
+    println!("- pokerus encounters import <wild_encounters.json> <rom> [-o <output.gba>] [--table <offset>] [--project <decomp_root>]");
+    println!("    Writes the encounters for maps back to a ROM. Tables and maps the ROM doesn't have yet go to free space.");
+    println!("    gWildMonHeaders is found in English Emerald, FireRed and Ruby v1.0, other ROMs need --table.");
     println!("- pokerus palette <image> <output.pal>");
     println!("    Extract the palette of an image to a .pal file.");
 }
@@ -0,0 +1,552 @@
+use std::fs;
+use std::path::Path;
+
+use crate::json::{Json, merge_object};
+
+fn read_json(path: &Path) -> Result<Json, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Json::parse(&contents).map_err(|e| format!("failed to parse {}: {}", path.display(), e))
+}
+
+fn write_json(path: &Path, json: &Json) -> Result<(), String> {
+    fs::write(path, json.to_porymap_string()).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+/// whether writing `json` back to `path` would leave the file as it is
+pub fn round_trips(path: &Path, json: &Json) -> Result<bool, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok(contents == json.to_porymap_string())
+}
+
+fn string_array(json: &Json, key: &str) -> Result<Vec<String>, String> {
+    json.get(key).and_then(|v| v.as_array())
+        .ok_or(format!("missing array field \"{}\"", key))?
+        .iter()
+        .map(|v| v.as_str().map(|s| s.to_string()).ok_or(format!("\"{}\" must only contain strings", key)))
+        .collect()
+}
+
+fn to_string_array(values: &[String]) -> Json {
+    Json::Array(values.iter().map(|v| Json::String(v.clone())).collect())
+}
+
+fn string(value: &str) -> Option<Json> {
+    Some(Json::String(value.to_string()))
+}
+
+/// data/maps/map_groups.json
+#[derive(Clone)]
+pub struct MapGroups {
+    pub group_order: Vec<String>,
+    /// the maps of every group, in the order of `group_order`
+    pub groups: Vec<(String, Vec<String>)>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl MapGroups {
+    pub fn load(path: &Path) -> Result<MapGroups, String> {
+        MapGroups::from_json(&read_json(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, &self.to_json())
+    }
+
+    pub fn from_json(json: &Json) -> Result<MapGroups, String> {
+        let group_order = string_array(json, "group_order")?;
+        let mut groups = Vec::with_capacity(group_order.len());
+        for group in group_order.iter() {
+            groups.push((group.clone(), string_array(json, group)?));
+        }
+        Ok(MapGroups {
+            group_order,
+            groups,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![("group_order", Some(to_string_array(&self.group_order)))];
+        for (group, maps) in self.groups.iter() {
+            fields.push((group, Some(to_string_array(maps))));
+        }
+        merge_object(&self.fields, fields)
+    }
+
+    /// returns the group of a map and its index in the group
+    pub fn find_map(&self, map_name: &str) -> Option<(usize, usize)> {
+        for (group_index, (_, maps)) in self.groups.iter().enumerate() {
+            if let Some(map_index) = maps.iter().position(|m| m == map_name) {
+                return Some((group_index, map_index));
+            }
+        }
+        None
+    }
+}
+
+/// data/layouts/layouts.json
+#[derive(Clone)]
+pub struct Layouts {
+    pub layouts_table_label: String,
+    /// unused layout ids are empty objects in the file
+    pub layouts: Vec<Option<Layout>>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl Layouts {
+    pub fn load(path: &Path) -> Result<Layouts, String> {
+        Layouts::from_json(&read_json(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, &self.to_json())
+    }
+
+    pub fn from_json(json: &Json) -> Result<Layouts, String> {
+        let mut layouts = Vec::new();
+        for layout in json.get("layouts").and_then(|v| v.as_array()).ok_or("missing array field \"layouts\"")?.iter() {
+            match layout.as_object() {
+                Some(fields) if fields.is_empty() => layouts.push(None),
+                Some(_) => layouts.push(Some(Layout::from_json(layout)?)),
+                None => return Err("layouts must only contain objects".to_string())
+            }
+        }
+        Ok(Layouts {
+            layouts_table_label: json.string_field("layouts_table_label")?,
+            layouts,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        let layouts = self.layouts.iter()
+            .map(|layout| layout.as_ref().map(|l| l.to_json()).unwrap_or(Json::Object(Vec::new())))
+            .collect();
+        merge_object(&self.fields, vec![
+            ("layouts_table_label", string(&self.layouts_table_label)),
+            ("layouts", Some(Json::Array(layouts))),
+        ])
+    }
+
+    /// returns a layout by its id (LAYOUT_PETALBURG_CITY) or name (PetalburgCity_Layout)
+    pub fn find(&self, layout: &str) -> Option<&Layout> {
+        self.layouts.iter()
+            .filter_map(|l| l.as_ref())
+            .find(|l| l.id == layout || l.name == layout)
+    }
+}
+
+#[derive(Clone)]
+pub struct Layout {
+    pub id: String,
+    pub name: String,
+    pub width: i64,
+    pub height: i64,
+    /// only pokefirered has custom border sizes
+    pub border_width: Option<i64>,
+    pub border_height: Option<i64>,
+    pub primary_tileset: String,
+    pub secondary_tileset: String,
+    pub border_filepath: String,
+    pub blockdata_filepath: String,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl Layout {
+    pub fn from_json(json: &Json) -> Result<Layout, String> {
+        Ok(Layout {
+            id: json.string_field("id")?,
+            name: json.string_field("name")?,
+            width: json.integer_field("width")?,
+            height: json.integer_field("height")?,
+            border_width: json.get("border_width").and_then(|v| v.as_i64()),
+            border_height: json.get("border_height").and_then(|v| v.as_i64()),
+            primary_tileset: json.string_field("primary_tileset")?,
+            secondary_tileset: json.string_field("secondary_tileset")?,
+            border_filepath: json.string_field("border_filepath")?,
+            blockdata_filepath: json.string_field("blockdata_filepath")?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("id", string(&self.id)),
+            ("name", string(&self.name)),
+            ("width", Some(Json::Integer(self.width))),
+            ("height", Some(Json::Integer(self.height))),
+            ("border_width", self.border_width.map(Json::Integer)),
+            ("border_height", self.border_height.map(Json::Integer)),
+            ("primary_tileset", string(&self.primary_tileset)),
+            ("secondary_tileset", string(&self.secondary_tileset)),
+            ("border_filepath", string(&self.border_filepath)),
+            ("blockdata_filepath", string(&self.blockdata_filepath)),
+        ])
+    }
+}
+
+/// data/maps/<map>/map.json
+#[derive(Clone)]
+pub struct Map {
+    pub id: String,
+    pub name: String,
+    pub layout: String,
+    pub music: String,
+    pub region_map_section: String,
+    pub requires_flash: Option<bool>,
+    pub weather: String,
+    pub map_type: String,
+    /// pokeruby doesn't have the allow_* fields
+    pub allow_cycling: Option<bool>,
+    pub allow_escaping: Option<bool>,
+    pub allow_running: Option<bool>,
+    pub show_map_name: Option<bool>,
+    /// only pokefirered has floor numbers
+    pub floor_number: Option<i64>,
+    pub battle_scene: String,
+    /// maps without connections have null instead of an empty list
+    pub connections: Option<Vec<Connection>>,
+    pub object_events: Vec<Json>,
+    pub warp_events: Vec<Json>,
+    pub coord_events: Vec<Json>,
+    pub bg_events: Vec<Json>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Map, String> {
+        Map::from_json(&read_json(path)?).map_err(|e| format!("invalid map {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, &self.to_json())
+    }
+
+    pub fn from_json(json: &Json) -> Result<Map, String> {
+        let connections = match json.get("connections") {
+            Some(Json::Array(connections)) => Some(connections.iter().map(Connection::from_json).collect::<Result<Vec<_>, _>>()?),
+            _ => None
+        };
+        let events = |key: &str| json.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        Ok(Map {
+            id: json.string_field("id")?,
+            name: json.string_field("name")?,
+            layout: json.string_field("layout")?,
+            music: json.string_field("music")?,
+            region_map_section: json.string_field("region_map_section")?,
+            requires_flash: json.get("requires_flash").and_then(|v| v.as_bool()),
+            weather: json.string_field("weather")?,
+            map_type: json.string_field("map_type")?,
+            allow_cycling: json.get("allow_cycling").and_then(|v| v.as_bool()),
+            allow_escaping: json.get("allow_escaping").and_then(|v| v.as_bool()),
+            allow_running: json.get("allow_running").and_then(|v| v.as_bool()),
+            show_map_name: json.get("show_map_name").and_then(|v| v.as_bool()),
+            floor_number: json.get("floor_number").and_then(|v| v.as_i64()),
+            battle_scene: json.string_field("battle_scene")?,
+            connections,
+            object_events: events("object_events"),
+            warp_events: events("warp_events"),
+            coord_events: events("coord_events"),
+            bg_events: events("bg_events"),
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        let has_field = |key: &str| self.fields.iter().any(|(k, _)| k == key);
+        let connections = match &self.connections {
+            Some(connections) => Some(Json::Array(connections.iter().map(|c| c.to_json()).collect())),
+            None if has_field("connections") => Some(Json::Null),
+            None => None
+        };
+        // event lists are only added to maps that didn't have them when there are events
+        let events = |key: &str, events: &Vec<Json>| {
+            if events.is_empty() && !has_field(key) { None } else { Some(Json::Array(events.clone())) }
+        };
+        merge_object(&self.fields, vec![
+            ("id", string(&self.id)),
+            ("name", string(&self.name)),
+            ("layout", string(&self.layout)),
+            ("music", string(&self.music)),
+            ("region_map_section", string(&self.region_map_section)),
+            ("requires_flash", self.requires_flash.map(Json::Bool)),
+            ("weather", string(&self.weather)),
+            ("map_type", string(&self.map_type)),
+            ("allow_cycling", self.allow_cycling.map(Json::Bool)),
+            ("allow_escaping", self.allow_escaping.map(Json::Bool)),
+            ("allow_running", self.allow_running.map(Json::Bool)),
+            ("show_map_name", self.show_map_name.map(Json::Bool)),
+            ("floor_number", self.floor_number.map(Json::Integer)),
+            ("battle_scene", string(&self.battle_scene)),
+            ("connections", connections),
+            ("object_events", events("object_events", &self.object_events)),
+            ("warp_events", events("warp_events", &self.warp_events)),
+            ("coord_events", events("coord_events", &self.coord_events)),
+            ("bg_events", events("bg_events", &self.bg_events)),
+        ])
+    }
+}
+
+#[derive(Clone)]
+pub struct Connection {
+    pub map: String,
+    pub offset: i64,
+    pub direction: String,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl Connection {
+    pub fn from_json(json: &Json) -> Result<Connection, String> {
+        Ok(Connection {
+            map: json.string_field("map")?,
+            offset: json.integer_field("offset")?,
+            direction: json.string_field("direction")?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("map", string(&self.map)),
+            ("offset", Some(Json::Integer(self.offset))),
+            ("direction", string(&self.direction)),
+        ])
+    }
+}
+
+/// the encounter tables a map can have, in the order of the wild mon headers
+pub const ENCOUNTER_TYPES: [&str; 4] = ["land_mons", "water_mons", "rock_smash_mons", "fishing_mons"];
+
+/// src/data/wild_encounters.json
+#[derive(Clone)]
+pub struct WildEncounters {
+    pub groups: Vec<EncounterGroup>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl WildEncounters {
+    pub fn load(path: &Path) -> Result<WildEncounters, String> {
+        WildEncounters::from_json(&read_json(path)?).map_err(|e| format!("invalid wild encounters {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, &self.to_json())
+    }
+
+    pub fn from_json(json: &Json) -> Result<WildEncounters, String> {
+        let groups = json.get("wild_encounter_groups").and_then(|v| v.as_array())
+            .ok_or("missing array field \"wild_encounter_groups\"")?;
+        Ok(WildEncounters {
+            groups: groups.iter().map(EncounterGroup::from_json).collect::<Result<Vec<_>, _>>()?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("wild_encounter_groups", Some(Json::Array(self.groups.iter().map(|g| g.to_json()).collect()))),
+        ])
+    }
+}
+
+/// A table of headers like gWildMonHeaders, with the slot rates of its encounter types
+#[derive(Clone)]
+pub struct EncounterGroup {
+    pub label: String,
+    /// groups that aren't for maps, like the Battle Pike's, have no map in their encounters
+    pub for_maps: bool,
+    pub encounter_fields: Vec<EncounterField>,
+    pub encounters: Vec<MapEncounters>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl EncounterGroup {
+    pub fn from_json(json: &Json) -> Result<EncounterGroup, String> {
+        let array = |key: &str| json.get(key).and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        Ok(EncounterGroup {
+            label: json.string_field("label")?,
+            for_maps: json.get("for_maps").and_then(|v| v.as_bool()).unwrap_or(false),
+            encounter_fields: array("fields").iter().map(EncounterField::from_json).collect::<Result<Vec<_>, _>>()?,
+            encounters: array("encounters").iter().map(MapEncounters::from_json).collect::<Result<Vec<_>, _>>()?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("label", string(&self.label)),
+            ("for_maps", Some(Json::Bool(self.for_maps))),
+            ("fields", Some(Json::Array(self.encounter_fields.iter().map(|f| f.to_json()).collect()))),
+            ("encounters", Some(Json::Array(self.encounters.iter().map(|e| e.to_json()).collect()))),
+        ])
+    }
+
+    pub fn find(&self, map: &str) -> Option<&MapEncounters> {
+        self.encounters.iter().find(|e| e.map.as_ref().map(|m| m == map).unwrap_or(false) || e.base_label == map)
+    }
+
+    pub fn find_mut(&mut self, map: &str) -> Option<&mut MapEncounters> {
+        self.encounters.iter_mut().find(|e| e.map.as_ref().map(|m| m == map).unwrap_or(false) || e.base_label == map)
+    }
+
+    pub fn field(&self, kind: &str) -> Option<&EncounterField> {
+        self.encounter_fields.iter().find(|f| f.kind == kind)
+    }
+}
+
+/// The chance of every slot of an encounter type, and the slots of every fishing rod
+#[derive(Clone)]
+pub struct EncounterField {
+    pub kind: String,
+    pub encounter_rates: Vec<i64>,
+    pub groups: Vec<(String, Vec<i64>)>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl EncounterField {
+    pub fn from_json(json: &Json) -> Result<EncounterField, String> {
+        let integers = |json: &Json, key: &str| -> Result<Vec<i64>, String> {
+            json.as_array().ok_or(format!("\"{}\" must be an array", key))?
+                .iter()
+                .map(|v| v.as_i64().ok_or(format!("\"{}\" must only contain integers", key)))
+                .collect()
+        };
+        let groups = match json.get("groups").and_then(|v| v.as_object()) {
+            Some(groups) => groups.iter().map(|(name, slots)| Ok((name.clone(), integers(slots, name)?))).collect::<Result<Vec<_>, String>>()?,
+            None => Vec::new()
+        };
+        Ok(EncounterField {
+            kind: json.string_field("type")?,
+            encounter_rates: integers(json.get("encounter_rates").ok_or("missing field \"encounter_rates\"")?, "encounter_rates")?,
+            groups,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        let integers = |values: &Vec<i64>| Json::Array(values.iter().map(|v| Json::Integer(*v)).collect());
+        let groups = if self.groups.is_empty() {
+            None
+        } else {
+            Some(Json::Object(self.groups.iter().map(|(name, slots)| (name.clone(), integers(slots))).collect()))
+        };
+        merge_object(&self.fields, vec![
+            ("type", string(&self.kind)),
+            ("encounter_rates", Some(integers(&self.encounter_rates))),
+            ("groups", groups),
+        ])
+    }
+}
+
+/// The encounter tables of one map
+#[derive(Clone)]
+pub struct MapEncounters {
+    pub map: Option<String>,
+    pub base_label: String,
+    /// the tables by their type, like land_mons
+    pub tables: Vec<(String, EncounterTable)>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl MapEncounters {
+    pub fn from_json(json: &Json) -> Result<MapEncounters, String> {
+        let base_label = json.string_field("base_label")?;
+        let mut tables = Vec::new();
+        for kind in ENCOUNTER_TYPES.iter() {
+            if let Some(table) = json.get(kind) {
+                tables.push((kind.to_string(), EncounterTable::from_json(table).map_err(|e| format!("{} of {}: {}", kind, base_label, e))?));
+            }
+        }
+        Ok(MapEncounters {
+            map: json.get("map").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            base_label,
+            tables,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("map", self.map.as_ref().and_then(|map| string(map))),
+            ("base_label", string(&self.base_label)),
+        ];
+        for kind in ENCOUNTER_TYPES.iter() {
+            fields.push((kind, self.table(kind).map(|t| t.to_json())));
+        }
+        merge_object(&self.fields, fields)
+    }
+
+    pub fn table(&self, kind: &str) -> Option<&EncounterTable> {
+        self.tables.iter().find(|(k, _)| k == kind).map(|(_, table)| table)
+    }
+
+    pub fn table_mut(&mut self, kind: &str) -> Option<&mut EncounterTable> {
+        self.tables.iter_mut().find(|(k, _)| k == kind).map(|(_, table)| table)
+    }
+}
+
+#[derive(Clone)]
+pub struct EncounterTable {
+    pub encounter_rate: i64,
+    pub mons: Vec<WildMon>,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl EncounterTable {
+    pub fn from_json(json: &Json) -> Result<EncounterTable, String> {
+        let mons = json.get("mons").and_then(|v| v.as_array()).ok_or("missing array field \"mons\"")?;
+        Ok(EncounterTable {
+            encounter_rate: json.integer_field("encounter_rate")?,
+            mons: mons.iter().map(WildMon::from_json).collect::<Result<Vec<_>, _>>()?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("encounter_rate", Some(Json::Integer(self.encounter_rate))),
+            ("mons", Some(Json::Array(self.mons.iter().map(|m| m.to_json()).collect()))),
+        ])
+    }
+}
+
+#[derive(Clone)]
+pub struct WildMon {
+    pub min_level: i64,
+    pub max_level: i64,
+    pub species: String,
+    pub fields: Vec<(String, Json)>,
+}
+
+impl WildMon {
+    pub fn from_json(json: &Json) -> Result<WildMon, String> {
+        Ok(WildMon {
+            min_level: json.integer_field("min_level")?,
+            max_level: json.integer_field("max_level")?,
+            species: json.string_field("species")?,
+            fields: json.as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        merge_object(&self.fields, vec![
+            ("min_level", Some(Json::Integer(self.min_level))),
+            ("max_level", Some(Json::Integer(self.max_level))),
+            ("species", string(&self.species)),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_round_trip() {
+        // pokeruby maps have no allow_* fields, and a map can leave out requires_flash and show_map_name
+        let source = "{\n  \"id\": \"MAP_ROUTE101\",\n  \"name\": \"Route101\",\n  \"layout\": \"LAYOUT_ROUTE101\",\n  \"music\": \"MUS_ROUTE101\",\n  \"region_map_section\": \"MAPSEC_ROUTE_101\",\n  \"weather\": \"WEATHER_SUNNY\",\n  \"map_type\": \"MAP_TYPE_ROUTE\",\n  \"battle_scene\": \"MAP_BATTLE_SCENE_NORMAL\",\n  \"connections\": null,\n  \"object_events\": [],\n  \"warp_events\": [],\n  \"coord_events\": [],\n  \"bg_events\": [],\n  \"zoom\": 1.0\n}\n";
+        let map = Map::from_json(&Json::parse(source).unwrap()).unwrap();
+        assert_eq!(map.requires_flash, None);
+        assert_eq!(map.to_json().to_porymap_string(), source);
+    }
+}
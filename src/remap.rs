@@ -0,0 +1,84 @@
+use std::fs;
+
+use crate::decomp::{normalize_name, Project};
+use crate::layout::MapLayout;
+use crate::porymap::Layouts;
+use crate::tileset::parse_metatile_config;
+
+/// a metatile is identified by the sheets and indices of its bottom and top layer
+type MetatileDefinition = ((String, usize), (String, usize));
+
+fn read_definitions(path: &str) -> Result<Vec<MetatileDefinition>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let refs = parse_metatile_config(contents.lines().map(|l| l.to_string()).collect());
+    Ok(refs.chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect())
+}
+
+/// Returns the new index of every metatile of the old definitions file,
+/// or None if the metatile isn't in the new definitions file anymore
+pub fn metatile_mapping(old_definitions: &str, new_definitions: &str) -> Result<Vec<Option<usize>>, String> {
+    let old = read_definitions(old_definitions)?;
+    let new = read_definitions(new_definitions)?;
+    Ok(old.iter()
+        .map(|metatile| new.iter().position(|m| m == metatile))
+        .collect())
+}
+
+/// Rewrites the map.bin and border.bin of every layout using the tileset with the mapping
+/// and returns the files that changed. Nothing is written if a layout
+/// uses a metatile that was removed.
+pub fn remap_layouts(project: &Project, tileset: &str, mapping: &[Option<usize>]) -> Result<Vec<String>, String> {
+    let (folder, primary) = project.find_tileset(tileset)
+        .ok_or(format!("couldn't find tileset {} in {}", tileset, project.root.display()))?;
+    let offset = if primary { 0 } else { project.game.primary_tile_count() };
+    let wanted = normalize_name(&folder.file_name().unwrap_or_default().to_string_lossy());
+
+    let mut changed = Vec::new();
+    let mut removed = Vec::new();
+    let all_layouts = Layouts::load(&project.layouts_path())?;
+    for layout in all_layouts.layouts.iter().filter_map(|l| l.as_ref()) {
+        let own = if primary { &layout.primary_tileset } else { &layout.secondary_tileset };
+        if normalize_name(own) != wanted {
+            continue;
+        }
+        let files = [
+            (&layout.blockdata_filepath, layout.width as usize),
+            (&layout.border_filepath, layout.border_width.unwrap_or(2) as usize),
+        ];
+        for (file, width) in files.iter() {
+            let path = project.root.join(file);
+            let mut blocks = MapLayout::load(&path, *width)?;
+            let mut modified = false;
+            for (i, block) in blocks.blocks.iter_mut().enumerate() {
+                if block.metatile < offset || block.metatile - offset >= mapping.len() {
+                    continue;
+                }
+                match mapping[block.metatile - offset] {
+                    Some(new_id) if new_id + offset != block.metatile => {
+                        block.metatile = new_id + offset;
+                        modified = true;
+                    }
+                    Some(_) => {}
+                    None => removed.push(format!("{} ({}, {}) uses removed metatile 0x{:03X}",
+                                                 file, i % blocks.width, i / blocks.width, block.metatile))
+                }
+            }
+            if modified {
+                changed.push((path, blocks));
+            }
+        }
+    }
+    if !removed.is_empty() {
+        return Err(format!("{} blocks use metatiles that aren't in the new definitions:\n{}", removed.len(), removed.join("\n")));
+    }
+
+    let mut names = Vec::with_capacity(changed.len());
+    for (path, blocks) in changed.iter() {
+        blocks.save(path)?;
+        names.push(path.strip_prefix(&project.root).unwrap_or(path).display().to_string());
+    }
+    Ok(names)
+}
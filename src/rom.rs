@@ -1,27 +1,125 @@
 use std::fs::File;
-use std::io::{Seek, SeekFrom, Read, Write};
+use std::io::{Read, Write};
 
-struct Rom {
-    path: String,
-    buffer: Vec<u8>,
+use crate::decomp::Game;
+
+/// where the cartridge is mapped in memory, which pointers in the ROM are relative to
+pub const ROM_BASE: usize = 0x0800_0000;
+/// the largest cartridge the GBA can address
+const MAX_ROM_SIZE: usize = 0x0200_0000;
+/// where the four letter game code is in the cartridge header, like BPEE for Emerald
+const GAME_CODE_OFFSET: usize = 0xac;
+/// the revision of the game, 0 for the first release and 1 for a v1.1
+const REVISION_OFFSET: usize = 0xbc;
+/// unused bytes of a ROM are 0xFF
+pub const FREE_BYTE: u8 = 0xff;
+
+pub struct Rom {
+    pub path: String,
+    pub buffer: Vec<u8>,
 }
 
 impl Rom {
     pub fn from_file(file_name: &str) -> std::io::Result<Rom> {
-        let mut file = File::open("foo.txt")?;
-        let size = file.seek(SeekFrom::End(0))? as usize;
-        let mut buffer = Vec::with_capacity(size);
+        let mut file = File::open(file_name)?;
+        let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
         Ok(Rom { path: file_name.to_string(), buffer })
     }
 
     pub fn write_to_file(&self) -> std::io::Result<()> {
-        let mut file = File::open(&self.path)?;
+        let mut file = File::create(&self.path)?;
         file.write_all(self.buffer.as_slice())?;
         Ok(())
     }
 
+    /// detects the game from the game code in the header, ignoring its language
+    pub fn game(&self) -> Option<Game> {
+        match self.buffer.get(GAME_CODE_OFFSET..GAME_CODE_OFFSET + 3)? {
+            b"BPE" => Some(Game::Emerald),
+            b"BPR" | b"BPG" => Some(Game::FireRed),
+            b"AXV" | b"AXP" => Some(Game::Ruby),
+            _ => None
+        }
+    }
+
+    /// the four letter game code and the revision of the header, like BPEE and 0 for English Emerald
+    pub fn version(&self) -> Option<(String, u8)> {
+        let code = self.buffer.get(GAME_CODE_OFFSET..GAME_CODE_OFFSET + 4)?;
+        Some((String::from_utf8_lossy(code).to_string(), self.read_byte(REVISION_OFFSET)?))
+    }
+
+    /// Looks up where a table is in this ROM from the versions it is known for. Other languages
+    /// and revisions move the tables around, so they need the offset passed with `option`.
+    pub fn known_offset(&self, table: &str, option: &str, offsets: &[(&str, u8, usize)]) -> Result<usize, String> {
+        let (code, revision) = self.version().unwrap_or_default();
+        offsets.iter()
+            .find(|(c, r, _)| *c == code && *r == revision)
+            .map(|(_, _, offset)| *offset)
+            .ok_or(format!("the offset of {} isn't known for {} revision {}, pass it with {} <offset>", table, code, revision, option))
+    }
+
     pub fn read_byte(&self, offset: usize) -> Option<u8> {
         self.buffer.get(offset).map(|x| x.clone())
     }
-}
\ No newline at end of file
+
+    pub fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.buffer.get(offset..offset + 4)?;
+        Some(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24)
+    }
+
+    pub fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.buffer.get(offset..offset + 2)?;
+        Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    /// returns the ROM offset a pointer points to, or None if it doesn't point into the ROM
+    pub fn read_pointer(&self, offset: usize) -> Option<usize> {
+        let pointer = self.read_u32(offset)? as usize;
+        if pointer >= ROM_BASE && pointer < ROM_BASE + MAX_ROM_SIZE && pointer - ROM_BASE < self.buffer.len() {
+            Some(pointer - ROM_BASE)
+        } else {
+            None
+        }
+    }
+
+    pub fn write_bytes(&mut self, offset: usize, bytes: &[u8]) -> Result<(), String> {
+        if offset + bytes.len() > self.buffer.len() {
+            return Err(format!("can't write 0x{:X} bytes at 0x{:X}, the ROM ends at 0x{:X}", bytes.len(), offset, self.buffer.len()));
+        }
+        self.buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn write_pointer(&mut self, offset: usize, target: usize) -> Result<(), String> {
+        let pointer = (target + ROM_BASE) as u32;
+        self.write_bytes(offset, &pointer.to_le_bytes())
+    }
+
+    /// Finds a word aligned run of free bytes, starting the search at `start`
+    pub fn find_free_space(&self, start: usize, length: usize) -> Option<usize> {
+        let mut offset = (start + 3) & !3;
+        while offset + length <= self.buffer.len() {
+            match self.buffer[offset..offset + length].iter().rposition(|&b| b != FREE_BYTE) {
+                // skip past the last used byte
+                Some(used) => offset = (offset + used + 1 + 3) & !3,
+                None => return Some(offset)
+            }
+        }
+        None
+    }
+
+    /// Finds the word aligned pointers to `target`, to repoint data that was moved
+    pub fn find_pointers(&self, target: usize) -> Vec<usize> {
+        let pointer = ((target + ROM_BASE) as u32).to_le_bytes();
+        self.buffer.chunks_exact(4).enumerate()
+            .filter(|(_, word)| *word == pointer)
+            .map(|(i, _)| i * 4)
+            .collect()
+    }
+
+    /// the offset after the last used byte, where the free space at the end of the ROM starts
+    pub fn end_of_data(&self) -> usize {
+        self.buffer.iter().rposition(|&b| b != FREE_BYTE).map(|last| last + 1).unwrap_or(0)
+    }
+}
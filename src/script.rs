@@ -0,0 +1,626 @@
+use std::collections::BTreeMap;
+
+use crate::charmap::Charmap;
+use crate::decomp::Game;
+use crate::rom::Rom;
+
+/// the end of a movement script
+pub const STEP_END: u8 = 0xfe;
+/// the script context variables that giveitem and finditem load the item and amount into
+pub const VAR_ITEM: u32 = 0x8000;
+pub const VAR_AMOUNT: u32 = 0x8001;
+pub const STD_OBTAIN_ITEM: u32 = 0;
+pub const STD_FIND_ITEM: u32 = 1;
+/// the message box types of callstd, which msgbox takes
+pub const MSGBOX_TYPES: [(u32, &str); 7] = [
+    (2, "MSGBOX_NPC"),
+    (3, "MSGBOX_SIGN"),
+    (4, "MSGBOX_DEFAULT"),
+    (5, "MSGBOX_YESNO"),
+    (6, "MSGBOX_AUTOCLOSE"),
+    (9, "MSGBOX_GETPOINTS"),
+    (10, "MSGBOX_POKENAV"),
+];
+/// the comparisons of goto_if and call_if, which have a macro each
+pub const CONDITIONS: [&str; 6] = ["lt", "eq", "gt", "le", "ge", "ne"];
+
+/// The arguments of a command, and what the pointers among them point to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Arg {
+    Byte,
+    Half,
+    Word,
+    /// a map group and number, written as one map constant
+    Map,
+    Script,
+    Text,
+    Movement,
+    /// a list of items or decorations, ended by 0
+    Mart,
+}
+
+/// Returns the name and the arguments of a command. Ruby and Emerald share their commands,
+/// FireRed has different ones after comparehiddenvar.
+fn command(game: Game, opcode: u8) -> Option<(&'static str, &'static [Arg])> {
+    use self::Arg::*;
+    let command: (&'static str, &'static [Arg]) = match opcode {
+        0x00 => ("nop", &[]),
+        0x01 => ("nop1", &[]),
+        0x02 => ("end", &[]),
+        0x03 => ("return", &[]),
+        0x04 => ("call", &[Script]),
+        0x05 => ("goto", &[Script]),
+        0x06 => ("goto_if", &[Byte, Script]),
+        0x07 => ("call_if", &[Byte, Script]),
+        0x08 => ("gotostd", &[Byte]),
+        0x09 => ("callstd", &[Byte]),
+        0x0a => ("gotostd_if", &[Byte, Byte]),
+        0x0b => ("callstd_if", &[Byte, Byte]),
+        0x0c => ("returnram", &[]),
+        0x0d => ("endram", &[]),
+        0x0e => ("setmysteryeventstatus", &[Byte]),
+        0x0f => ("loadword", &[Byte, Word]),
+        0x10 => ("loadbyte", &[Byte, Byte]),
+        0x11 => ("setptr", &[Byte, Word]),
+        0x12 => ("loadbytefromptr", &[Byte, Word]),
+        0x13 => ("setptrbyte", &[Byte, Word]),
+        0x14 => ("copylocal", &[Byte, Byte]),
+        0x15 => ("copybyte", &[Word, Word]),
+        0x16 => ("setvar", &[Half, Half]),
+        0x17 => ("addvar", &[Half, Half]),
+        0x18 => ("subvar", &[Half, Half]),
+        0x19 => ("copyvar", &[Half, Half]),
+        0x1a => ("setorcopyvar", &[Half, Half]),
+        0x1b => ("compare_local_to_local", &[Byte, Byte]),
+        0x1c => ("compare_local_to_value", &[Byte, Byte]),
+        0x1d => ("compare_local_to_ptr", &[Byte, Word]),
+        0x1e => ("compare_ptr_to_local", &[Word, Byte]),
+        0x1f => ("compare_ptr_to_value", &[Word, Byte]),
+        0x20 => ("compare_ptr_to_ptr", &[Word, Word]),
+        0x21 => ("compare_var_to_value", &[Half, Half]),
+        0x22 => ("compare_var_to_var", &[Half, Half]),
+        0x23 => ("callnative", &[Word]),
+        0x24 => ("gotonative", &[Word]),
+        0x25 => ("special", &[Half]),
+        0x26 => ("specialvar", &[Half, Half]),
+        0x27 => ("waitstate", &[]),
+        0x28 => ("delay", &[Half]),
+        0x29 => ("setflag", &[Half]),
+        0x2a => ("clearflag", &[Half]),
+        0x2b => ("checkflag", &[Half]),
+        0x2c => ("initclock", &[Half, Half]),
+        0x2d => ("dotimebasedevents", &[]),
+        0x2e => ("gettime", &[]),
+        0x2f => ("playse", &[Half]),
+        0x30 => ("waitse", &[]),
+        0x31 => ("playfanfare", &[Half]),
+        0x32 => ("waitfanfare", &[]),
+        0x33 => ("playbgm", &[Half, Byte]),
+        0x34 => ("savebgm", &[Half]),
+        0x35 => ("fadedefaultbgm", &[]),
+        0x36 => ("fadenewbgm", &[Half]),
+        0x37 => ("fadeoutbgm", &[Byte]),
+        0x38 => ("fadeinbgm", &[Byte]),
+        0x39 => ("warp", &[Map, Byte, Half, Half]),
+        0x3a => ("warpsilent", &[Map, Byte, Half, Half]),
+        0x3b => ("warpdoor", &[Map, Byte, Half, Half]),
+        0x3c => ("warphole", &[Map]),
+        0x3d => ("warpteleport", &[Map, Byte, Half, Half]),
+        0x3e => ("setwarp", &[Map, Byte, Half, Half]),
+        0x3f => ("setdynamicwarp", &[Map, Byte, Half, Half]),
+        0x40 => ("setdivewarp", &[Map, Byte, Half, Half]),
+        0x41 => ("setholewarp", &[Map, Byte, Half, Half]),
+        0x42 => ("getplayerxy", &[Half, Half]),
+        0x43 => ("getpartysize", &[]),
+        0x44 => ("additem", &[Half, Half]),
+        0x45 => ("removeitem", &[Half, Half]),
+        0x46 => ("checkitemspace", &[Half, Half]),
+        0x47 => ("checkitem", &[Half, Half]),
+        0x48 => ("checkitemtype", &[Half]),
+        0x49 => ("addpcitem", &[Half, Half]),
+        0x4a => ("checkpcitem", &[Half, Half]),
+        0x4b => ("adddecoration", &[Half]),
+        0x4c => ("removedecoration", &[Half]),
+        0x4d => ("checkdecor", &[Half]),
+        0x4e => ("checkdecorspace", &[Half]),
+        0x4f => ("applymovement", &[Half, Movement]),
+        0x50 => ("applymovementat", &[Half, Movement, Map]),
+        0x51 => ("waitmovement", &[Half]),
+        0x52 => ("waitmovementat", &[Half, Map]),
+        0x53 => ("removeobject", &[Half]),
+        0x54 => ("removeobjectat", &[Half, Map]),
+        0x55 => ("addobject", &[Half]),
+        0x56 => ("addobjectat", &[Half, Map]),
+        0x57 => ("setobjectxy", &[Half, Half, Half]),
+        0x58 => ("showobjectat", &[Half, Map]),
+        0x59 => ("hideobjectat", &[Half, Map]),
+        0x5a => ("faceplayer", &[]),
+        0x5b => ("turnobject", &[Half, Byte]),
+        // the pointers of trainerbattle depend on its type, see trainer_battle_pointers
+        0x5c => ("trainerbattle", &[Byte, Half, Half]),
+        0x5d => ("dotrainerbattle", &[]),
+        0x5e => ("gotopostbattlescript", &[]),
+        0x5f => ("gotobeatenscript", &[]),
+        0x60 => ("checktrainerflag", &[Half]),
+        0x61 => ("settrainerflag", &[Half]),
+        0x62 => ("cleartrainerflag", &[Half]),
+        0x63 => ("setobjectxyperm", &[Half, Half, Half]),
+        0x64 => ("copyobjectxytoperm", &[Half]),
+        0x65 => ("setobjectmovementtype", &[Half, Byte]),
+        0x66 => ("waitmessage", &[]),
+        0x67 => ("message", &[Text]),
+        0x68 => ("closemessage", &[]),
+        0x69 => ("lockall", &[]),
+        0x6a => ("lock", &[]),
+        0x6b => ("releaseall", &[]),
+        0x6c => ("release", &[]),
+        0x6d => ("waitbuttonpress", &[]),
+        0x6e => ("yesnobox", &[Byte, Byte]),
+        0x6f => ("multichoice", &[Byte, Byte, Byte, Byte]),
+        0x70 => ("multichoicedefault", &[Byte, Byte, Byte, Byte, Byte]),
+        0x71 => ("multichoicegrid", &[Byte, Byte, Byte, Byte, Byte]),
+        0x72 => ("drawbox", &[]),
+        0x73 => ("erasebox", &[Byte, Byte, Byte, Byte]),
+        0x74 => ("drawboxtext", &[Byte, Byte, Byte, Byte]),
+        0x75 => ("showmonpic", &[Half, Byte, Byte]),
+        0x76 => ("hidemonpic", &[]),
+        0x77 => ("showcontestpainting", &[Byte]),
+        // braille isn't encoded with the charmap
+        0x78 => ("braillemessage", &[Word]),
+        0x79 => ("givemon", &[Half, Byte, Half, Word, Word, Byte]),
+        0x7a => ("giveegg", &[Half]),
+        0x7b => ("setmonmove", &[Byte, Byte, Half]),
+        0x7c => ("checkpartymove", &[Half]),
+        0x7d => ("bufferspeciesname", &[Byte, Half]),
+        0x7e => ("bufferleadmonspeciesname", &[Byte]),
+        0x7f => ("bufferpartymonnick", &[Byte, Half]),
+        0x80 => ("bufferitemname", &[Byte, Half]),
+        0x81 => ("bufferdecorationname", &[Byte, Half]),
+        0x82 => ("buffermovename", &[Byte, Half]),
+        0x83 => ("buffernumberstring", &[Byte, Half]),
+        0x84 => ("bufferstdstring", &[Byte, Half]),
+        0x85 => ("bufferstring", &[Byte, Text]),
+        0x86 => ("pokemart", &[Mart]),
+        0x87 => ("pokemartdecoration", &[Mart]),
+        0x88 => ("pokemartdecoration2", &[Mart]),
+        0x89 => ("playslotmachine", &[Half]),
+        0x8a => ("setberrytree", &[Byte, Byte, Byte]),
+        0x8b => ("choosecontestmon", &[]),
+        0x8c => ("startcontest", &[]),
+        0x8d => ("showcontestresults", &[]),
+        0x8e => ("contestlinktransfer", &[]),
+        0x8f => ("random", &[Half]),
+        0x90 => ("addmoney", &[Word, Byte]),
+        0x91 => ("removemoney", &[Word, Byte]),
+        0x92 => ("checkmoney", &[Word, Byte]),
+        0x93 => ("showmoneybox", &[Byte, Byte, Byte]),
+        0x94 => ("hidemoneybox", &[]),
+        0x95 => ("updatemoneybox", &[Byte, Byte, Byte]),
+        0x96 => ("getpokenewsactive", &[Half]),
+        0x97 => ("fadescreen", &[Byte]),
+        0x98 => ("fadescreenspeed", &[Byte, Byte]),
+        0x99 => ("setflashlevel", &[Half]),
+        0x9a => ("animateflash", &[Byte]),
+        0x9b => ("messageautoscroll", &[Text]),
+        0x9c => ("dofieldeffect", &[Half]),
+        0x9d => ("setfieldeffectargument", &[Byte, Half]),
+        0x9e => ("waitfieldeffect", &[Half]),
+        0x9f => ("setrespawn", &[Half]),
+        0xa0 => ("checkplayergender", &[]),
+        0xa1 => ("playmoncry", &[Half, Half]),
+        0xa2 => ("setmetatile", &[Half, Half, Half, Half]),
+        0xa3 => ("resetweather", &[]),
+        0xa4 => ("setweather", &[Half]),
+        0xa5 => ("doweather", &[]),
+        0xa6 => ("setstepcallback", &[Byte]),
+        0xa7 => ("setmaplayoutindex", &[Half]),
+        0xa8 => ("setobjectsubpriority", &[Half, Map, Byte]),
+        0xa9 => ("resetobjectsubpriority", &[Half, Map]),
+        0xaa => ("createvobject", &[Byte, Byte, Half, Half, Byte, Byte]),
+        0xab => ("turnvobject", &[Byte, Byte]),
+        0xac => ("opendoor", &[Half, Half]),
+        0xad => ("closedoor", &[Half, Half]),
+        0xae => ("waitdooranim", &[]),
+        0xaf => ("setdooropen", &[Half, Half]),
+        0xb0 => ("setdoorclosed", &[Half, Half]),
+        0xb1 => ("addelevmenuitem", &[Byte, Half, Half, Half]),
+        0xb2 => ("showelevmenu", &[]),
+        0xb3 => ("checkcoins", &[Half]),
+        0xb4 => ("addcoins", &[Half]),
+        0xb5 => ("removecoins", &[Half]),
+        0xb6 => ("setwildbattle", &[Half, Byte, Half]),
+        0xb7 => ("dowildbattle", &[]),
+        // the v commands take pointers relative to setvaddress, which can't be followed
+        0xb8 => ("setvaddress", &[Word]),
+        0xb9 => ("vgoto", &[Word]),
+        0xba => ("vcall", &[Word]),
+        0xbb => ("vgoto_if", &[Byte, Word]),
+        0xbc => ("vcall_if", &[Byte, Word]),
+        0xbd => ("vmessage", &[Word]),
+        0xbe => ("vbuffermessage", &[Word]),
+        0xbf => ("vbufferstring", &[Byte, Word]),
+        0xc0 => ("showcoinsbox", &[Byte, Byte]),
+        0xc1 => ("hidecoinsbox", &[Byte, Byte]),
+        0xc2 => ("updatecoinsbox", &[Byte, Byte]),
+        0xc3 => ("incrementgamestat", &[Byte]),
+        0xc4 => ("setescapewarp", &[Map, Byte, Half, Half]),
+        0xc5 => ("waitmoncry", &[]),
+        0xc6 => ("bufferboxname", &[Byte, Half]),
+        0xc7 => ("textcolor", &[Byte]),
+        0xc8 => ("loadhelp", &[Word]),
+        0xc9 => ("unloadhelp", &[]),
+        0xca => ("signmsg", &[]),
+        0xcb => ("normalmsg", &[]),
+        0xcc => ("comparehiddenvar", &[Byte, Word]),
+        _ => match (game, opcode) {
+            (Game::FireRed, 0xcd) => ("setmonobedient", &[Half]),
+            (Game::FireRed, 0xce) => ("checkmonobedience", &[Half]),
+            (Game::FireRed, 0xcf) => ("execram", &[]),
+            (Game::FireRed, 0xd0) => ("setworldmapflag", &[Half]),
+            (Game::FireRed, 0xd1) => ("warpteleport2", &[Map, Byte, Half, Half]),
+            (Game::FireRed, 0xd2) => ("setmonmetlocation", &[Half, Byte]),
+            (Game::FireRed, 0xd3) => ("getbraillestringwidth", &[Word]),
+            (Game::FireRed, 0xd4) => ("bufferitemnameplural", &[Byte, Half, Half]),
+            (Game::FireRed, _) => return None,
+            (_, 0xcd) => ("setmonmodernfatefulencounter", &[Half]),
+            (_, 0xce) => ("checkmonmodernfatefulencounter", &[Half]),
+            (_, 0xcf) => ("trywondercardscript", &[]),
+            (_, 0xd0) => ("setworldmapflag", &[Half]),
+            (_, 0xd1) => ("warpspinenter", &[Map, Byte, Half, Half]),
+            (_, 0xd2) => ("setmonmetlocation", &[Half, Byte]),
+            (_, 0xd3) => ("moverotatingtileobjects", &[Half]),
+            (_, 0xd4) => ("turnrotatingtileobjects", &[]),
+            (_, 0xd5) => ("initrotatingtilepuzzle", &[Half]),
+            (_, 0xd6) => ("freerotatingtilepuzzle", &[]),
+            (_, 0xd7) => ("warpmossdeepgym", &[Map, Byte, Half, Half]),
+            (_, 0xd8) => ("selectapproachingtrainer", &[]),
+            (_, 0xd9) => ("lockfortrainer", &[]),
+            (_, 0xda) => ("closebraillemessage", &[]),
+            (_, 0xdb) => ("messageinstant", &[Text]),
+            (_, 0xdc) => ("fadescreenswapbuffers", &[Byte]),
+            (_, 0xdd) => ("buffertrainerclassname", &[Byte, Half]),
+            (_, 0xde) => ("buffertrainername", &[Byte, Half]),
+            (_, 0xdf) => ("pokenavcall", &[Text]),
+            (_, 0xe0) => ("warpwhitefade", &[Map, Byte, Half, Half]),
+            (_, 0xe1) => ("buffercontestname", &[Byte, Half]),
+            (_, 0xe2) => ("bufferitemnameplural", &[Byte, Half, Half]),
+            _ => return None
+        }
+    };
+    Some(command)
+}
+
+/// Finds a command by its name, for compiling scripts
+pub fn opcode(game: Game, name: &str) -> Option<(u8, &'static [Arg])> {
+    (0..=0xff).filter_map(|opcode| command(game, opcode).map(|(n, args)| (opcode, n, args)))
+        .find(|(_, n, _)| *n == name)
+        .map(|(opcode, _, args)| (opcode, args))
+}
+
+/// scripts end at these commands, everything after them is only reached by jumping there
+pub fn ends_script(name: &str) -> bool {
+    match name {
+        "end" | "return" | "goto" | "gotostd" | "returnram" | "endram" | "gotonative" | "vgoto"
+        | "gotopostbattlescript" | "gotobeatenscript" => true,
+        _ => false
+    }
+}
+
+/// the texts and the script after the battle of every type of trainerbattle
+fn trainer_battle_pointers(battle_type: u8) -> &'static [Arg] {
+    use self::Arg::*;
+    match battle_type {
+        // continue script and continue script without music
+        1 | 2 => &[Text, Text, Script],
+        // no intro text
+        3 => &[Text],
+        // double and rematch double, with the text when there aren't enough Pokémon
+        4 | 7 => &[Text, Text, Text],
+        // continue script double, with and without music
+        6 | 8 => &[Text, Text, Text, Script],
+        _ => &[Text, Text],
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operand {
+    Number(u32),
+    /// a pointer into the ROM, written as the label of what it points to
+    Pointer(usize),
+    /// a pointer to RAM or a function, written as it is
+    Address(u32),
+}
+
+struct Command {
+    name: &'static str,
+    operands: Vec<Operand>,
+    length: usize,
+}
+
+enum Data {
+    Text(String),
+    Movement(Vec<u8>),
+    Mart(Vec<u16>),
+}
+
+/// Decompiles event scripts, following the jumps and calls to other scripts
+/// and the pointers to texts, movements and marts
+pub struct Decompiler<'a> {
+    rom: &'a Rom,
+    charmap: &'a Charmap,
+    game: Game,
+    prefix: String,
+    commands: BTreeMap<usize, Command>,
+    data: BTreeMap<usize, Data>,
+    labels: BTreeMap<usize, String>,
+    queue: Vec<(usize, Arg)>,
+}
+
+impl<'a> Decompiler<'a> {
+    pub fn new(rom: &'a Rom, charmap: &'a Charmap, game: Game, prefix: &str) -> Decompiler<'a> {
+        Decompiler {
+            rom,
+            charmap,
+            game,
+            prefix: prefix.to_string(),
+            commands: BTreeMap::new(),
+            data: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            queue: Vec::new(),
+        }
+    }
+
+    /// Decompiles the script at `offset` and everything it leads to
+    pub fn add_script(&mut self, offset: usize) -> Result<(), String> {
+        if offset >= self.rom.buffer.len() {
+            return Err(format!("0x{:X} is outside of the ROM", offset));
+        }
+        self.label(offset, Arg::Script);
+        while let Some((offset, kind)) = self.queue.pop() {
+            match kind {
+                Arg::Script => self.decode_script(offset)?,
+                _ => self.decode_data(offset, kind),
+            }
+        }
+        Ok(())
+    }
+
+    /// Names what a pointer points to and queues it to be decoded. The first kind wins,
+    /// like a text that's also used as a script.
+    fn label(&mut self, offset: usize, kind: Arg) {
+        if self.labels.contains_key(&offset) {
+            return;
+        }
+        let kind_name = match kind {
+            Arg::Script => "EventScript",
+            Arg::Text => "Text",
+            Arg::Movement => "Movement",
+            Arg::Mart => "Mart",
+            _ => "Data",
+        };
+        self.labels.insert(offset, format!("{}_{}_{:06X}", self.prefix, kind_name, offset));
+        self.queue.push((offset, kind));
+    }
+
+    fn decode_script(&mut self, offset: usize) -> Result<(), String> {
+        let mut position = offset;
+        // scripts that run into a script that was already decoded go on there
+        while !self.commands.contains_key(&position) {
+            let opcode = self.rom.read_byte(position)
+                .ok_or(format!("the script at 0x{:X} runs past the end of the ROM", offset))?;
+            let (name, args) = command(self.game, opcode)
+                .ok_or(format!("unknown command 0x{:02X} at 0x{:X} in the script at 0x{:X}", opcode, position, offset))?;
+            let mut length = 1;
+            let mut operands = Vec::with_capacity(args.len());
+            let mut args = args.to_vec();
+            let mut i = 0;
+            while i < args.len() {
+                let at = position + length;
+                let error = || format!("the {} at 0x{:X} runs past the end of the ROM", name, position);
+                let operand = match args[i] {
+                    Arg::Byte => {
+                        length += 1;
+                        Operand::Number(self.rom.read_byte(at).ok_or_else(error)? as u32)
+                    }
+                    Arg::Half => {
+                        length += 2;
+                        Operand::Number(self.rom.read_u16(at).ok_or_else(error)? as u32)
+                    }
+                    Arg::Map => {
+                        length += 2;
+                        let group = self.rom.read_byte(at).ok_or_else(error)? as u32;
+                        let number = self.rom.read_byte(at + 1).ok_or_else(error)? as u32;
+                        Operand::Number(group << 8 | number)
+                    }
+                    Arg::Word => {
+                        length += 4;
+                        Operand::Address(self.rom.read_u32(at).ok_or_else(error)?)
+                    }
+                    kind => {
+                        length += 4;
+                        match self.rom.read_pointer(at) {
+                            Some(target) => {
+                                self.label(target, kind);
+                                Operand::Pointer(target)
+                            }
+                            None => Operand::Address(self.rom.read_u32(at).ok_or_else(error)?)
+                        }
+                    }
+                };
+                if name == "trainerbattle" && i == 0 {
+                    if let Operand::Number(battle_type) = operand {
+                        args.extend_from_slice(trainer_battle_pointers(battle_type as u8));
+                    }
+                }
+                operands.push(operand);
+                i += 1;
+            }
+            // msgbox loads its text with loadword 0
+            if name == "loadword" && operands[0] == Operand::Number(0) {
+                if let Some(target) = self.rom.read_pointer(position + 2) {
+                    self.label(target, Arg::Text);
+                    operands[1] = Operand::Pointer(target);
+                }
+            }
+            self.commands.insert(position, Command { name, operands, length });
+            position += length;
+            if ends_script(name) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_data(&mut self, offset: usize, kind: Arg) {
+        let bytes = &self.rom.buffer[offset..];
+        let data = match kind {
+            Arg::Text => Data::Text(self.charmap.decode(bytes).0),
+            Arg::Movement => Data::Movement(bytes.iter().cloned().take_while(|&step| step != STEP_END).collect()),
+            _ => Data::Mart(bytes.chunks(2)
+                .map(|pair| pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8)
+                .take_while(|&item| item != 0)
+                .collect()),
+        };
+        self.data.insert(offset, data);
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Number(value) if *value < 10 => value.to_string(),
+            Operand::Number(value) => format!("0x{:X}", value),
+            Operand::Pointer(offset) => self.labels[offset].clone(),
+            Operand::Address(address) => format!("0x{:08X}", address),
+        }
+    }
+
+    /// Writes the scripts as assembly like the .inc files of the decomps, with the
+    /// commands that the msgbox, giveitem, finditem and goto_if macros stand for put back together
+    pub fn format(&self) -> String {
+        let mut output = String::new();
+        let commands: Vec<(&usize, &Command)> = self.commands.iter().collect();
+        let mut end = None;
+        let mut i = 0;
+        while i < commands.len() {
+            let (&offset, command) = commands[i];
+            if end != Some(offset) {
+                output.push('\n');
+            }
+            if let Some(label) = self.labels.get(&offset) {
+                output.push_str(&format!("{}::\n", label));
+            }
+            // the commands of a macro are only put together if nothing jumps between them
+            let following = |count: usize| -> Option<Vec<&Command>> {
+                let mut following = Vec::with_capacity(count);
+                let mut next = offset + command.length;
+                for j in 1..=count {
+                    let (&at, next_command) = *commands.get(i + j)?;
+                    if at != next || self.labels.contains_key(&at) {
+                        return None;
+                    }
+                    next += next_command.length;
+                    following.push(next_command);
+                }
+                Some(following)
+            };
+            let mut consumed = 1;
+            let line = match (command.name, command.operands.as_slice()) {
+                ("loadword", [Operand::Number(0), text @ Operand::Pointer(_)]) => {
+                    match following(1).as_ref().map(|f| (f[0].name, f[0].operands.as_slice())) {
+                        Some(("callstd", [Operand::Number(std)])) if MSGBOX_TYPES.iter().any(|(t, _)| t == std) => {
+                            consumed = 2;
+                            let msgbox_type = MSGBOX_TYPES.iter().find(|(t, _)| t == std).unwrap().1;
+                            format!("msgbox {}, {}", self.operand(text), msgbox_type)
+                        }
+                        _ => self.format_command(command)
+                    }
+                }
+                ("setorcopyvar", [Operand::Number(VAR_ITEM), item]) => {
+                    let macro_name = following(2).and_then(|f| match (f[0].name, f[0].operands.as_slice(), f[1].name, f[1].operands.as_slice()) {
+                        ("setorcopyvar", [Operand::Number(VAR_AMOUNT), amount], "callstd", [Operand::Number(std)]) => {
+                            let name = match *std {
+                                STD_OBTAIN_ITEM => "giveitem",
+                                STD_FIND_ITEM => "finditem",
+                                _ => return None
+                            };
+                            Some(format!("{} {}, {}", name, self.operand(item), self.operand(amount)))
+                        }
+                        _ => None
+                    });
+                    match macro_name {
+                        Some(line) => {
+                            consumed = 3;
+                            line
+                        }
+                        None => self.format_command(command)
+                    }
+                }
+                ("goto_if", [Operand::Number(condition), target]) | ("call_if", [Operand::Number(condition), target])
+                if (*condition as usize) < CONDITIONS.len() => {
+                    format!("{}_{} {}", command.name, CONDITIONS[*condition as usize], self.operand(target))
+                }
+                _ => self.format_command(command)
+            };
+            output.push_str(&format!("\t{}\n", line));
+            end = Some(offset + command.length);
+            for (_, skipped) in commands[i + 1..i + consumed].iter() {
+                end = end.map(|end| end + skipped.length);
+            }
+            i += consumed;
+        }
+
+        for (offset, data) in self.data.iter() {
+            output.push_str(&format!("\n{}::\n", self.labels[offset]));
+            match data {
+                Data::Text(text) => {
+                    for line in split_string(text) {
+                        output.push_str(&format!("\t.string \"{}\"\n", line));
+                    }
+                }
+                Data::Movement(steps) => {
+                    for step in steps.iter() {
+                        output.push_str(&format!("\t.byte 0x{:02X}\n", step));
+                    }
+                    output.push_str("\tstep_end\n");
+                }
+                Data::Mart(items) => {
+                    for item in items.iter() {
+                        output.push_str(&format!("\t.2byte 0x{:X}\n", item));
+                    }
+                    output.push_str("\t.2byte 0\n");
+                }
+            }
+        }
+        output.trim_start().to_string()
+    }
+
+    fn format_command(&self, command: &Command) -> String {
+        if command.operands.is_empty() {
+            return command.name.to_string();
+        }
+        let operands: Vec<String> = command.operands.iter().map(|o| self.operand(o)).collect();
+        format!("{} {}", command.name, operands.join(", "))
+    }
+}
+
+/// splits a string after every \n, \l and \p like the strings of the decomps, and ends it with $
+pub fn split_string(text: &str) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        lines.last_mut().unwrap().push(c);
+        if c == '\\' {
+            if let Some(&escaped) = chars.peek() {
+                chars.next();
+                lines.last_mut().unwrap().push(escaped);
+                if escaped == 'n' || escaped == 'l' || escaped == 'p' {
+                    lines.push(String::new());
+                }
+            }
+        }
+    }
+    if lines.len() > 1 && lines.last().unwrap().is_empty() {
+        lines.pop();
+    }
+    lines.last_mut().unwrap().push('$');
+    lines
+}
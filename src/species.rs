@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::Path;
+
+use crate::decomp::{Game, Project};
+use crate::lz77;
+use crate::sprite::Sprite;
+use crate::tileset::{read_rgb_image, read_rgba_image, Tile, TileStorage};
+
+/// the shared palettes that every Pokémon icon has to use one of
+pub const ICON_PALETTE_COUNT: usize = 3;
+
+/// Encodes a palette as 16 BGR555 colors, like the .gbapal files of the decomps
+pub fn to_gbapal(palette: &[[u8; 3]; 16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(32);
+    for [r, g, b] in palette.iter() {
+        let color = (*r as u16 >> 3) | ((*g as u16 >> 3) << 5) | ((*b as u16 >> 3) << 10);
+        bytes.push((color & 0xff) as u8);
+        bytes.push((color >> 8) as u8);
+    }
+    bytes
+}
+
+/// colors are compared the way the GBA sees them, with 5 bits per channel
+fn same_color(a: [u8; 3], b: [u8; 3]) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| a >> 3 == b >> 3)
+}
+
+/// Reads the shared icon palettes of a decomp, from graphics/pokemon/icon_palettes
+pub fn read_icon_palettes(project: &Project) -> Result<Vec<[[u8; 3]; 16]>, String> {
+    let mut palettes = Vec::with_capacity(ICON_PALETTE_COUNT);
+    for i in 0..ICON_PALETTE_COUNT {
+        let path = project.root.join(format!("graphics/pokemon/icon_palettes/pal{}.pal", i));
+        palettes.push(TileStorage::read_pal_file(path.display().to_string())
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?);
+    }
+    Ok(palettes)
+}
+
+/// Reads an indexed image as (width, height, palette, row-major indices)
+pub fn read_indexed(path: &Path) -> Result<(usize, usize, [[u8; 3]; 16], Vec<u8>), String> {
+    let name = path.display().to_string();
+    let (width, height, _) = read_rgb_image(&name).map_err(|e| format!("failed to read {}: {}", name, e))?;
+    let palette = TileStorage::read_palette(name.clone()).map_err(|e| format!("{} must be an indexed image: {}", name, e))?;
+    let tiles = TileStorage::read_tiles_image(name.clone()).map_err(|e| format!("failed to read {}: {}", name, e))?;
+    let tiles_per_row = width / 8;
+    let mut indices = vec![0u8; width * height];
+    for (i, tile) in tiles.iter().enumerate() {
+        for y in 0..8 {
+            for x in 0..8 {
+                indices[((i / tiles_per_row) * 8 + y) * width + (i % tiles_per_row) * 8 + x] = tile.data[y][x];
+            }
+        }
+    }
+    if indices.iter().any(|&index| index >= 16) {
+        return Err(format!("{} uses more than 16 colors", name));
+    }
+    Ok((width, height, palette, indices))
+}
+
+/// writes the files the decomp builds from the graphics and returns their paths
+fn emit_files(folder: &Path, front_name: &str, normal: &[[u8; 3]; 16], shiny: Option<&[[u8; 3]; 16]>) -> Result<Vec<String>, String> {
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let sheet = |name: &str| folder.join(format!("{}.png", name)).display().to_string();
+    let front = Sprite::read_sheet(&sheet(front_name), 64, 64)?;
+    files.push((format!("{}.4bpp.lz", front_name), lz77::compress(&front.to_4bpp())));
+    if front_name != "front" {
+        // gMonStillFrontPic is front.png, or the first frame of the animation without it
+        let mut still = Sprite::read_sheet(&sheet(if folder.join("front.png").exists() { "front" } else { front_name }), 64, 64)?;
+        still.frames.truncate(1);
+        files.push(("front.4bpp.lz".to_string(), lz77::compress(&still.to_4bpp())));
+    }
+    let back = Sprite::read_sheet(&sheet("back"), 64, 64)?;
+    files.push(("back.4bpp.lz".to_string(), lz77::compress(&back.to_4bpp())));
+    // icons are loaded without decompressing them
+    let icon = Sprite::read_sheet(&sheet("icon"), 32, 32)?;
+    files.push(("icon.4bpp".to_string(), icon.to_4bpp()));
+
+    TileStorage::output_palette(normal, folder.join("normal.pal").display().to_string());
+    files.push(("normal.gbapal.lz".to_string(), lz77::compress(&to_gbapal(normal))));
+    if let Some(shiny) = shiny {
+        TileStorage::output_palette(shiny, folder.join("shiny.pal").display().to_string());
+        files.push(("shiny.gbapal.lz".to_string(), lz77::compress(&to_gbapal(shiny))));
+    }
+
+    let mut written = Vec::with_capacity(files.len());
+    for (name, bytes) in files {
+        let path = folder.join(&name);
+        fs::write(&path, bytes).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        written.push(path.display().to_string());
+    }
+    Ok(written)
+}
+
+/// Validates the graphics of a species folder and writes the compressed files the decomp
+/// builds from them. Returns a line for every file written, or every problem that was found.
+pub fn build_species_graphics(folder: &str, game: Game, icon_palettes: Option<&[[[u8; 3]; 16]]>) -> Result<Vec<String>, Vec<String>> {
+    let folder = Path::new(folder);
+    let mut problems = Vec::new();
+
+    // pokeemerald animates the front pic with a second frame below the first
+    let (front_name, front_height) = match game {
+        Game::Emerald => ("anim_front", 128),
+        _ => ("front", 64),
+    };
+    if !folder.join(format!("{}.png", front_name)).exists() {
+        return Err(vec![format!("missing {}.png, the front pic is 64x{} in {}", front_name, front_height, game.name())]);
+    }
+    let front = match read_indexed(&folder.join(format!("{}.png", front_name))) {
+        Ok(front) => front,
+        Err(e) => return Err(vec![e])
+    };
+    if (front.0, front.1) != (64, front_height) {
+        problems.push(format!("{}.png must be 64x{} in {}, but it is {}x{}", front_name, front_height, game.name(), front.0, front.1));
+    }
+    let normal = front.2;
+
+    // the still front pic of pokeemerald is optional, the first frame is used without it
+    if front_name != "front" && folder.join("front.png").exists() {
+        match read_indexed(&folder.join("front.png")) {
+            Ok(still) => {
+                if (still.0, still.1) != (64, 64) {
+                    problems.push(format!("front.png must be 64x64, but it is {}x{}", still.0, still.1));
+                }
+                if still.2.iter().zip(normal.iter()).any(|(a, b)| !same_color(*a, *b)) {
+                    problems.push(format!("front.png must use the same palette as {}.png", front_name));
+                }
+            }
+            Err(e) => problems.push(e)
+        }
+    }
+
+    match read_indexed(&folder.join("back.png")) {
+        Ok(back) => {
+            if (back.0, back.1) != (64, 64) {
+                problems.push(format!("back.png must be 64x64, but it is {}x{}", back.0, back.1));
+            }
+            if back.2.iter().zip(normal.iter()).any(|(a, b)| !same_color(*a, *b)) {
+                problems.push(format!("back.png must use the same palette as {}.png", front_name));
+            }
+        }
+        Err(e) => problems.push(e)
+    }
+
+    // a recolored front pic is the easiest way to make a shiny palette with the same index layout
+    let shiny = if folder.join("shiny.png").exists() {
+        match read_indexed(&folder.join("shiny.png")) {
+            Ok(shiny) => {
+                if (shiny.0, shiny.1) != (front.0, front.1) || shiny.3 != front.3 {
+                    problems.push(format!("shiny.png must have the same pixel indices as {}.png, only the colors can change", front_name));
+                }
+                Some(shiny.2)
+            }
+            Err(e) => {
+                problems.push(e);
+                None
+            }
+        }
+    } else {
+        match TileStorage::read_pal_file(folder.join("shiny.pal").display().to_string()) {
+            Ok(shiny) => Some(shiny),
+            Err(e) => {
+                problems.push(format!("missing shiny.png or shiny.pal: {}", e));
+                None
+            }
+        }
+    };
+    if let Some(shiny) = &shiny {
+        if !same_color(shiny[0], normal[0]) {
+            problems.push("the shiny palette must keep the transparent color of the normal palette".to_string());
+        }
+    }
+
+    let mut icon_palette = None;
+    match read_indexed(&folder.join("icon.png")) {
+        Ok(icon) => {
+            if (icon.0, icon.1) != (32, 64) {
+                problems.push(format!("icon.png must be two 32x32 frames (32x64), but it is {}x{}", icon.0, icon.1));
+            }
+            if let Some(icon_palettes) = icon_palettes {
+                icon_palette = icon_palettes.iter().take(ICON_PALETTE_COUNT).position(|palette| {
+                    // only the colors the icon uses have to match
+                    icon.3.iter().all(|&index| index == 0 || same_color(icon.2[index as usize], palette[index as usize]))
+                });
+                if icon_palette.is_none() {
+                    problems.push(format!("icon.png must only use colors of one of the {} icon palettes", ICON_PALETTE_COUNT));
+                }
+            }
+        }
+        Err(e) => problems.push(e)
+    }
+
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let mut written = emit_files(folder, front_name, &normal, shiny.as_ref()).map_err(|e| vec![e])?;
+    if let Some(index) = icon_palette {
+        written.push(format!("icon.png uses icon palette {}", index));
+    }
+    Ok(written)
+}
+
+/// the "redmean" approximation of how different two colors look
+fn color_distance(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let red_mean = (a[0] as f64 + b[0] as f64) / 2.0;
+    let r = a[0] as f64 - b[0] as f64;
+    let g = a[1] as f64 - b[1] as f64;
+    let b = a[2] as f64 - b[2] as f64;
+    ((2.0 + red_mean / 256.0) * r * r + 4.0 * g * g + (2.0 + (255.0 - red_mean) / 256.0) * b * b).sqrt()
+}
+
+/// Reads the pixels of an icon, with None for transparent pixels. Index 0 of indexed
+/// images is transparent, and true color images are transparent where their alpha is,
+/// or where they have the color of the top left pixel if they don't have alpha.
+pub fn read_icon_pixels(path: &str) -> Result<(usize, usize, Vec<Option<[u8; 3]>>), String> {
+    if let Ok((width, height, palette, indices)) = read_indexed(Path::new(path)) {
+        let pixels = indices.iter()
+            .map(|&index| if index == 0 { None } else { Some(palette[index as usize]) })
+            .collect();
+        return Ok((width, height, pixels));
+    }
+    let (width, height, rgba) = read_rgba_image(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let has_alpha = rgba.chunks(4).any(|c| c[3] != 0xff);
+    let background = [rgba[0], rgba[1], rgba[2]];
+    let pixels = rgba.chunks(4)
+        .map(|c| {
+            let color = [c[0], c[1], c[2]];
+            let transparent = if has_alpha { c[3] < 0x80 } else { color == background };
+            if transparent { None } else { Some(color) }
+        })
+        .collect();
+    Ok((width, height, pixels))
+}
+
+/// Finds the icon palette that draws the pixels with the lowest total error and returns
+/// its index, the pixels remapped to its colors and the average error of a pixel
+pub fn match_icon_palette(pixels: &[Option<[u8; 3]>], palettes: &[[[u8; 3]; 16]]) -> (usize, Vec<u8>, f64) {
+    let mut best: Option<(usize, Vec<u8>, f64)> = None;
+    for (palette_index, palette) in palettes.iter().take(ICON_PALETTE_COUNT).enumerate() {
+        let mut error = 0.0;
+        let mut indices = Vec::with_capacity(pixels.len());
+        for pixel in pixels.iter() {
+            let color = match pixel {
+                Some(color) => *color,
+                None => {
+                    indices.push(0);
+                    continue;
+                }
+            };
+            // index 0 is transparent, so only the other 15 colors can be used
+            let (index, distance) = (1..16)
+                .map(|i| (i, color_distance(color, palette[i])))
+                .fold((1, std::f64::MAX), |best, candidate| if candidate.1 < best.1 { candidate } else { best });
+            error += distance;
+            indices.push(index as u8);
+        }
+        if best.as_ref().map(|b| error < b.2).unwrap_or(true) {
+            best = Some((palette_index, indices, error));
+        }
+    }
+    let (palette_index, indices, error) = best.unwrap_or((0, vec![0; pixels.len()], 0.0));
+    let opaque = pixels.iter().filter(|p| p.is_some()).count().max(1);
+    (palette_index, indices, error / opaque as f64)
+}
+
+/// Write row-major indices to a 4bpp indexed image
+pub fn write_indexed(path: &str, width: usize, height: usize, indices: &[u8], palette: &[[u8; 3]; 16]) {
+    let tiles_per_row = width / 8;
+    let tiles: Vec<Tile> = (0..tiles_per_row * (height / 8))
+        .map(|i| {
+            let mut data = [[0u8; 8]; 8];
+            for (y, row) in data.iter_mut().enumerate() {
+                for (x, index) in row.iter_mut().enumerate() {
+                    *index = indices[((i / tiles_per_row) * 8 + y) * width + (i % tiles_per_row) * 8 + x];
+                }
+            }
+            Tile::new(data)
+        })
+        .collect();
+    TileStorage::output_tiles_image(&tiles, palette, width as u32, height as u32, path.to_string());
+}
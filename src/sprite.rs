@@ -0,0 +1,130 @@
+use std::fs;
+
+use crate::tileset::{read_rgb_image, Tile, TILE_SIZE, TileStorage};
+
+/// the OBJ sizes the GBA can draw, as (width, height) in pixels
+const OBJ_SIZES: [(usize, usize); 12] = [
+    (8, 8), (16, 16), (32, 32), (64, 64),
+    (16, 8), (32, 8), (32, 16), (64, 32),
+    (8, 16), (8, 32), (16, 32), (32, 64),
+];
+
+/// Frames of an overworld sprite or a trainer/Pokémon pic. With 1D mapping the tiles
+/// of every frame are stored row by row, and the frames follow each other in VRAM.
+pub struct Sprite {
+    pub frame_width: usize,
+    pub frame_height: usize,
+    pub frames: Vec<Vec<Tile>>,
+}
+
+impl Sprite {
+    /// parses a frame size like 16x32
+    pub fn parse_size(size: &str) -> Result<(usize, usize), String> {
+        let parts: Vec<&str> = size.split('x').collect();
+        let parsed: Vec<usize> = parts.iter().filter_map(|p| p.parse::<usize>().ok()).collect();
+        if parts.len() != 2 || parsed.len() != 2 {
+            return Err(format!("frame size must be WIDTHxHEIGHT: {}", size));
+        }
+        let (width, height) = (parsed[0], parsed[1]);
+        if !OBJ_SIZES.contains(&(width, height)) {
+            return Err(format!("{}x{} isn't a size the GBA can draw sprites in", width, height));
+        }
+        Ok((width, height))
+    }
+
+    fn tiles_per_frame(&self) -> usize {
+        (self.frame_width / TILE_SIZE) * (self.frame_height / TILE_SIZE)
+    }
+
+    /// Read the frames of an indexed sprite sheet, left to right and top to bottom
+    pub fn read_sheet(path: &str, frame_width: usize, frame_height: usize) -> Result<Sprite, String> {
+        let (width, height, _) = read_rgb_image(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        if width % frame_width != 0 || height % frame_height != 0 {
+            return Err(format!("{} is {}x{}, which isn't a multiple of the {}x{} frame size",
+                               path, width, height, frame_width, frame_height));
+        }
+        let tiles = TileStorage::read_tiles_image(path.to_string())
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+
+        let sheet_tiles_per_row = width / TILE_SIZE;
+        let mut frames = Vec::new();
+        for frame_y in 0..height / frame_height {
+            for frame_x in 0..width / frame_width {
+                let mut frame = Vec::new();
+                for y in 0..frame_height / TILE_SIZE {
+                    for x in 0..frame_width / TILE_SIZE {
+                        let tile_x = frame_x * frame_width / TILE_SIZE + x;
+                        let tile_y = frame_y * frame_height / TILE_SIZE + y;
+                        frame.push(Tile::new(tiles[tile_y * sheet_tiles_per_row + tile_x].data));
+                    }
+                }
+                frames.push(frame);
+            }
+        }
+        Ok(Sprite { frame_width, frame_height, frames })
+    }
+
+    /// Split 4bpp graphics in OBJ tile order into frames
+    pub fn from_4bpp(bytes: &[u8], frame_width: usize, frame_height: usize) -> Result<Sprite, String> {
+        let frame_size = (frame_width / TILE_SIZE) * (frame_height / TILE_SIZE) * 32;
+        if bytes.is_empty() || bytes.len() % frame_size != 0 {
+            return Err(format!("{} bytes of graphics aren't a whole number of {}x{} frames", bytes.len(), frame_width, frame_height));
+        }
+        let frames = bytes.chunks(frame_size)
+            .map(|frame| frame.chunks(32).map(Tile::from_4bpp).collect())
+            .collect();
+        Ok(Sprite { frame_width, frame_height, frames })
+    }
+
+    pub fn to_4bpp(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.frames.len() * self.tiles_per_frame() * 32);
+        for tile in self.frames.iter().flatten() {
+            data.append(&mut tile.to_4bpp());
+        }
+        data
+    }
+
+    /// Write the frames to an indexed sheet with `frames_per_row` frames in every row,
+    /// like the sheets in the decomps
+    pub fn write_sheet(&self, path: &str, palette: &[[u8; 3]; 16], frames_per_row: usize) {
+        let frames_per_row = frames_per_row.max(1).min(self.frames.len().max(1));
+        let frame_rows = (self.frames.len() + frames_per_row - 1) / frames_per_row;
+        let frame_tiles_per_row = self.frame_width / TILE_SIZE;
+        let frame_tile_rows = self.frame_height / TILE_SIZE;
+        let sheet_tiles_per_row = frame_tiles_per_row * frames_per_row;
+        let mut tiles: Vec<Tile> = (0..sheet_tiles_per_row * frame_tile_rows * frame_rows).map(|_| Tile::blank()).collect();
+        for (i, frame) in self.frames.iter().enumerate() {
+            for (j, tile) in frame.iter().enumerate() {
+                let x = (i % frames_per_row) * frame_tiles_per_row + j % frame_tiles_per_row;
+                let y = (i / frames_per_row) * frame_tile_rows + j / frame_tiles_per_row;
+                tiles[y * sheet_tiles_per_row + x] = Tile::new(tile.data);
+            }
+        }
+        TileStorage::output_tiles_image(&tiles, palette, (self.frame_width * frames_per_row) as u32,
+                                        (self.frame_height * frame_rows) as u32, path.to_string());
+    }
+
+    /// Returns the SpriteFrameImage table of an overworld sprite, which points at every frame of the pic
+    pub fn frame_table(&self, name: &str) -> String {
+        let mut table = format!("static const struct SpriteFrameImage sPicTable_{}[] = {{\n", name);
+        for i in 0..self.frames.len() {
+            table.push_str(&format!("    overworld_frame(gObjectEventPic_{}, {}, {}, {}),\n",
+                                    name, self.frame_width / TILE_SIZE, self.frame_height / TILE_SIZE, i));
+        }
+        table.push_str("};\n");
+        table
+    }
+}
+
+/// Read 4bpp graphics from a .4bpp file, or from any file like a ROM at an offset
+pub fn read_graphics(path: &str, offset: usize, length: Option<usize>) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let end = match length {
+        Some(length) => offset + length,
+        None => bytes.len()
+    };
+    if offset > end || end > bytes.len() {
+        return Err(format!("0x{:X}-0x{:X} is outside of {}, which has 0x{:X} bytes", offset, end, path, bytes.len()));
+    }
+    Ok(bytes[offset..end].to_vec())
+}
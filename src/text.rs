@@ -0,0 +1,189 @@
+use crate::charmap::Charmap;
+use crate::font::FontWidths;
+use crate::rom::{FREE_BYTE, Rom};
+
+/// A string of a pointer table, as it's written in a text dump
+pub struct TextEntry {
+    /// where the pointer to the string is
+    pub pointer: usize,
+    /// where the string is
+    pub address: usize,
+    pub text: String,
+}
+
+/// Reads the strings of a table of `count` pointers at `table`
+pub fn dump_table(rom: &Rom, charmap: &Charmap, table: usize, count: usize) -> Result<Vec<TextEntry>, String> {
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let pointer = table + i * 4;
+        let address = rom.read_pointer(pointer)
+            .ok_or(format!("0x{:X} isn't a pointer into the ROM", pointer))?;
+        let (text, _) = charmap.decode(&rom.buffer[address..]);
+        entries.push(TextEntry { pointer, address, text });
+    }
+    Ok(entries)
+}
+
+/*
+# comments start with a hash
+0x3E0000 0x123456 = Hello {PLAYER}!\nHow are you?
+*/
+pub fn format_dump(source: &str, entries: &[TextEntry]) -> String {
+    let mut dump = format!("# pokerus text dump of {}\n# <pointer> <string> = <text>\n", source);
+    for entry in entries.iter() {
+        dump.push_str(&format!("0x{:06X} 0x{:06X} = {}\n", entry.pointer, entry.address, entry.text));
+    }
+    dump
+}
+
+pub fn parse_dump(contents: &str) -> Result<Vec<TextEntry>, String> {
+    let mut entries = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || format!("line {} must be <pointer> <string> = <text>: {}", line_number + 1, line);
+        let separator = line.find(" = ").ok_or_else(error)?;
+        let numbers = line[..separator].split_whitespace()
+            .map(|n| usize::from_str_radix(n.trim_start_matches("0x"), 16))
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| error())?;
+        if numbers.len() != 2 {
+            return Err(error());
+        }
+        entries.push(TextEntry { pointer: numbers[0], address: numbers[1], text: line[separator + 3..].to_string() });
+    }
+    Ok(entries)
+}
+
+/// what happened to the strings of a text dump when they were inserted
+pub struct InsertReport {
+    pub unchanged: usize,
+    pub in_place: usize,
+    /// (pointer, new address) of the strings that grew
+    pub relocated: Vec<(usize, usize)>,
+}
+
+/// Writes the strings back where they are. Strings that don't fit anymore are moved
+/// to free space found from `free_space`, and their pointer is changed to the new location.
+pub fn insert_strings(rom: &mut Rom, charmap: &Charmap, entries: &[TextEntry], free_space: usize) -> Result<InsertReport, String> {
+    let mut report = InsertReport { unchanged: 0, in_place: 0, relocated: Vec::new() };
+    let mut free_space = free_space;
+    for entry in entries.iter() {
+        let address = rom.read_pointer(entry.pointer)
+            .ok_or(format!("0x{:X} isn't a pointer into the ROM", entry.pointer))?;
+        let (_, old_length) = charmap.decode(&rom.buffer[address..]);
+        let encoded = charmap.encode(&entry.text)
+            .map_err(|e| format!("failed to encode the string at 0x{:X}: {}", entry.pointer, e))?;
+        if rom.buffer[address..address + old_length] == encoded[..] {
+            report.unchanged += 1;
+            continue;
+        }
+
+        // a string that other entries point to as well can't be changed in place
+        let shared = entries.iter().filter(|e| rom.read_pointer(e.pointer) == Some(address)).count() > 1;
+        if encoded.len() <= old_length && !shared {
+            let mut bytes = encoded;
+            bytes.resize(old_length, FREE_BYTE);
+            rom.write_bytes(address, &bytes)?;
+            report.in_place += 1;
+            continue;
+        }
+        let new_address = rom.find_free_space(free_space, encoded.len())
+            .ok_or(format!("no free space left for the 0x{:X} bytes of the string at 0x{:X}", encoded.len(), entry.pointer))?;
+        rom.write_bytes(new_address, &encoded)?;
+        rom.write_pointer(entry.pointer, new_address)?;
+        // the terminator is a free byte, so the next string has to start after it
+        free_space = new_address + encoded.len();
+        report.relocated.push((entry.pointer, new_address));
+    }
+    Ok(report)
+}
+
+/// the start of the free space at the end of the ROM, one byte after the last used byte
+/// so the terminator of a string that was moved there before isn't overwritten
+pub fn default_free_space(rom: &Rom) -> usize {
+    (rom.end_of_data() + 1).min(rom.buffer.len())
+}
+
+/// A string of a .inc or .s file, or of a text dump
+pub struct SourceString {
+    /// the label of the string, or its pointer for dumps
+    pub label: String,
+    pub line: usize,
+    pub text: String,
+}
+
+/*
+gText_Hello::
+    .string "Hello {PLAYER}!\n"
+    .string "How are you?$"
+*/
+pub fn parse_inc_strings(contents: &str) -> Vec<SourceString> {
+    let mut strings = Vec::new();
+    let mut label = String::new();
+    let mut current: Option<SourceString> = None;
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.ends_with(':') && !line.contains(' ') {
+            label = line.trim_end_matches(':').to_string();
+            continue;
+        }
+        if !line.starts_with(".string") {
+            continue;
+        }
+        let (start, end) = match (line.find('"'), line.rfind('"')) {
+            (Some(start), Some(end)) if end > start => (start, end),
+            _ => continue
+        };
+        let string = current.get_or_insert(SourceString { label: label.clone(), line: line_number + 1, text: String::new() });
+        string.text.push_str(&line[start + 1..end]);
+        // a string goes on over several .string lines until its terminator
+        if string.text.ends_with('$') {
+            strings.extend(current.take());
+        }
+    }
+    strings.extend(current.take());
+    strings
+}
+
+pub fn dump_strings(entries: &[TextEntry]) -> Vec<SourceString> {
+    entries.iter().enumerate()
+        .map(|(i, entry)| SourceString { label: format!("0x{:06X}", entry.pointer), line: i + 1, text: entry.text.clone() })
+        .collect()
+}
+
+/// Returns a message for every line of the strings that is wider than `max_width` pixels
+pub fn check_widths(charmap: &Charmap, font: &FontWidths, strings: &[SourceString], max_width: usize) -> Result<Vec<String>, String> {
+    let mut overflows = Vec::new();
+    for string in strings.iter() {
+        let encoded = charmap.encode(&string.text)
+            .map_err(|e| format!("failed to encode {} (line {}): {}", string.label, string.line, e))?;
+        let source_lines = split_lines(string.text.trim_end_matches('$'));
+        for (i, width) in font.measure_lines(&encoded).iter().enumerate() {
+            if *width > max_width {
+                overflows.push(format!("{} (line {}): line {} is {}px wide, {}px more than {}px: {}",
+                                       string.label, string.line, i + 1, width, width - max_width, max_width,
+                                       source_lines.get(i).map(|l| l.as_str()).unwrap_or("")));
+            }
+        }
+    }
+    Ok(overflows)
+}
+
+/// splits a string at \n, \l and \p like the game does
+fn split_lines(text: &str) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) | ('\\', Some('l')) | ('\\', Some('p')) => {
+                chars.next();
+                lines.push(String::new());
+            }
+            _ => lines.last_mut().unwrap().push(c)
+        }
+    }
+    lines
+}
@@ -6,9 +6,12 @@ use std::path::Path;
 
 use png::HasParameters;
 
-const METATILE_SIZE: usize = 16;
-const TILE_SIZE: usize = 8;
-const SECONDARY_PALETTE_OFFSET: usize = 6;
+use crate::decomp::Game;
+
+pub const METATILE_SIZE: usize = 16;
+pub const TILE_SIZE: usize = 8;
+/// Porymap's metatile selector is 8 metatiles wide
+const PREVIEW_METATILES_PER_ROW: usize = 8;
 
 #[derive(Eq, PartialEq)]
 pub struct Tile {
@@ -58,6 +61,61 @@ impl Tile {
         }
     }
 
+    /// true if every pixel uses the first (transparent) color of the palette
+    pub fn is_transparent(&self) -> bool {
+        self.data.iter().all(|row| row.iter().all(|index| *index == 0))
+    }
+
+    /// encodes the tile in the GBA's 4bpp format, with the left pixel in the low nibble
+    pub fn to_4bpp(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TILE_SIZE * TILE_SIZE / 2);
+        for row in self.data.iter() {
+            for pixels in row.chunks(2) {
+                bytes.push((pixels[0] & 0xf) | ((pixels[1] & 0xf) << 4));
+            }
+        }
+        bytes
+    }
+
+    /// decodes 32 bytes of the GBA's 4bpp format
+    pub fn from_4bpp(bytes: &[u8]) -> Tile {
+        let mut data: [[u8; TILE_SIZE]; TILE_SIZE] = Default::default();
+        for (i, byte) in bytes.iter().take(TILE_SIZE * TILE_SIZE / 2).enumerate() {
+            data[i / 4][(i % 4) * 2] = byte & 0xf;
+            data[i / 4][(i % 4) * 2 + 1] = byte >> 4;
+        }
+        Tile { data }
+    }
+
+    /// encodes the tile in the 2bpp format of the fonts, where every row is a halfword
+    /// with the left pixel in the high bits, like gbagfx writes .latfont files
+    pub fn to_2bpp(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TILE_SIZE * TILE_SIZE / 4);
+        for row in self.data.iter() {
+            let halfword = row.iter().enumerate().fold(0u16, |halfword, (x, pixel)| halfword | ((*pixel as u16 & 0x3) << (14 - x * 2)));
+            bytes.extend_from_slice(&halfword.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// decodes 16 bytes of the 2bpp format of the fonts
+    pub fn from_2bpp(bytes: &[u8]) -> Tile {
+        let mut data: [[u8; TILE_SIZE]; TILE_SIZE] = Default::default();
+        for (y, row) in bytes.chunks(2).take(TILE_SIZE).enumerate() {
+            let halfword = row[0] as u16 | (*row.get(1).unwrap_or(&0) as u16) << 8;
+            for x in 0..TILE_SIZE {
+                data[y][x] = (halfword >> (14 - x * 2)) as u8 & 0x3;
+            }
+        }
+        Tile { data }
+    }
+
+    /// returns a copy of the tile with the flips of a `TileRef` applied
+    pub fn flip(&self, flip_x: bool, flip_y: bool) -> Tile {
+        let tile = if flip_x { self.flip_x() } else { Tile::new(self.data) };
+        if flip_y { tile.flip_y() } else { tile }
+    }
+
     fn reverse_row(row: [u8; TILE_SIZE]) -> [u8; TILE_SIZE] {
         [
             row[7],
@@ -107,6 +165,50 @@ impl Tile {
     }
 }
 
+/// A reference to a tile inside of a metatile, stored as a u16 in metatiles.bin
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct TileRef {
+    pub tile_id: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub palette: usize,
+}
+
+impl TileRef {
+    pub fn encode(&self) -> u16 {
+        let flip_x_bit = if self.flip_x { 1usize } else { 0 };
+        let flip_y_bit = if self.flip_y { 1usize } else { 0 };
+        (((self.palette & 0xf) << 12) | (flip_y_bit << 11) | (flip_x_bit << 10) | (self.tile_id & 0x3ff)) as u16
+    }
+
+    pub fn decode(value: u16) -> TileRef {
+        let value = value as usize;
+        TileRef {
+            tile_id: value & 0x3ff,
+            flip_x: (value >> 10) & 1 == 1,
+            flip_y: (value >> 11) & 1 == 1,
+            palette: (value >> 12) & 0xf,
+        }
+    }
+
+    /// decodes the little endian tile references of a metatiles.bin file
+    pub fn decode_all(bytes: &[u8]) -> Vec<TileRef> {
+        bytes.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| TileRef::decode(pair[0] as u16 | ((pair[1] as u16) << 8)))
+            .collect()
+    }
+}
+
+/// The color that is treated as transparent when compositing layers
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Transparency {
+    /// a palette index, the GBA only treats index 0 as transparent
+    Index(u8),
+    /// a key color, like the #FF00FD magenta used by the frontend
+    Color([u8; 3]),
+}
+
 pub struct TileStorage {
     pub tiles: Vec<Tile>,
     pub palettes: Vec<[[u8; 3]; 16]>,
@@ -114,10 +216,26 @@ pub struct TileStorage {
     pub encoded_metatiles: HashMap<(String, usize), Vec<u8>>,
     /// true if it's the primary tileset, false if it's the secondary tileset
     pub primary: bool,
+    /// this color is moved to index 0 of every palette so the GBA draws it as transparent
+    pub transparency: Transparency,
+    /// the game decides where the tiles and palettes of secondary tilesets start
+    pub game: Game,
+    /// tiles of the primary tileset that a secondary tileset can reference with ids below the primary tile count
+    pub primary_tiles: Vec<Tile>,
+    /// palettes of the primary tileset that a secondary tileset can reference
+    pub primary_palettes: Vec<[[u8; 3]; 16]>,
+    /// palette slots loaded from a previous build that haven't been claimed by an image yet
+    pub unclaimed_palettes: Vec<usize>,
+    /// the palette slot of every image, decided up front by plan_palettes in incremental builds
+    pub planned_palettes: HashMap<String, usize>,
+    /// tiles put at fixed slots by reserve_tiles, which metatiles don't have to reference
+    pub reserved_tiles: Vec<usize>,
+    /// blank slots in front of reserved tiles that new tiles are pushed into
+    pub free_slots: Vec<usize>,
 }
 
 impl TileStorage {
-    pub fn new(output_folder: String, primary: bool) -> TileStorage {
+    pub fn new(output_folder: String, primary: bool, game: Game) -> TileStorage {
         fs::create_dir_all(format!("{}/palettes/", output_folder));
         let mut tiles = Vec::new();
         tiles.push(Tile::blank());
@@ -127,6 +245,14 @@ impl TileStorage {
             output_folder,
             encoded_metatiles: HashMap::new(),
             primary,
+            transparency: Transparency::Index(0),
+            game,
+            primary_tiles: Vec::new(),
+            primary_palettes: Vec::new(),
+            unclaimed_palettes: Vec::new(),
+            planned_palettes: HashMap::new(),
+            reserved_tiles: Vec::new(),
+            free_slots: Vec::new(),
         }
     }
 
@@ -159,18 +285,24 @@ impl TileStorage {
                 indexed_palette.push(color);
             }
 
-            // copy the values from the palette into storage
-            let mut formatted_palette: [[u8; 3]; 16] = Default::default();
-            for i in 0..16usize {
-                if let Some(colors) = indexed_palette.get(i) {
-                    let r = colors[0];
-                    let g = colors[1];
-                    let b = colors[2];
-                    formatted_palette[i] = [r, g, b];
-                }
-            }
-            self.add_palette(formatted_palette);
-            let palette_id = self.palettes.len() - 1;
+            // swap the transparent color into index 0, which reindexes the image below
+            let transparent_index = self.transparent_index(&indexed_palette)?;
+            indexed_palette.swap(0, transparent_index);
+            let formatted_palette = TileStorage::format_palette(&indexed_palette);
+            // secondary tilesets can use a palette that is already in the primary tileset
+            let palette = if let Some(primary_palette_id) = self.primary_palettes.iter().position(|p| *p == formatted_palette) {
+                primary_palette_id
+            } else {
+                // incremental builds decide the palette slots of all images up front in plan_palettes
+                let palette_id = match self.planned_palettes.get(&path) {
+                    Some(&palette_id) => palette_id,
+                    None => {
+                        self.add_palette(formatted_palette);
+                        self.palettes.len() - 1
+                    }
+                };
+                palette_id + self.palette_offset()
+            };
 
             // index the image by splitting it into chunks of (r, g, b) and finding it in the palette
             let mut indexed_image: Vec<u8> = Vec::with_capacity(buf.len());
@@ -202,15 +334,17 @@ impl TileStorage {
                     let tiles = Tile::extract(metatile);
                     // encode the tiles now while we have the information
                     for tile in tiles {
-                        let (mut tile_id, flip_x, flip_y) = self.push(tile);
-                        if !self.primary {
+                        let (tile_id, flip_x, flip_y) = if tile.is_transparent() {
+                            // fully transparent tiles always use the blank tile of the primary tileset
+                            (0, false, false)
+                        } else if let Some(primary_tile) = self.find_primary_tile(&tile) {
+                            primary_tile
+                        } else {
+                            let (tile_id, flip_x, flip_y) = self.push(tile);
                             // secondary tilesets start after the primary tileset loaded in a map
-                            tile_id += 0x200;
-                        }
-                        let flip_x_bit = if flip_x { 1usize } else { 0 };
-                        let flip_y_bit = if flip_y { 1usize } else { 0 };
-                        let p = if self.primary { palette_id } else { palette_id + SECONDARY_PALETTE_OFFSET };
-                        let value = ((p & 0xf) << 12) | (flip_y_bit << 11) | (flip_x_bit << 10) | (tile_id & 0x3ff);
+                            (tile_id + self.tile_offset(), flip_x, flip_y)
+                        };
+                        let value = TileRef { tile_id, flip_x, flip_y, palette }.encode();
                         encoded_tiles.push((value & 0xff) as u8);
                         encoded_tiles.push(((value >> 8) & 0xff) as u8);
                     }
@@ -223,6 +357,209 @@ impl TileStorage {
         }
     }
 
+    /// Seed the storage with the tiles and palettes of a previous build in the output folder,
+    /// so existing tiles and palette slots keep their indices
+    pub fn load_previous(&mut self) -> io::Result<()> {
+        let tiles_path = format!("{}/tiles.png", self.output_folder);
+        if !Path::new(&tiles_path).exists() {
+            // nothing to seed from on the first build
+            return Ok(());
+        }
+        let mut tiles = TileStorage::read_tiles_image(tiles_path)?;
+        // new tiles go after the last used one
+        TileStorage::trim_blank_tiles(&mut tiles);
+        // blank tiles are never referenced, so the ones left are padding in front of reserved tiles
+        self.free_slots = (1..tiles.len()).filter(|&i| tiles[i].is_transparent()).collect();
+        self.tiles = tiles;
+
+        self.palettes.clear();
+        self.unclaimed_palettes.clear();
+        for i in 0..self.max_palette_count() {
+            let palette_id = i + self.palette_offset();
+            let path = format!("{}/palettes/{:0>2}.pal", self.output_folder, palette_id);
+            if !Path::new(&path).exists() {
+                break;
+            }
+            self.palettes.push(TileStorage::read_pal_file(path)?);
+            self.unclaimed_palettes.push(i);
+        }
+        Ok(())
+    }
+
+    /// Remove the blank tiles that tiles.png is padded with, keeping the blank tile at index 0
+    pub fn trim_blank_tiles(tiles: &mut Vec<Tile>) {
+        while tiles.len() > 1 && tiles.last().map(|tile| tile.is_transparent()).unwrap_or(false) {
+            tiles.pop();
+        }
+        if tiles.is_empty() {
+            tiles.push(Tile::blank());
+        }
+    }
+
+    /// returns the index of the transparent color in the palette of an image
+    fn transparent_index(&self, palette: &[&[u8]]) -> Result<usize, String> {
+        match self.transparency {
+            Transparency::Index(index) if (index as usize) < palette.len() => Ok(index as usize),
+            Transparency::Index(index) => Err(format!("transparent index {} is outside of the {} color palette", index, palette.len())),
+            Transparency::Color(color) => palette.iter().position(|&c| c == color)
+                .ok_or(format!("transparent color #{:02X}{:02X}{:02X} is not in the palette", color[0], color[1], color[2])),
+        }
+    }
+
+    /// copies the first 16 colors of the palette of an image
+    fn format_palette(palette: &[&[u8]]) -> [[u8; 3]; 16] {
+        let mut formatted_palette: [[u8; 3]; 16] = Default::default();
+        for (i, colors) in palette.iter().take(16).enumerate() {
+            formatted_palette[i] = [colors[0], colors[1], colors[2]];
+        }
+        formatted_palette
+    }
+
+    /// Decide the palette slot of every image of an incremental build before adding them.
+    /// Images keep the slot of an identical palette from the previous build, and an image
+    /// whose palette changed takes over the unclaimed slot that shares the most colors with it.
+    pub fn plan_palettes(&mut self, paths: &[String]) -> Result<(), String> {
+        let mut changed = Vec::new();
+        for path in paths {
+            let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+            let (_, reader) = png::Decoder::new(file).read_info().map_err(|e| format!("failed to read {}: {}", path, e))?;
+            let colors = match &reader.info().palette {
+                Some(colors) => colors.clone(),
+                None => continue
+            };
+            let mut indexed_palette: Vec<&[u8]> = colors.chunks(3).collect();
+            let transparent_index = self.transparent_index(&indexed_palette).map_err(|e| format!("{}: {}", path, e))?;
+            indexed_palette.swap(0, transparent_index);
+            let palette = TileStorage::format_palette(&indexed_palette);
+            if self.primary_palettes.contains(&palette) {
+                continue;
+            }
+            match self.palettes.iter().position(|p| *p == palette) {
+                Some(palette_id) => {
+                    self.unclaimed_palettes.retain(|&i| i != palette_id);
+                    self.planned_palettes.insert(path.clone(), palette_id);
+                }
+                None => changed.push((path.clone(), palette))
+            }
+        }
+        for (path, palette) in changed {
+            // images with the same new palette share a slot
+            if let Some(palette_id) = self.palettes.iter().position(|p| *p == palette) {
+                self.planned_palettes.insert(path, palette_id);
+                continue;
+            }
+            let shared_colors = |palette_id: usize| self.palettes[palette_id].iter().filter(|c| palette.contains(c)).count();
+            let best = self.unclaimed_palettes.iter().enumerate()
+                .max_by_key(|&(position, &palette_id)| (shared_colors(palette_id), usize::max_value() - position))
+                .map(|(position, _)| position);
+            let palette_id = match best {
+                Some(position) => {
+                    let palette_id = self.unclaimed_palettes.remove(position);
+                    self.palettes[palette_id] = palette;
+                    palette_id
+                }
+                None => {
+                    self.add_palette(palette);
+                    self.palettes.len() - 1
+                }
+            };
+            self.planned_palettes.insert(path, palette_id);
+        }
+        Ok(())
+    }
+
+    /// Release the tiles and palette slots of a previous build that no metatile references anymore.
+    /// Released tiles are blanked so the next incremental build can fill them, and released
+    /// palettes at the end are dropped.
+    pub fn release_unused(&mut self) {
+        let tile_offset = self.tile_offset();
+        let mut referenced = vec![false; self.tiles.len()];
+        referenced[0] = true;
+        for &slot in self.reserved_tiles.iter() {
+            referenced[slot] = true;
+        }
+        for encoded in self.encoded_metatiles.values() {
+            for tile_ref in TileRef::decode_all(encoded) {
+                if tile_ref.tile_id >= tile_offset && tile_ref.tile_id - tile_offset < referenced.len() {
+                    referenced[tile_ref.tile_id - tile_offset] = true;
+                }
+            }
+        }
+        for (slot, referenced) in referenced.into_iter().enumerate() {
+            if !referenced {
+                self.tiles[slot] = Tile::blank();
+            }
+        }
+        TileStorage::trim_blank_tiles(&mut self.tiles);
+
+        for &palette_id in self.unclaimed_palettes.iter() {
+            self.palettes[palette_id] = Default::default();
+        }
+        while self.palettes.len() > 0 && self.unclaimed_palettes.contains(&(self.palettes.len() - 1)) {
+            let palette_id = self.palettes.len() - 1;
+            self.unclaimed_palettes.retain(|&i| i != palette_id);
+            self.palettes.pop();
+        }
+    }
+
+    /// returns an error if the tiles or palettes don't fit in the tileset
+    pub fn check_limits(&self) -> Result<(), String> {
+        let kind = if self.primary { "primary" } else { "secondary" };
+        let (max_tiles, max_palettes) = (self.max_tile_count(), self.max_palette_count());
+        if self.tiles.len() > max_tiles {
+            return Err(format!("the tileset has {} tiles, but a {} tileset only has room for {}", self.tiles.len(), kind, max_tiles));
+        }
+        if self.palettes.len() > max_palettes {
+            return Err(format!("the tileset has {} palettes, but a {} tileset only has room for {}", self.palettes.len(), kind, max_palettes));
+        }
+        Ok(())
+    }
+
+    /// Load the tiles and palettes of a built primary tileset so a secondary tileset can reuse them
+    pub fn load_primary(&mut self, folder: &str) -> io::Result<()> {
+        self.primary_tiles = TileStorage::read_tiles_image(format!("{}/tiles.png", folder))?;
+        self.primary_palettes.clear();
+        for i in 0..self.game.primary_palette_count() {
+            let path = format!("{}/palettes/{:0>2}.pal", folder, i);
+            if !Path::new(&path).exists() {
+                break;
+            }
+            self.primary_palettes.push(TileStorage::read_pal_file(path)?);
+        }
+        Ok(())
+    }
+
+    /// returns the id/index of an equivalent tile in the loaded primary tileset with flip_x and flip_y
+    pub fn find_primary_tile(&self, tile: &Tile) -> Option<(usize, bool, bool)> {
+        for (i, other) in self.primary_tiles.iter().enumerate() {
+            let (equivalent, flip_x, flip_y) = other.is_equivalent(tile);
+            if equivalent {
+                return Some((i, flip_x, flip_y));
+            }
+        }
+        None
+    }
+
+    /// Put tiles at fixed slots, like the frames of animated tiles. Any gap between the
+    /// existing tiles and the slots is padded with blank tiles that `push` fills later.
+    pub fn reserve_tiles(&mut self, start: usize, tiles: Vec<Tile>) -> Result<(), String> {
+        while self.tiles.len() < start + tiles.len() {
+            self.free_slots.push(self.tiles.len());
+            self.tiles.push(Tile::blank());
+        }
+        for (i, tile) in tiles.into_iter().enumerate() {
+            let slot = start + i;
+            if let Some(position) = self.free_slots.iter().position(|&s| s == slot) {
+                self.free_slots.remove(position);
+            } else if self.tiles[slot] != tile {
+                return Err(format!("can't reserve tile 0x{:03X}, it is already in use", slot));
+            }
+            self.tiles[slot] = tile;
+            self.reserved_tiles.push(slot);
+        }
+        Ok(())
+    }
+
     /// returns the id/index of the tile with flip_x and flip_y
     pub fn push(&mut self, tile: Tile) -> (usize, bool, bool) {
         for (i, other) in self.tiles.iter().enumerate() {
@@ -231,6 +568,11 @@ impl TileStorage {
                 return (i, flip_x, flip_y);
             }
         }
+        if !self.free_slots.is_empty() {
+            let slot = self.free_slots.remove(0);
+            self.tiles[slot] = tile;
+            return (slot, false, false);
+        }
         self.tiles.push(tile);
         return (self.tiles.len() - 1, false, false);
     }
@@ -266,6 +608,57 @@ impl TileStorage {
         io::Result::Err(io::Error::new(ErrorKind::Other, "failed to extract palette"))
     }
 
+    /// Read a palette in .pal format
+    pub fn read_pal_file(path: String) -> io::Result<[[u8; 3]; 16]> {
+        let contents = fs::read_to_string(path)?;
+        let mut palette: [[u8; 3]; 16] = Default::default();
+        // skip the JASC-PAL header, version and color count
+        for (i, line) in contents.lines().skip(3).take(16).enumerate() {
+            let colors: Vec<u8> = line.split_whitespace()
+                .filter_map(|c| c.parse::<u8>().ok())
+                .collect();
+            if colors.len() != 3 {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("invalid color in palette: {}", line)));
+            }
+            palette[i] = [colors[0], colors[1], colors[2]];
+        }
+        Ok(palette)
+    }
+
+    /// Read the tiles of a 4bpp or 8bpp indexed image, like the tiles.png written by `output`
+    pub fn read_tiles_image(path: String) -> io::Result<Vec<Tile>> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        // we want the palette indices, not the colors
+        decoder.set(png::Transformations::IDENTITY);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buf = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buf)?;
+
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let indices: Vec<u8> = match info.bit_depth {
+            png::BitDepth::Four => buf.iter().flat_map(|b| vec![b >> 4, b & 0xf]).collect(),
+            png::BitDepth::Eight => buf,
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "tiles image must be 4bpp or 8bpp"))
+        };
+        // rows of 4bpp images are padded to a full byte
+        let row_length = if info.bit_depth == png::BitDepth::Four { (width + 1) / 2 * 2 } else { width };
+
+        let mut tiles = Vec::with_capacity((width / TILE_SIZE) * (height / TILE_SIZE));
+        for y in 0..height / TILE_SIZE {
+            for x in 0..width / TILE_SIZE {
+                let mut data: [[u8; TILE_SIZE]; TILE_SIZE] = Default::default();
+                for ty in 0..TILE_SIZE {
+                    for tx in 0..TILE_SIZE {
+                        data[ty][tx] = indices[(y * TILE_SIZE + ty) * row_length + x * TILE_SIZE + tx];
+                    }
+                }
+                tiles.push(Tile::new(data));
+            }
+        }
+        Ok(tiles)
+    }
+
     /// Output palette in .pal format
     pub fn output_palette(palette: &[[u8; 3]; 16], path: String) {
         fs::remove_file(&path); // ignore if fail
@@ -282,20 +675,26 @@ impl TileStorage {
 
     pub fn output(&self) {
         for (i, palette) in self.palettes.iter().enumerate() {
-            let palette_id = if self.primary { i } else { i + SECONDARY_PALETTE_OFFSET };
+            let palette_id = i + self.palette_offset();
             let pal_path = format!("{}/palettes/{}.pal", self.output_folder, format!("{:0>2}", palette_id));
             TileStorage::output_palette(palette, pal_path);
         }
-        let palette = self.palettes.get(0).expect("palette list is empty"); // will never fail
+        // secondary tilesets can use only primary palettes
+        let palette = self.palettes.get(0).or(self.primary_palettes.get(0)).expect("palette list is empty");
 
-        let width = 128u32;
-        let height = 256u32;
+        // 16 tiles per row, with enough rows for every tile the tileset can have
+        let height = (self.max_tile_count() + 15) / 16 * 8;
+        let tileset_path = format!("{}/tiles.png", self.output_folder);
+        TileStorage::output_tiles_image(&self.tiles, palette, 128, height as u32, tileset_path);
+    }
+
+    /// Output tiles in row-major order to a 4bpp indexed image, padding it with blank tiles
+    pub fn output_tiles_image(tiles: &[Tile], palette: &[[u8; 3]; 16], width: u32, height: u32, path: String) {
         let max_x = width / 8;
         let max_y = height / 8;
 
-        let tileset_path = format!("{}/tiles.png", self.output_folder);
-        fs::remove_file(&tileset_path);
-        let tileset_file = File::create(tileset_path).expect("can't create tiles.png");
+        fs::remove_file(&path);
+        let tileset_file = File::create(&path).expect(&format!("can't create {}", path));
         let ref mut w = BufWriter::new(tileset_file);
 
         let mut buffer = Vec::with_capacity((width * height) as usize);
@@ -304,7 +703,7 @@ impl TileStorage {
             for row_index in 0..8 {
                 for x in 0..max_x {
                     let tile_index = (y * max_x + x) as usize;
-                    if let Some(tile) = self.tiles.get(tile_index) {
+                    if let Some(tile) = tiles.get(tile_index) {
                         let row = tile.data[row_index];
                         for palette_index in row.iter() {
                             bit_writer.write_bits(*palette_index as u32, 4usize).expect("failed to write tile png bytes");
@@ -335,6 +734,98 @@ impl TileStorage {
         writer.write_image_data(buffer.as_slice());
     }
 
+    /// the tile id of the first tile of this tileset, secondary tilesets start after the primary tiles of the game
+    pub fn tile_offset(&self) -> usize {
+        if self.primary { 0 } else { self.game.primary_tile_count() }
+    }
+
+    /// the palette id of the first palette of this tileset
+    pub fn palette_offset(&self) -> usize {
+        if self.primary { 0 } else { self.game.primary_palette_count() }
+    }
+
+    /// the number of tiles that fit in this tileset
+    pub fn max_tile_count(&self) -> usize {
+        if self.primary { self.game.primary_tile_count() } else { 0x400 - self.game.primary_tile_count() }
+    }
+
+    /// the number of palettes that fit in this tileset
+    pub fn max_palette_count(&self) -> usize {
+        if self.primary { self.game.primary_palette_count() } else { self.game.secondary_palette_count() }
+    }
+
+    /// returns the tile referenced by a tile id in metatiles.bin
+    pub fn get_tile(&self, tile_id: usize) -> Option<Tile> {
+        if self.primary {
+            self.tiles.get(tile_id).map(|tile| Tile::new(tile.data))
+        } else if tile_id >= self.tile_offset() {
+            self.tiles.get(tile_id - self.tile_offset()).map(|tile| Tile::new(tile.data))
+        } else if tile_id == 0 && self.primary_tiles.is_empty() {
+            Some(Tile::blank())
+        } else {
+            self.primary_tiles.get(tile_id).map(|tile| Tile::new(tile.data))
+        }
+    }
+
+    /// returns the palette referenced by a palette id in metatiles.bin
+    pub fn get_palette(&self, palette_id: usize) -> Option<&[[u8; 3]; 16]> {
+        if self.primary {
+            self.palettes.get(palette_id)
+        } else if palette_id >= self.palette_offset() {
+            self.palettes.get(palette_id - self.palette_offset())
+        } else {
+            self.primary_palettes.get(palette_id)
+        }
+    }
+
+    /// returns the palette index and color of every pixel in an encoded metatile layer
+    pub fn render_metatile_layer(&self, encoded: &[u8]) -> [[(u8, [u8; 3]); METATILE_SIZE]; METATILE_SIZE] {
+        let mut pixels = [[(0u8, [0u8; 3]); METATILE_SIZE]; METATILE_SIZE];
+        for (i, tile_ref) in TileRef::decode_all(encoded).iter().enumerate().take(4) {
+            let tile = self.get_tile(tile_ref.tile_id).expect("encoded metatile references a missing tile")
+                .flip(tile_ref.flip_x, tile_ref.flip_y);
+            let palette = self.get_palette(tile_ref.palette).expect("encoded metatile references a missing palette");
+            for y in 0..TILE_SIZE {
+                for x in 0..TILE_SIZE {
+                    let index = tile.data[y][x];
+                    pixels[(i / 2) * TILE_SIZE + y][(i % 2) * TILE_SIZE + x] = (index, palette[index as usize]);
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Output a full color image of the metatiles with their layers composited,
+    /// like the metatile selector in Porymap
+    pub fn output_metatile_preview(&self, metatile_definitions: &[(String, usize)]) {
+        let metatile_count = metatile_definitions.len() / 2;
+        let width = PREVIEW_METATILES_PER_ROW * METATILE_SIZE;
+        let rows = ((metatile_count + PREVIEW_METATILES_PER_ROW - 1) / PREVIEW_METATILES_PER_ROW).max(1);
+        let height = rows * METATILE_SIZE;
+
+        let mut buffer = vec![0u8; width * height * 3];
+        for (layer_index, (file_name, metatile_id)) in metatile_definitions.iter().enumerate() {
+            let encoded = self.encoded_metatiles.get(&(file_name.clone(), *metatile_id))
+                .expect(&format!("failed to get encoded metatile: {} {}", file_name, metatile_id));
+            let top_layer = layer_index % 2 == 1;
+            let x_start = (layer_index / 2 % PREVIEW_METATILES_PER_ROW) * METATILE_SIZE;
+            let y_start = (layer_index / 2 / PREVIEW_METATILES_PER_ROW) * METATILE_SIZE;
+            for (y, row) in self.render_metatile_layer(encoded).iter().enumerate() {
+                for (x, (index, color)) in row.iter().enumerate() {
+                    // the transparent color is always moved to index 0 by `add_image`
+                    if top_layer && *index == 0 {
+                        continue;
+                    }
+                    let offset = ((y_start + y) * width + x_start + x) * 3;
+                    buffer[offset..offset + 3].copy_from_slice(color);
+                }
+            }
+        }
+
+        let preview_path = format!("{}/metatiles_preview.png", self.output_folder);
+        write_rgb_image(&preview_path, width, height, &buffer).expect("failed to write metatile preview");
+    }
+
     pub fn dump_tiles(&self) {
         let mut i = 0usize;
         for tile in self.tiles.iter() {
@@ -347,6 +838,46 @@ impl TileStorage {
     }
 }
 
+/// Read an image as (width, height, rgb pixels)
+pub fn read_rgb_image(path: &str) -> io::Result<(usize, usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+    let pixels = match info.color_type {
+        png::ColorType::RGB => buf,
+        png::ColorType::RGBA => buf.chunks(4).flat_map(|c| vec![c[0], c[1], c[2]]).collect(),
+        _ => return Err(io::Error::new(ErrorKind::InvalidData, format!("unsupported color type in {}", path)))
+    };
+    Ok((info.width as usize, info.height as usize, pixels))
+}
+
+/// Read an image as (width, height, rgba pixels), images without transparency are opaque
+pub fn read_rgba_image(path: &str) -> io::Result<(usize, usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+    let pixels = match info.color_type {
+        png::ColorType::RGB => buf.chunks(3).flat_map(|c| vec![c[0], c[1], c[2], 0xff]).collect(),
+        png::ColorType::RGBA => buf,
+        _ => return Err(io::Error::new(ErrorKind::InvalidData, format!("unsupported color type in {}", path)))
+    };
+    Ok((info.width as usize, info.height as usize, pixels))
+}
+
+/// Write rgb pixels to a full color image
+pub fn write_rgb_image(path: &str, width: usize, height: usize, pixels: &[u8]) -> io::Result<()> {
+    fs::remove_file(path); // ignore
+    let file = File::create(path)?;
+    let ref mut w = BufWriter::new(file);
+    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
+    encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
 pub fn parse_metatile_config(lines: Vec<String>) -> Vec<(String, usize)> {
     let mut file_map: HashMap<String, String> = HashMap::new();
     let mut metatile_refs: Vec<(String, usize)> = Vec::new();
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::decomp::{normalize_name, Project};
+use crate::layout::MapLayout;
+use crate::porymap::Layouts;
+use crate::tileset::TileRef;
+
+/// Which metatiles of a tileset are used by the layouts of a project,
+/// and which tiles can be reclaimed by removing the unused metatiles
+pub struct TilesetUsage {
+    pub primary: bool,
+    /// the id of the first metatile and tile of the tileset
+    pub offset: usize,
+    /// how many blocks of the layouts use each metatile
+    pub metatile_uses: Vec<usize>,
+    /// metatiles that are referenced by a METATILE_ label in the code
+    pub labeled_metatiles: HashSet<usize>,
+    pub layouts: Vec<String>,
+    /// tiles that are only referenced by unused metatiles
+    pub reclaimable_tiles: Vec<usize>,
+}
+
+impl TilesetUsage {
+    pub fn unused_metatiles(&self) -> Vec<usize> {
+        self.metatile_uses.iter().enumerate()
+            .filter(|&(i, uses)| *uses == 0 && !self.labeled_metatiles.contains(&(i + self.offset)))
+            .map(|(i, _)| i + self.offset)
+            .collect()
+    }
+}
+
+fn read_metatiles(folder: &PathBuf) -> Result<Vec<Vec<TileRef>>, String> {
+    let path = folder.join("metatiles.bin");
+    let bytes = fs::read(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok(bytes.chunks(16).map(|metatile| TileRef::decode_all(metatile)).collect())
+}
+
+pub fn analyze_tileset(project: &Project, tileset: &str) -> Result<TilesetUsage, String> {
+    let (folder, primary) = project.find_tileset(tileset)
+        .ok_or(format!("couldn't find tileset {} in {}", tileset, project.root.display()))?;
+    let metatiles = read_metatiles(&folder)?;
+    let offset = if primary { 0 } else { project.game.primary_tile_count() };
+    let wanted = normalize_name(&folder.file_name().unwrap_or_default().to_string_lossy());
+
+    let mut metatile_uses = vec![0usize; metatiles.len()];
+    let mut layouts = Vec::new();
+    // secondary tilesets can use the tiles of the primary tilesets they are paired with
+    let mut paired_tilesets: Vec<String> = Vec::new();
+    let all_layouts = Layouts::load(&project.layouts_path())?;
+    for layout in all_layouts.layouts.iter().filter_map(|l| l.as_ref()) {
+        let (own, other) = if primary {
+            (&layout.primary_tileset, &layout.secondary_tileset)
+        } else {
+            (&layout.secondary_tileset, &layout.primary_tileset)
+        };
+        if normalize_name(own) != wanted {
+            continue;
+        }
+        if !paired_tilesets.contains(other) {
+            paired_tilesets.push(other.clone());
+        }
+        layouts.push(layout.name.clone());
+
+        let map = MapLayout::load(&project.root.join(&layout.blockdata_filepath), layout.width as usize)?;
+        let border = MapLayout::load(&project.root.join(&layout.border_filepath), layout.border_width.unwrap_or(2) as usize)?;
+        for block in map.blocks.iter().chain(border.blocks.iter()) {
+            if block.metatile >= offset && block.metatile - offset < metatile_uses.len() {
+                metatile_uses[block.metatile - offset] += 1;
+            }
+        }
+    }
+
+    // metatiles used by the code are labeled in include/constants/metatile_labels.h
+    let mut labeled_metatiles = HashSet::new();
+    if let Ok(labels) = fs::read_to_string(project.root.join("include/constants/metatile_labels.h")) {
+        for line in labels.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts[0] != "#define" || !parts[1].starts_with("METATILE_") {
+                continue;
+            }
+            let label_tileset = parts[1]["METATILE_".len()..].split('_').next().unwrap_or("");
+            if normalize_name(label_tileset) != wanted {
+                continue;
+            }
+            if let Ok(id) = usize::from_str_radix(parts[2].trim_start_matches("0x"), 16) {
+                labeled_metatiles.insert(id);
+            }
+        }
+    }
+
+    // trace the metatiles down to the tiles of this tileset
+    let tile_count = project.game.primary_tile_count();
+    let owns_tile = |tile_id: usize| if primary { tile_id < tile_count } else { tile_id >= tile_count };
+    let mut used_tiles = HashSet::new();
+    let mut unused_tiles = HashSet::new();
+    for (i, tile_refs) in metatiles.iter().enumerate() {
+        let used = metatile_uses[i] > 0 || labeled_metatiles.contains(&(i + offset));
+        for tile_ref in tile_refs.iter().filter(|t| owns_tile(t.tile_id)) {
+            if used { used_tiles.insert(tile_ref.tile_id); } else { unused_tiles.insert(tile_ref.tile_id); }
+        }
+    }
+    if primary {
+        for paired in paired_tilesets.iter() {
+            if let Some((paired_folder, false)) = project.find_tileset(paired) {
+                for tile_refs in read_metatiles(&paired_folder)? {
+                    used_tiles.extend(tile_refs.iter().map(|t| t.tile_id).filter(|&id| owns_tile(id)));
+                }
+            }
+        }
+    }
+    let mut reclaimable_tiles: Vec<usize> = unused_tiles.difference(&used_tiles)
+        .cloned()
+        // the first tile is the blank tile that transparent tiles use
+        .filter(|&id| id != 0)
+        .collect();
+    reclaimable_tiles.sort();
+
+    Ok(TilesetUsage {
+        primary,
+        offset,
+        metatile_uses,
+        labeled_metatiles,
+        layouts,
+        reclaimable_tiles,
+    })
+}
+
+/// formats ids as hex ranges, like 0x010-0x01F, 0x025
+pub fn format_ranges(ids: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < ids.len() {
+        let start = ids[i];
+        while i + 1 < ids.len() && ids[i + 1] == ids[i] + 1 {
+            i += 1;
+        }
+        if ids[i] == start {
+            ranges.push(format!("0x{:03X}", start));
+        } else {
+            ranges.push(format!("0x{:03X}-0x{:03X}", start, ids[i]));
+        }
+        i += 1;
+    }
+    ranges.join(", ")
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::decomp::Game;
+use crate::tileset::{METATILE_SIZE, read_rgb_image, Tile, TILE_SIZE, TileRef, TileStorage};
+
+/// Re-renders every metatile from the tiles.png, palettes and metatiles.bin in the output folder
+/// and compares them against the source sheets. Returns a description of every mismatch.
+/// Secondary tilesets that reference a primary tileset need its folder to be rendered.
+pub fn verify_tileset(output_folder: &str, primary: bool, game: Game, primary_folder: Option<&str>, metatile_definitions: &[(String, usize)], inputs: &[String]) -> Result<Vec<String>, String> {
+    let tiles = TileStorage::read_tiles_image(format!("{}/tiles.png", output_folder))
+        .map_err(|e| format!("failed to read tiles.png: {}", e))?;
+    let primary_tiles = match primary_folder {
+        Some(folder) => TileStorage::read_tiles_image(format!("{}/tiles.png", folder))
+            .map_err(|e| format!("failed to read primary tiles.png: {}", e))?,
+        None => Vec::new()
+    };
+    let metatiles = fs::read(format!("{}/metatiles.bin", output_folder))
+        .map_err(|e| format!("failed to read metatiles.bin: {}", e))?;
+    let tile_refs = TileRef::decode_all(&metatiles);
+
+    // source sheets are referenced by file name in the metatile definitions
+    let mut sheets: HashMap<String, (usize, usize, Vec<u8>)> = HashMap::new();
+    for input in inputs {
+        let file_name = Path::new(input).file_name()
+            .expect(&format!("couldn't find file {}", input)).to_string_lossy().to_string();
+        let image = read_rgb_image(input).map_err(|e| format!("failed to read {}: {}", input, e))?;
+        sheets.insert(file_name, image);
+    }
+
+    let tile_offset = if primary { 0 } else { game.primary_tile_count() };
+    let mut palettes: HashMap<usize, [[u8; 3]; 16]> = HashMap::new();
+    let mut mismatches = Vec::new();
+    for (layer_index, (sheet_name, sheet_metatile)) in metatile_definitions.iter().enumerate() {
+        let metatile_id = layer_index / 2 + tile_offset;
+        let layer = if layer_index % 2 == 0 { "bottom" } else { "top" };
+        let (width, height, pixels) = match sheets.get(sheet_name) {
+            Some(sheet) => sheet,
+            None => return Err(format!("metatile definitions reference unknown sheet {}", sheet_name))
+        };
+        let max_x = width / METATILE_SIZE;
+        let sheet_x = sheet_metatile % max_x;
+        let sheet_y = sheet_metatile / max_x;
+        if (sheet_y + 1) * METATILE_SIZE > *height {
+            return Err(format!("metatile {} is outside of sheet {}", sheet_metatile, sheet_name));
+        }
+
+        let location = format!("metatile 0x{:03X} {} layer ({} at {}, {})",
+                               metatile_id, layer, sheet_name, sheet_x, sheet_y);
+        if tile_refs.len() < (layer_index + 1) * 4 {
+            mismatches.push(format!("{}: missing from metatiles.bin", location));
+            continue;
+        }
+
+        'tiles: for (i, tile_ref) in tile_refs[layer_index * 4..(layer_index + 1) * 4].iter().enumerate() {
+            let tile = if primary {
+                tiles.get(tile_ref.tile_id)
+            } else if tile_ref.tile_id >= tile_offset {
+                tiles.get(tile_ref.tile_id - tile_offset)
+            } else {
+                primary_tiles.get(tile_ref.tile_id)
+            }.map(|tile| Tile::new(tile.data));
+            // transparent tiles in secondary tilesets use the blank tile of the primary tileset
+            let tile = if !primary && tile_ref.tile_id == 0 && primary_tiles.is_empty() { Some(Tile::blank()) } else { tile };
+            let tile = match tile {
+                Some(tile) => tile.flip(tile_ref.flip_x, tile_ref.flip_y),
+                None => {
+                    mismatches.push(format!("{}: tile 0x{:03X} is not in tiles.png", location, tile_ref.tile_id));
+                    break;
+                }
+            };
+            if !palettes.contains_key(&tile_ref.palette) {
+                let folder = match primary_folder {
+                    Some(folder) if !primary && tile_ref.palette < game.primary_palette_count() => folder,
+                    _ => output_folder
+                };
+                let path = format!("{}/palettes/{:0>2}.pal", folder, tile_ref.palette);
+                match TileStorage::read_pal_file(path.clone()) {
+                    Ok(palette) => { palettes.insert(tile_ref.palette, palette); }
+                    Err(e) => {
+                        mismatches.push(format!("{}: failed to read palette {}: {}", location, path, e));
+                        break;
+                    }
+                }
+            }
+            let palette = &palettes[&tile_ref.palette];
+
+            for y in 0..TILE_SIZE {
+                for x in 0..TILE_SIZE {
+                    let pixel_x = sheet_x * METATILE_SIZE + (i % 2) * TILE_SIZE + x;
+                    let pixel_y = sheet_y * METATILE_SIZE + (i / 2) * TILE_SIZE + y;
+                    let offset = (pixel_y * width + pixel_x) * 3;
+                    let expected = &pixels[offset..offset + 3];
+                    let actual = palette[tile.data[y][x] as usize];
+                    if expected != actual {
+                        mismatches.push(format!("{}: pixel ({}, {}) is #{:02X}{:02X}{:02X}, expected #{:02X}{:02X}{:02X}",
+                                                location, pixel_x, pixel_y,
+                                                actual[0], actual[1], actual[2],
+                                                expected[0], expected[1], expected[2]));
+                        break 'tiles;
+                    }
+                }
+            }
+        }
+    }
+    Ok(mismatches)
+}